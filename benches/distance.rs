@@ -0,0 +1,88 @@
+use clap::Parser;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use psdm::{hamming_distance, hamming_distance_no_ignore, pairwise_matrix, Transformer};
+
+const BASES: &[u8] = b"ACGT";
+
+/// Minimal xorshift generator so the synthetic data is reproducible without extra dependencies
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// A synthetic alignment of `n` sequences of length `len`. Each sequence differs from a random
+/// reference at ~1% of sites, and a `missing` proportion of sites are N. The sequences are loaded
+/// with the default `Transformer` so missing data is handled exactly as in psdm.
+fn synthetic_alignment(n: usize, len: usize, missing: f64) -> Vec<Vec<u8>> {
+    let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+    let reference: Vec<u8> = (0..len).map(|_| BASES[(rng.next() % 4) as usize]).collect();
+
+    let mut phylip = format!("{} {}\n", n, len).into_bytes();
+    for i in 0..n {
+        phylip.extend(format!("s{} ", i).bytes());
+        for &base in &reference {
+            let x = rng.next_f64();
+            if x < missing {
+                phylip.push(b'N');
+            } else if x < missing + 0.01 {
+                phylip.push(BASES[(rng.next() % 4) as usize]);
+            } else {
+                phylip.push(base);
+            }
+        }
+        phylip.push(b'\n');
+    }
+
+    let transformer = Transformer::parse_from(["psdm"]);
+    let (_, seqs) = transformer.load_phylip(&phylip[..], 0).unwrap();
+    seqs
+}
+
+fn bench_hamming_distance(c: &mut Criterion) {
+    let mut group = c.benchmark_group("hamming_distance");
+    for len in [1_000, 100_000, 1_000_000] {
+        for missing in [0.0, 0.1] {
+            let seqs = synthetic_alignment(2, len, missing);
+            group.throughput(Throughput::Bytes(len as u64));
+            group.bench_with_input(
+                BenchmarkId::new(format!("missing={}", missing), len),
+                &seqs,
+                |b, seqs| b.iter(|| hamming_distance(&seqs[0], &seqs[1])),
+            );
+        }
+        let seqs = synthetic_alignment(2, len, 0.0);
+        group.bench_with_input(BenchmarkId::new("no_ignore", len), &seqs, |b, seqs| {
+            b.iter(|| hamming_distance_no_ignore(&seqs[0], &seqs[1]))
+        });
+    }
+    group.finish();
+}
+
+fn bench_pairwise_matrix(c: &mut Criterion) {
+    let mut group = c.benchmark_group("pairwise_matrix");
+    group.sample_size(10);
+    for n in [10, 50, 100] {
+        for missing in [0.0, 0.1] {
+            let seqs = synthetic_alignment(n, 10_000, missing);
+            group.bench_with_input(
+                BenchmarkId::new(format!("missing={}", missing), n),
+                &seqs,
+                |b, seqs| b.iter(|| pairwise_matrix(seqs)),
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_hamming_distance, bench_pairwise_matrix);
+criterion_main!(benches);
@@ -0,0 +1,30 @@
+use std::env;
+use std::process::Command;
+
+/// Run a command and return its trimmed stdout, if it succeeded
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if output.status.success() {
+        Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        None
+    }
+}
+
+fn main() {
+    let commit = command_output("git", &["rev-parse", "--short", "HEAD"]);
+    println!(
+        "cargo:rustc-env=PSDM_GIT_COMMIT={}",
+        commit.unwrap_or_else(|| "unknown".to_string())
+    );
+
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version = command_output(&rustc, &["--version"]);
+    println!(
+        "cargo:rustc-env=PSDM_RUSTC_VERSION={}",
+        rustc_version.unwrap_or_else(|| "unknown".to_string())
+    );
+
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,163 @@
+use anyhow::{bail, Context, Result};
+use ndarray::Array2;
+use std::io::{Read, Write};
+
+/// Magic bytes at the start of a psdm binary matrix file
+pub const BINARY_MAGIC: &[u8; 4] = b"PSDM";
+/// Version of the binary layout. Bump this whenever the layout changes
+pub const BINARY_VERSION: u8 = 1;
+
+/// A distance matrix along with its column and row names, as stored in the binary format
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BinaryMatrix {
+    pub matrix: Array2<u64>,
+    pub column_names: Vec<Vec<u8>>,
+    pub row_names: Vec<Vec<u8>>,
+}
+
+/// Write a distance matrix in psdm's binary format. All integers are little-endian and the layout
+/// is:
+///
+/// 1. magic bytes `PSDM` and a version byte
+/// 2. number of columns and rows (`u64`)
+/// 3. the column names, then the row names - each as its length (`u32`) followed by its bytes
+/// 4. the distances (`u64`) in row-major order
+pub fn write_binary(
+    ostream: &mut dyn Write,
+    matrix: &Array2<u64>,
+    column_names: &[Vec<u8>],
+    row_names: &[Vec<u8>],
+) -> std::io::Result<()> {
+    ostream.write_all(BINARY_MAGIC)?;
+    ostream.write_all(&[BINARY_VERSION])?;
+    ostream.write_all(&(column_names.len() as u64).to_le_bytes())?;
+    ostream.write_all(&(row_names.len() as u64).to_le_bytes())?;
+    for name in column_names.iter().chain(row_names) {
+        ostream.write_all(&(name.len() as u32).to_le_bytes())?;
+        ostream.write_all(name)?;
+    }
+    for d in matrix.iter() {
+        ostream.write_all(&d.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> std::io::Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> std::io::Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_names<R: Read>(reader: &mut R, n: usize) -> std::io::Result<Vec<Vec<u8>>> {
+    let mut names = Vec::with_capacity(n);
+    for _ in 0..n {
+        let mut name = vec![0; read_u32(reader)? as usize];
+        reader.read_exact(&mut name)?;
+        names.push(name);
+    }
+    Ok(names)
+}
+
+/// Read a distance matrix written by [`write_binary`]
+pub fn read_binary<R: Read>(mut reader: R) -> Result<BinaryMatrix> {
+    let mut magic = [0; 4];
+    reader
+        .read_exact(&mut magic)
+        .context("Failed to read binary matrix header")?;
+    if &magic != BINARY_MAGIC {
+        bail!("Not a psdm binary matrix file");
+    }
+    let mut version = [0; 1];
+    reader.read_exact(&mut version)?;
+    if version[0] != BINARY_VERSION {
+        bail!(
+            "Unsupported binary matrix version {} (expected {})",
+            version[0],
+            BINARY_VERSION
+        );
+    }
+
+    let n_cols = read_u64(&mut reader)? as usize;
+    let n_rows = read_u64(&mut reader)? as usize;
+    let column_names = read_names(&mut reader, n_cols).context("Failed to read column names")?;
+    let row_names = read_names(&mut reader, n_rows).context("Failed to read row names")?;
+
+    let mut values = Vec::with_capacity(n_rows * n_cols);
+    for _ in 0..n_rows * n_cols {
+        values.push(read_u64(&mut reader).context("Binary matrix is truncated")?);
+    }
+    let matrix = Array2::from_shape_vec((n_rows, n_cols), values)?;
+
+    Ok(BinaryMatrix {
+        matrix,
+        column_names,
+        row_names,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binary_round_trip() {
+        let expected = BinaryMatrix {
+            matrix: ndarray::arr2(&[[0, 1, 2], [3, 4, u64::MAX]]),
+            column_names: vec![b"s0".to_vec(), b"s1".to_vec(), b"sample 2".to_vec()],
+            row_names: vec![b"a".to_vec(), b"".to_vec()],
+        };
+        let mut buf = vec![];
+        write_binary(
+            &mut buf,
+            &expected.matrix,
+            &expected.column_names,
+            &expected.row_names,
+        )
+        .unwrap();
+
+        let actual = read_binary(&buf[..]).unwrap();
+
+        assert_eq!(actual, expected)
+    }
+
+    #[test]
+    fn read_binary_bad_magic() {
+        let actual = read_binary(&b"s0,s1\n"[..]).unwrap_err();
+
+        assert!(actual.to_string().contains("Not a psdm binary"))
+    }
+
+    #[test]
+    fn read_binary_unsupported_version() {
+        let data = b"PSDM\x02";
+
+        let actual = read_binary(&data[..]).unwrap_err();
+
+        assert!(actual
+            .to_string()
+            .contains("Unsupported binary matrix version 2"))
+    }
+
+    #[test]
+    fn read_binary_truncated() {
+        let mut buf = vec![];
+        write_binary(
+            &mut buf,
+            &ndarray::arr2(&[[0, 1]]),
+            &[vec![], vec![]],
+            &[vec![]],
+        )
+        .unwrap();
+        buf.truncate(buf.len() - 1);
+
+        let actual = read_binary(&buf[..]).unwrap_err();
+
+        assert!(actual.to_string().contains("truncated"))
+    }
+}
@@ -0,0 +1,201 @@
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::hash::Hasher;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+const HEADER_PREFIX: &str = "#psdm-checkpoint v1";
+
+/// An FNV-1a hash of everything the distances of a run depend on - e.g., the names, sequences,
+/// and options - so a checkpoint is only resumed by the same run. Unlike `DefaultHasher`, the
+/// algorithm is fixed, so the hash is stable across Rust versions.
+pub struct Fingerprint(u64);
+
+impl Default for Fingerprint {
+    fn default() -> Self {
+        Fingerprint(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fingerprint {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 = (self.0 ^ u64::from(*b)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+/// Records the distances of completed chunks of pairs so an interrupted run can be resumed.
+///
+/// The file starts with a header describing the run (number of pairs, chunk size, and the
+/// [`Fingerprint`] of its inputs), followed by
+/// one line per completed chunk with the chunk index and its comma-separated distances. A partially
+/// written (truncated) line is ignored when resuming.
+pub struct Checkpoint {
+    file: File,
+    chunks: HashMap<usize, Vec<u64>>,
+}
+
+/// Parse a line of the checkpoint. Returns `None` if the line is incomplete
+fn parse_chunk(line: &str, num_pairs: usize, chunk_size: usize) -> Option<(usize, Vec<u64>)> {
+    let (idx, values) = line.split_once('\t')?;
+    let idx: usize = idx.parse().ok()?;
+    let values: Vec<u64> = values
+        .split(',')
+        .map(|v| v.parse())
+        .collect::<Result<_, _>>()
+        .ok()?;
+    // the number of pairs in this chunk - the last chunk may be smaller
+    let start = idx.saturating_mul(chunk_size).min(num_pairs);
+    let expected_len = (num_pairs - start).min(chunk_size);
+    if values.is_empty() || values.len() != expected_len {
+        return None;
+    }
+    Some((idx, values))
+}
+
+impl Checkpoint {
+    /// Open a checkpoint for a run with `num_pairs` pairs processed in chunks of `chunk_size`, whose
+    /// inputs hash to `fingerprint`. If `resume` is true and the file exists, the completed chunks
+    /// are loaded from it, otherwise a new checkpoint file is created.
+    pub fn open(
+        path: &Path,
+        num_pairs: usize,
+        chunk_size: usize,
+        fingerprint: u64,
+        resume: bool,
+    ) -> Result<Self> {
+        let header = format!(
+            "{} pairs={} chunk_size={} fingerprint={:016x}",
+            HEADER_PREFIX, num_pairs, chunk_size, fingerprint
+        );
+        let mut chunks = HashMap::new();
+
+        let file = if resume && path.exists() {
+            let reader = BufReader::new(File::open(path).context("Failed to open checkpoint")?);
+            let mut lines = reader.lines();
+            match lines.next().transpose()? {
+                Some(line) if line == header => (),
+                _ => bail!(
+                    "Checkpoint {} does not match this run - was it created with different inputs?",
+                    path.display()
+                ),
+            }
+            for line in lines {
+                if let Some((idx, values)) = parse_chunk(&line?, num_pairs, chunk_size) {
+                    chunks.insert(idx, values);
+                }
+            }
+            OpenOptions::new()
+                .append(true)
+                .open(path)
+                .context("Failed to open checkpoint")?
+        } else {
+            let mut file = File::create(path).context("Failed to create checkpoint")?;
+            writeln!(file, "{}", header)?;
+            file
+        };
+
+        Ok(Checkpoint { file, chunks })
+    }
+
+    /// The distances of chunk `idx`, if it has already been completed
+    pub fn get(&self, idx: usize) -> Option<&Vec<u64>> {
+        self.chunks.get(&idx)
+    }
+
+    /// The number of completed chunks
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Record the distances for chunk `idx` and flush them to disk
+    pub fn record(&mut self, idx: usize, values: &[u64]) -> Result<()> {
+        let values = values
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        writeln!(self.file, "{}\t{}", idx, values)?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resume_from_checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("checkpoint");
+
+        let mut checkpoint = Checkpoint::open(&path, 5, 2, 0, false).unwrap();
+        checkpoint.record(0, &[1, 2]).unwrap();
+        checkpoint.record(2, &[5]).unwrap();
+        drop(checkpoint);
+        // simulate an interrupted write
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        write!(file, "1\t3").unwrap();
+        drop(file);
+
+        let checkpoint = Checkpoint::open(&path, 5, 2, 0, true).unwrap();
+
+        assert_eq!(checkpoint.len(), 2);
+        assert_eq!(checkpoint.get(0), Some(&vec![1, 2]));
+        assert_eq!(checkpoint.get(1), None);
+        assert_eq!(checkpoint.get(2), Some(&vec![5]));
+    }
+
+    #[test]
+    fn resume_from_mismatched_checkpoint_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("checkpoint");
+        Checkpoint::open(&path, 5, 2, 0, false).unwrap();
+
+        let result = Checkpoint::open(&path, 6, 2, 0, true);
+
+        assert!(result.is_err())
+    }
+
+    #[test]
+    fn resume_with_different_fingerprint_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("checkpoint");
+        Checkpoint::open(&path, 5, 2, 1, false).unwrap();
+
+        let result = Checkpoint::open(&path, 5, 2, 2, true);
+
+        assert!(result.is_err())
+    }
+
+    #[test]
+    fn fingerprint_is_fnv1a() {
+        let mut fingerprint = Fingerprint::default();
+        fingerprint.write(b"a");
+
+        assert_eq!(fingerprint.finish(), 0xaf63_dc4c_8601_ec8c)
+    }
+
+    #[test]
+    fn no_resume_overwrites_checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("checkpoint");
+        let mut checkpoint = Checkpoint::open(&path, 5, 2, 0, false).unwrap();
+        checkpoint.record(0, &[1, 2]).unwrap();
+
+        let checkpoint = Checkpoint::open(&path, 5, 2, 0, false).unwrap();
+
+        assert!(checkpoint.is_empty())
+    }
+}
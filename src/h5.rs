@@ -0,0 +1,75 @@
+use anyhow::{Context, Result};
+use hdf5::types::VarLenUnicode;
+use ndarray::Array2;
+use std::path::Path;
+
+fn write_names(file: &hdf5::File, dataset: &str, names: &[Vec<u8>]) -> Result<()> {
+    let names = names
+        .iter()
+        .map(|n| String::from_utf8_lossy(n).parse::<VarLenUnicode>())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Invalid name for the {} dataset", dataset))?;
+    file.new_dataset_builder()
+        .with_data(&names)
+        .create(dataset)?;
+    Ok(())
+}
+
+/// Write a distance matrix and its names to an HDF5 file. The matrix is the `matrix` dataset,
+/// compressed with deflate at `level` (0-9), and the names are the `names` dataset for a single
+/// alignment, or the `column_names` and `row_names` datasets for two alignments.
+pub fn write_hdf5(
+    path: &Path,
+    matrix: &Array2<u64>,
+    column_names: &[Vec<u8>],
+    row_names: &[Vec<u8>],
+    intra: bool,
+    level: u8,
+) -> Result<()> {
+    let file = hdf5::File::create(path)
+        .with_context(|| format!("Failed to create HDF5 file {}", path.display()))?;
+    let builder = file.new_dataset_builder();
+    // filters need a chunked dataset, which cannot have a zero-length dimension
+    let builder = if matrix.is_empty() {
+        builder
+    } else {
+        builder.deflate(level)
+    };
+    builder.with_data(matrix).create("matrix")?;
+    if intra {
+        write_names(&file, "names", column_names)?;
+    } else {
+        write_names(&file, "column_names", column_names)?;
+        write_names(&file, "row_names", row_names)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_hdf5_matrix_and_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dists.h5");
+        let matrix = ndarray::arr2(&[[0, 3], [3, 0]]);
+        let names = vec![b"s1".to_vec(), b"s2".to_vec()];
+
+        write_hdf5(&path, &matrix, &names, &names, true, 6).unwrap();
+
+        let file = hdf5::File::open(&path).unwrap();
+        let actual = file.dataset("matrix").unwrap().read_2d::<u64>().unwrap();
+        assert_eq!(actual, matrix);
+        let actual: Vec<String> = file
+            .dataset("names")
+            .unwrap()
+            .read_raw::<VarLenUnicode>()
+            .unwrap()
+            .iter()
+            .map(|n| n.to_string())
+            .collect();
+        assert_eq!(actual, vec!["s1", "s2"]);
+        assert!(file.dataset("row_names").is_err())
+    }
+}
@@ -1,14 +1,41 @@
 use anyhow::{anyhow, Context, Result};
-use clap::Parser;
+use clap::{ArgEnum, Parser};
 use itertools::iproduct;
-use ndarray::{ArrayBase, Ix2, OwnedRepr};
+use log::{info, warn};
+use ndarray::{Array2, ArrayBase, Ix2, OwnedRepr};
 use noodles_fasta as fasta;
-use std::collections::HashSet;
-use std::fmt::Write as _;
+use rayon::prelude::*;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{Display, Write as _};
 use std::io::{BufRead, Error, Write};
 use std::iter::FromIterator;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+mod binary;
+mod checkpoint;
+#[cfg(feature = "hdf5")]
+mod h5;
+mod model;
+mod network;
+mod phylip;
+mod tree;
+
+pub use binary::{read_binary, write_binary, BinaryMatrix, BINARY_MAGIC, BINARY_VERSION};
+pub use checkpoint::{Checkpoint, Fingerprint};
+#[cfg(feature = "hdf5")]
+pub use h5::write_hdf5;
+pub use model::{base_frequencies, substitution_counts, Model, SubstitutionCounts};
+pub use network::{write_edgelist, write_graphml, write_sparse};
+pub use tree::neighbour_joining;
 
 const IGNORE: u8 = b'.';
+/// Maximum number of characters sampled when checking whether an alignment looks like protein
+const ALPHABET_SAMPLE_SIZE: usize = 100_000;
+/// Fraction of sampled letters that are not A, C, G, T, U, or N above which an alignment is
+/// considered protein
+const PROTEIN_THRESHOLD: f64 = 0.1;
 
 trait SortExt<T> {
     fn argsort(&self) -> Vec<usize>;
@@ -22,242 +49,2607 @@ impl<T: Ord + Clone> SortExt<T> for Vec<T> {
         indices
     }
 
-    fn sort_by_indices(&mut self, indices: &mut Vec<usize>) {
-        for idx in 0..self.len() {
-            if indices[idx] != usize::MAX {
-                let mut current_idx = idx;
-                loop {
-                    let target_idx = indices[current_idx];
-                    indices[current_idx] = usize::MAX;
-                    if indices[target_idx] == usize::MAX {
-                        break;
-                    }
-                    self.swap(current_idx, target_idx);
-                    current_idx = target_idx;
-                }
-            }
+    fn sort_by_indices(&mut self, indices: &mut Vec<usize>) {
+        for idx in 0..self.len() {
+            if indices[idx] != usize::MAX {
+                let mut current_idx = idx;
+                loop {
+                    let target_idx = indices[current_idx];
+                    indices[current_idx] = usize::MAX;
+                    if indices[target_idx] == usize::MAX {
+                        break;
+                    }
+                    self.swap(current_idx, target_idx);
+                    current_idx = target_idx;
+                }
+            }
+        }
+    }
+}
+
+fn parse_ignored_chars(s: &str) -> HashSet<u8> {
+    HashSet::from_iter(s.as_bytes().to_vec())
+}
+
+/// Read an order file - one ID per line - into the position of each ID. Only the first occurrence
+/// of an ID is used.
+fn read_order(path: &Path) -> Result<HashMap<Vec<u8>, usize>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read order file {}", path.display()))?;
+    let mut order = HashMap::new();
+    for id in contents.lines().filter_map(|l| l.split_whitespace().next()) {
+        let rank = order.len();
+        order.entry(id.as_bytes().to_vec()).or_insert(rank);
+    }
+    Ok(order)
+}
+
+/// Read the regions of a BED file as 0-based, half-open intervals, along with the (1-based) line
+/// each is on. Regions are 1-based and inclusive, or 0-based and half-open (as in the BED
+/// specification) if `zero_based`. The sequence (first) field is not used.
+fn read_intervals(path: &Path, zero_based: bool) -> Result<Vec<(usize, Range<usize>)>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read regions file {}", path.display()))?;
+    let mut intervals = vec![];
+    for (lineno, line) in contents.lines().enumerate() {
+        let is_header = ["#", "track", "browser"]
+            .iter()
+            .any(|h| line.starts_with(h));
+        if line.trim().is_empty() || is_header {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (start, end) = match fields.as_slice() {
+            [_, start, end, ..] => (start.parse::<usize>(), end.parse::<usize>()),
+            _ => {
+                return Err(anyhow!(
+                    "Expected at least 3 fields on line {} of regions file",
+                    lineno + 1
+                ))
+            }
+        };
+        // convert to 0-based, half-open
+        let (start, end) = match (start, end) {
+            (Ok(start), Ok(end)) if zero_based && start < end => (start, end),
+            (Ok(start), Ok(end)) if !zero_based && 0 < start && start <= end => (start - 1, end),
+            _ => {
+                return Err(anyhow!(
+                    "Invalid region on line {} of regions file",
+                    lineno + 1
+                ))
+            }
+        };
+        intervals.push((lineno + 1, start..end));
+    }
+    Ok(intervals)
+}
+
+/// Read a BED file into whether each of the `seqlen` columns is in any of its regions - see
+/// [`read_intervals`]
+fn read_regions(path: &Path, seqlen: usize, zero_based: bool) -> Result<Vec<bool>> {
+    let mut included = vec![false; seqlen];
+    for (lineno, interval) in read_intervals(path, zero_based)? {
+        if interval.end > seqlen {
+            return Err(anyhow!(
+                "Region on line {} of regions file ends after the alignment ({}bp)",
+                lineno,
+                seqlen
+            ));
+        }
+        included[interval].fill(true);
+    }
+    Ok(included)
+}
+
+// A struct to hold all of the options for the transforming sequences
+#[derive(Parser, Debug, Default)]
+pub struct Transformer {
+    /// Case matters - i.e., dist(a, A) = 1
+    #[clap(short, long)]
+    case_sensitive: bool,
+    /// Sort the alignment(s) by ID
+    #[clap(short, long)]
+    sort: bool,
+    /// Order the alignment(s) by the IDs in this file - one ID per line
+    ///
+    /// Gives the same row/column order across runs, regardless of the order in the input. It is an
+    /// error if an ID in the alignment is not in the file.
+    #[clap(long, value_name = "FILE", conflicts_with = "sort")]
+    order_file: Option<PathBuf>,
+    /// Only compare the columns in the regions of this BED file
+    ///
+    /// Regions are 1-based and inclusive, so `chr\t1\t100` is the first 100 columns, unless
+    /// `--zero-based` is given. The sequence name (first field) is not used. Overlapping regions
+    /// are merged. `--stride` and `--offset` apply to the included columns.
+    #[clap(long, value_name = "BED")]
+    regions: Option<PathBuf>,
+    /// String of characters to ignore - e.g., `-e N-` -> dist(A, N) = 0 and dist(A, -) = 0
+    ///
+    /// Note, if using `--case-sensitive` the upper- and lower-case form of a character is needed.
+    /// To not ignore any characters, use `-e ''` or `-e ""`
+    #[clap(short = 'e', long, default_value="N-", parse(from_str=parse_ignored_chars), allow_hyphen_values = true)]
+    ignored_chars: HashSet<u8>,
+    /// Use the full FASTA header (ID and description) as the sequence name
+    ///
+    /// By default, only the ID - i.e., everything up to the first whitespace - is used.
+    #[clap(long)]
+    full_header: bool,
+    /// Only compare every Nth column, starting at `--offset`
+    ///
+    /// For example, `--stride 3 --offset 3` only compares third codon positions.
+    #[clap(long, default_value = "1", value_name = "N")]
+    stride: usize,
+    /// Position (1-based, unless `--zero-based`) of the first column to compare when using
+    /// `--stride` [default: first column]
+    #[clap(long, value_name = "K")]
+    offset: Option<usize>,
+    /// Treat U and T as the same base - i.e., dist(U, T) = 0
+    #[clap(long)]
+    rna: bool,
+    /// Remove sequences where every position is ignored (e.g., all N)
+    ///
+    /// Distances to such sequences are 0 as there are no sites to compare, which can make them
+    /// look identical to everything. A warning is given for these sequences regardless.
+    #[clap(long)]
+    drop_empty: bool,
+    /// A `.` in a sequence means the same character as the first (reference) sequence
+    ///
+    /// If the reference has a gap (`-`) or `.` at that position, there is nothing to substitute, so
+    /// the `.` is ignored.
+    #[clap(long)]
+    dot_is_ref: bool,
+    /// Skip (with a warning) FASTA records that can't be parsed instead of failing
+    #[clap(long)]
+    skip_bad_records: bool,
+    /// Report every sequence with the wrong length, instead of failing on the first one
+    #[clap(long)]
+    report_all_errors: bool,
+    /// Name FASTA records with an empty name `seq_<N>` (with a warning), where N is the record's
+    /// 1-based position, instead of failing
+    #[clap(long)]
+    autoname: bool,
+    /// Use 0-based, half-open coordinates (as in BED) for positions
+    ///
+    /// By default, all positions given to (e.g., `--regions` and `--offset`) and reported by (e.g.,
+    /// `--explain` and `--variant-report`) psdm are 1-based and inclusive.
+    #[clap(long)]
+    zero_based: bool,
+}
+
+pub type NamesAndSeqs = (Vec<Vec<u8>>, Vec<Vec<u8>>);
+
+/// Whether a FASTA definition line has no name - e.g., `>` or `> description`
+fn is_unnamed(definition: &str) -> bool {
+    definition
+        .strip_prefix('>')
+        .is_some_and(|rest| rest.chars().next().is_none_or(char::is_whitespace))
+}
+
+impl Transformer {
+    /// Stop ignoring the given characters - i.e., compare them like any other character
+    pub fn unignore(&mut self, chars: &[u8]) {
+        for c in chars {
+            self.ignored_chars.remove(c);
+        }
+    }
+
+    pub fn load_alignment<R: BufRead>(
+        &self,
+        reader: &mut fasta::Reader<R>,
+        starting_seqlen: usize,
+    ) -> Result<NamesAndSeqs, anyhow::Error> {
+        let mut names: Vec<Vec<u8>> = vec![];
+        let mut seqs: Vec<Vec<u8>> = vec![];
+
+        let mut n_skipped = 0;
+        let mut definition = String::new();
+        let mut seq = vec![];
+        loop {
+            definition.clear();
+            seq.clear();
+            if reader
+                .read_definition(&mut definition)
+                .context("Failed to parse record")?
+                == 0
+            {
+                break;
+            }
+            let record_number = names.len() + n_skipped + 1;
+            let name = if is_unnamed(&definition) {
+                if self.autoname {
+                    let name = format!("seq_{}", record_number).into_bytes();
+                    warn!(
+                        "Record {} has an empty name; naming it {}",
+                        record_number,
+                        String::from_utf8_lossy(&name)
+                    );
+                    Ok(name)
+                } else {
+                    Err(anyhow!(
+                        "Record {} has an empty name. Use --autoname to name it automatically",
+                        record_number
+                    ))
+                }
+            } else {
+                match definition.parse::<fasta::record::Definition>() {
+                    Err(e) => Err(anyhow!("Failed to parse record {}: {}", record_number, e)),
+                    Ok(d) => match d.description() {
+                        Some(desc) if self.full_header => Ok([d.name(), b" ", desc].concat()),
+                        _ => Ok(d.name().to_owned()),
+                    },
+                }
+            };
+            // the sequence is read even for a bad record, so the next record starts at its `>`
+            reader
+                .read_sequence(&mut seq)
+                .context("Failed to parse record")?;
+            match name {
+                Ok(name) => {
+                    names.push(name);
+                    seqs.push(seq.clone());
+                }
+                Err(e) if self.skip_bad_records => {
+                    warn!("Skipping malformed record: {}", e);
+                    n_skipped += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        if n_skipped > 0 {
+            warn!("Skipped {} malformed record(s)", n_skipped);
+        }
+
+        self.process(names, seqs, starting_seqlen)
+    }
+
+    /// Load a (relaxed) PHYLIP alignment - either sequential or interleaved
+    pub fn load_phylip<R: BufRead>(
+        &self,
+        reader: R,
+        starting_seqlen: usize,
+    ) -> Result<NamesAndSeqs, anyhow::Error> {
+        let (names, seqs) = phylip::parse(reader)?;
+        self.process(names, seqs, starting_seqlen)
+    }
+
+    /// Check the sequences are all the same length and apply the sorting, column selection, and
+    /// transformation options
+    fn process(
+        &self,
+        mut names: Vec<Vec<u8>>,
+        mut seqs: Vec<Vec<u8>>,
+        starting_seqlen: usize,
+    ) -> Result<NamesAndSeqs, anyhow::Error> {
+        let mut seqlen: usize = starting_seqlen;
+        let mut mismatches = vec![];
+        for (name, seq) in names.iter().zip(&seqs) {
+            if seqlen > 0 && seqlen != seq.len() {
+                if !self.report_all_errors {
+                    return Err(anyhow!(format!(
+                        "Alignment sequences must all be the same length [id: {}]",
+                        String::from_utf8_lossy(name)
+                    )));
+                }
+                mismatches.push(format!(
+                    "{} ({}bp)",
+                    String::from_utf8_lossy(name),
+                    seq.len()
+                ));
+            } else if seqlen == 0 {
+                seqlen = seq.len();
+            }
+        }
+        if !mismatches.is_empty() {
+            return Err(anyhow!(
+                "{} sequence(s) are not the expected length of {}bp: {}",
+                mismatches.len(),
+                seqlen,
+                mismatches.join(", ")
+            ));
+        }
+
+        if self.dot_is_ref {
+            if let Some((reference, others)) = seqs.split_first_mut() {
+                for seq in others {
+                    substitute_reference(seq, reference);
+                }
+            }
+        }
+
+        // `.` is how ignored positions are marked internally, so it is always ignored
+        if !self.ignored_chars.contains(&IGNORE) {
+            let n_dotted = seqs.iter().filter(|s| s.contains(&IGNORE)).count();
+            // any `.` left by --dot-is-ref is in the reference or over a reference gap
+            let hint = if self.dot_is_ref {
+                ""
+            } else {
+                ", or use --dot-is-ref if it means the same base as the first sequence"
+            };
+            if n_dotted > 0 {
+                warn!(
+                    "{} sequence(s) contain '.', which is always treated as an ignored position. \
+                     Add it to --ignored-chars to silence this{}",
+                    n_dotted, hint
+                );
+            }
+        }
+
+        if looks_like_protein(&seqs) {
+            warn!(
+                "Alignment looks like protein - distances will count amino acid differences. \
+                 Consider setting --ignored-chars to a protein-appropriate set (e.g., 'X-')"
+            );
+        }
+
+        if self.sort {
+            let mut indices = names.argsort();
+            names.sort();
+            seqs.sort_by_indices(&mut indices);
+        }
+
+        if let Some(ref path) = self.order_file {
+            let order = read_order(path)?;
+            let mut ranks = Vec::with_capacity(names.len());
+            for name in &names {
+                match order.get(name) {
+                    Some(rank) => ranks.push(*rank),
+                    None => {
+                        return Err(anyhow!(
+                            "{} is not in the order file",
+                            String::from_utf8_lossy(name)
+                        ))
+                    }
+                }
+            }
+            let names_set: HashSet<&Vec<u8>> = names.iter().collect();
+            let n_extra = order.keys().filter(|id| !names_set.contains(id)).count();
+            if n_extra > 0 {
+                warn!(
+                    "{} ID(s) in the order file are not in the alignment",
+                    n_extra
+                );
+            }
+            let mut indices = ranks.argsort();
+            names.sort_by_indices(&mut indices.clone());
+            seqs.sort_by_indices(&mut indices);
+        }
+
+        if let Some(ref path) = self.regions {
+            let included = read_regions(path, seqlen, self.zero_based)?;
+            for seq in seqs.iter_mut() {
+                let mut included_iter = included.iter();
+                seq.retain(|_| included_iter.next().copied().unwrap_or(false));
+            }
+            info!(
+                "Regions include {} of {} sites",
+                included.iter().filter(|i| **i).count(),
+                seqlen
+            );
+        }
+
+        if self.offset == Some(0) && !self.zero_based {
+            return Err(anyhow!(
+                "--offset is 1-based, so it must be at least 1 (or use --zero-based)"
+            ));
+        }
+        if self.stride > 1 || self.first_column() > 0 {
+            let seqlen = seqs.first().map_or(0, Vec::len);
+            for seq in seqs.iter_mut() {
+                *seq = self.select_columns(seq);
+            }
+            info!(
+                "Selected {} of {} sites (stride {}, offset {})",
+                seqs.first().map_or(0, Vec::len),
+                seqlen,
+                self.stride,
+                self.first_column() + self.coordinate_base()
+            );
+        }
+
+        let skip_transform = self.ignored_chars.is_empty() && !self.case_sensitive && !self.rna;
+        if !skip_transform {
+            for seq in seqs.iter_mut() {
+                self.transform(seq);
+            }
+        }
+
+        let is_empty = |seq: &Vec<u8>| seq.iter().all(|b| *b == IGNORE);
+        let n_empty = seqs.iter().filter(|s| is_empty(s)).count();
+        if n_empty > 0 && self.drop_empty {
+            warn!(
+                "Dropping {} sequence(s) with no unignored positions",
+                n_empty
+            );
+            let (kept_names, kept_seqs) = names
+                .into_iter()
+                .zip(seqs)
+                .filter(|(_, seq)| !is_empty(seq))
+                .unzip();
+            names = kept_names;
+            seqs = kept_seqs;
+            if seqs.is_empty() {
+                return Err(anyhow!("No sequences left after dropping empty sequences"));
+            }
+        } else if n_empty > 0 {
+            warn!(
+                "{} sequence(s) have no unignored positions, so all their distances will be 0. Use --drop-empty to remove them",
+                n_empty
+            );
+        }
+
+        let constant: Vec<_> = names
+            .iter()
+            .zip(&seqs)
+            .filter(|(_, seq)| is_constant(seq))
+            .map(|(name, _)| String::from_utf8_lossy(name))
+            .collect();
+        if !constant.is_empty() {
+            warn!(
+                "{} sequence(s) are a single repeated character, so may be malformed: {}",
+                constant.len(),
+                constant.join(", ")
+            );
+        }
+
+        Ok((names, seqs))
+    }
+
+    /// The first position of a sequence - i.e., 0 with `--zero-based`, otherwise 1
+    pub fn coordinate_base(&self) -> usize {
+        usize::from(!self.zero_based)
+    }
+
+    /// The (0-based) column of the alignment as read for each of the `n` columns kept by
+    /// `--regions`, `--stride`, and `--offset` - i.e., to report positions in the coordinates of
+    /// the input
+    pub fn column_map(&self, n: usize) -> Result<Vec<usize>> {
+        let regions: Option<Vec<usize>> = match self.regions {
+            Some(ref path) => {
+                let intervals = read_intervals(path, self.zero_based)?;
+                let seqlen = intervals.iter().map(|(_, r)| r.end).max().unwrap_or(0);
+                let included = read_regions(path, seqlen, self.zero_based)?;
+                Some((0..seqlen).filter(|i| included[*i]).collect())
+            }
+            None => None,
+        };
+        let columns = (0..n).map(|k| {
+            let i = self.first_column() + k * self.stride.max(1);
+            regions.as_ref().map_or(i, |r| r[i])
+        });
+        Ok(columns.collect())
+    }
+
+    /// 0-based index of the first column to compare - see `--offset`
+    fn first_column(&self) -> usize {
+        self.offset
+            .map_or(0, |k| k.saturating_sub(self.coordinate_base()))
+    }
+
+    /// Columns `offset`, `offset + stride`, `offset + 2 * stride`, ... of the sequence
+    fn select_columns(&self, seq: &[u8]) -> Vec<u8> {
+        seq.iter()
+            .skip(self.first_column())
+            .step_by(self.stride.max(1))
+            .copied()
+            .collect()
+    }
+
+    fn transform(&self, seq: &mut Vec<u8>) {
+        for b in seq {
+            if !self.case_sensitive {
+                b.make_ascii_uppercase();
+            }
+            if self.rna {
+                match *b {
+                    b'U' => *b = b'T',
+                    b'u' => *b = b't',
+                    _ => (),
+                }
+            }
+            if self.ignored_chars.contains(b) {
+                IGNORE.clone_into(b);
+            }
+        }
+    }
+}
+
+fn dist(a: u8, b: u8) -> u64 {
+    (a != b && a != IGNORE && b != IGNORE) as u64
+}
+
+pub fn hamming_distance(a: &[u8], b: &[u8]) -> u64 {
+    a.iter().zip(b).fold(0, |acc, (x, y)| acc + dist(*x, *y))
+}
+
+/// Hamming distance for sequences known to have no ignored positions - see [`has_ignored`]. This
+/// skips the ignored checks of [`hamming_distance`] in the hot loop.
+pub fn hamming_distance_no_ignore(a: &[u8], b: &[u8]) -> u64 {
+    a.iter().zip(b).fold(0, |acc, (x, y)| acc + (x != y) as u64)
+}
+
+/// Whether a sequence has at least two unignored positions and they are all the same character -
+/// e.g., all A or all gaps
+fn is_constant(seq: &[u8]) -> bool {
+    let mut unignored = seq.iter().filter(|b| **b != IGNORE).peekable();
+    match unignored.next() {
+        Some(first) if unignored.peek().is_some() => unignored.all(|b| b == first),
+        _ => false,
+    }
+}
+
+/// The number of times each of `seqlen` columns is drawn when resampling them with replacement
+pub fn bootstrap_weights(rng: &mut fastrand::Rng, seqlen: usize) -> Vec<u32> {
+    let mut weights = vec![0; seqlen];
+    for _ in 0..seqlen {
+        weights[rng.usize(..seqlen)] += 1;
+    }
+    weights
+}
+
+/// Randomly choose `n` of the indices `0..total` without replacement, in ascending order
+pub fn subsample_indices(rng: &mut fastrand::Rng, total: usize, n: usize) -> Vec<usize> {
+    let mut chosen = rng.choose_multiple(0..total, n);
+    chosen.sort_unstable();
+    chosen
+}
+
+/// Hamming distance with each site counted `weights[i]` times - e.g., for a bootstrap replicate
+/// from [`bootstrap_weights`]
+pub fn weighted_distance(a: &[u8], b: &[u8], weights: &[u32], iupac: bool) -> u64 {
+    let dist = if iupac { iupac_dist } else { dist };
+    a.iter()
+        .zip(b)
+        .zip(weights)
+        .fold(0, |acc, ((x, y), w)| acc + dist(*x, *y) * u64::from(*w))
+}
+
+/// The mean and sample standard deviation of some values. The standard deviation is 0 for fewer
+/// than two values.
+pub fn mean_sd(xs: &[f64]) -> (f64, f64) {
+    let n = xs.len() as f64;
+    let mean = xs.iter().sum::<f64>() / n;
+    if xs.len() < 2 {
+        return (mean, 0.0);
+    }
+    let var = xs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    (mean, var.sqrt())
+}
+
+/// Pearson's correlation coefficient of paired values. `None` if there are fewer than two pairs or
+/// either set of values is constant.
+pub fn pearson(xs: &[f64], ys: &[f64]) -> Option<f64> {
+    if xs.len() < 2 || xs.len() != ys.len() {
+        return None;
+    }
+    let (mean_x, _) = mean_sd(xs);
+    let (mean_y, _) = mean_sd(ys);
+    let (mut cov, mut var_x, mut var_y) = (0.0, 0.0, 0.0);
+    for (x, y) in xs.iter().zip(ys) {
+        cov += (x - mean_x) * (y - mean_y);
+        var_x += (x - mean_x).powi(2);
+        var_y += (y - mean_y).powi(2);
+    }
+    (var_x > 0.0 && var_y > 0.0).then(|| cov / (var_x * var_y).sqrt())
+}
+
+/// Format a number with its digits in groups of three - e.g., 1234567 as 1,234,567
+pub fn group_digits(n: u64, separator: char) -> String {
+    let digits = n.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(separator);
+        }
+        grouped.push(c);
+    }
+    grouped
+}
+
+/// Hamming distance where a site with an N (or n) in one sequence and any other non-ignored
+/// character in the other contributes `penalty` instead of 1
+pub fn n_penalty_distance(a: &[u8], b: &[u8], penalty: f64) -> f64 {
+    let is_n = |b: u8| b == b'N' || b == b'n';
+    a.iter().zip(b).fold(0.0, |acc, (x, y)| {
+        acc + match (*x, *y) {
+            (x, y) if x == IGNORE || y == IGNORE || x == y => 0.0,
+            (x, y) if is_n(x) && is_n(y) => 0.0,
+            (x, y) if is_n(x) || is_n(y) => penalty,
+            _ => 1.0,
+        }
+    })
+}
+
+/// Whether any of the sequences have an ignored position
+pub fn has_ignored<'a, I>(seqs: I) -> bool
+where
+    I: IntoIterator<Item = &'a Vec<u8>>,
+{
+    seqs.into_iter().any(|s| s.contains(&IGNORE))
+}
+
+/// Majority-rule consensus of the sequences. Ignored positions do not count towards the majority,
+/// ties are broken by the smallest byte, and a column where every sequence is ignored is ignored
+/// in the consensus. If `ambiguous`, a nucleotide column without a strict majority (more than half
+/// of its non-ignored characters) is instead the IUPAC code for all of the bases observed in it.
+pub fn consensus(seqs: &[Vec<u8>], ambiguous: bool) -> Vec<u8> {
+    let seqlen = seqs.first().map_or(0, Vec::len);
+    let mut column = [0usize; 256];
+    (0..seqlen)
+        .map(|i| {
+            column.fill(0);
+            for b in seqs.iter().filter_map(|seq| seq.get(i)) {
+                column[*b as usize] += 1;
+            }
+            column[IGNORE as usize] = 0;
+            // max_by_key returns the last max, so reverse to break ties with the smallest byte
+            let majority = match column.iter().enumerate().rev().max_by_key(|(_, n)| **n) {
+                Some((b, n)) if *n > 0 => b as u8,
+                _ => return IGNORE,
+            };
+            if !ambiguous || column[majority as usize] * 2 > column.iter().sum::<usize>() {
+                return majority;
+            }
+            let mut observed = column.iter().enumerate().filter(|(_, n)| **n > 0);
+            let bases = observed.try_fold(0, |acc, (b, _)| match iupac_bases(b as u8) {
+                0 => None,
+                bases => Some(acc | bases),
+            });
+            bases.map_or(majority, iupac_code)
+        })
+        .collect()
+}
+
+/// Replace each `.` in the sequence with the reference character at that position. A `.` over a
+/// reference gap (or `.`) is left as is, which is ignored.
+fn substitute_reference(seq: &mut [u8], reference: &[u8]) {
+    for (b, r) in seq.iter_mut().zip(reference) {
+        if *b == b'.' && *r != b'-' {
+            *b = *r;
+        }
+    }
+}
+
+/// Whether a sample of the letters in the sequences has many that are not A, C, G, T, U, or N. IUPAC
+/// ambiguity codes are also amino acids, so a nucleotide alignment with a few ambiguous bases is
+/// not considered protein.
+fn looks_like_protein(seqs: &[Vec<u8>]) -> bool {
+    let (letters, non_nucleotide) = seqs
+        .iter()
+        .flatten()
+        .take(ALPHABET_SAMPLE_SIZE)
+        .filter(|b| b.is_ascii_alphabetic())
+        .fold((0usize, 0usize), |(letters, non_nuc), b| {
+            let is_nuc = matches!(
+                b.to_ascii_uppercase(),
+                b'A' | b'C' | b'G' | b'T' | b'U' | b'N'
+            );
+            (letters + 1, non_nuc + usize::from(!is_nuc))
+        });
+    letters > 0 && non_nucleotide as f64 / letters as f64 > PROTEIN_THRESHOLD
+}
+
+/// The observed alleles, and the number of sequences with each, in a column of an alignment
+pub type Alleles = Vec<(u8, usize)>;
+
+/// The (0-based) index and alleles of every variable column - i.e., columns with more than one
+/// allele. Ignored positions are not counted as an allele and alleles are sorted by byte.
+pub fn variable_sites<'a, I>(seqs: I) -> Vec<(usize, Alleles)>
+where
+    I: IntoIterator<Item = &'a Vec<u8>>,
+{
+    let seqs: Vec<&Vec<u8>> = seqs.into_iter().collect();
+    let seqlen = seqs.first().map_or(0, |s| s.len());
+    let mut sites = vec![];
+    let mut counts = [0usize; 256];
+    for i in 0..seqlen {
+        counts.fill(0);
+        for seq in &seqs {
+            counts[seq[i] as usize] += 1;
+        }
+        counts[IGNORE as usize] = 0;
+        let alleles: Alleles = counts
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| **n > 0)
+            .map(|(b, n)| (b as u8, *n))
+            .collect();
+        if alleles.len() > 1 {
+            sites.push((i, alleles));
+        }
+    }
+    sites
+}
+
+/// Lazily computes the SNP distance between every pair of sequences - see [`distances`]
+#[derive(Debug, Clone)]
+pub struct Distances<'a> {
+    seqs: &'a [Vec<u8>],
+    pairs: TriangleIndices,
+}
+
+impl Iterator for Distances<'_> {
+    type Item = (usize, usize, u64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let ix = self.pairs.find(|ix| ix[0] != ix[1])?;
+        let (i, j) = (ix[0], ix[1]);
+        Some((i, j, hamming_distance(&self.seqs[i], &self.seqs[j])))
+    }
+}
+
+/// Iterate over the SNP distance of every pair of sequences `(i, j, distance)`, where `i < j`, in
+/// the order `(0, 1), (0, 2), ..., (1, 2), ...` - i.e., that of [`triangle_indices`], which psdm
+/// uses for its own output, without the diagonal. Distances are computed as the iterator is
+/// consumed, so the results are never all held in memory.
+///
+/// ```
+/// let seqs = vec![b"ACGT".to_vec(), b"ACCT".to_vec(), b"TCCT".to_vec()];
+/// let dists: Vec<_> = psdm::distances(&seqs).collect();
+/// assert_eq!(dists, vec![(0, 1, 1), (0, 2, 2), (1, 2, 1)]);
+/// ```
+pub fn distances(seqs: &[Vec<u8>]) -> Distances<'_> {
+    Distances {
+        seqs,
+        pairs: triangle_indices(seqs.len()),
+    }
+}
+
+/// Group identical sequences. Returns the index of the first occurrence of each distinct sequence
+/// (the representatives) and, for every sequence, the position of its representative in that list.
+pub fn dedup(seqs: &[Vec<u8>]) -> (Vec<usize>, Vec<usize>) {
+    let mut seen: HashMap<&[u8], usize> = HashMap::with_capacity(seqs.len());
+    let mut representatives = vec![];
+    let assignment = seqs
+        .iter()
+        .enumerate()
+        .map(|(i, seq)| {
+            *seen.entry(seq).or_insert_with(|| {
+                representatives.push(i);
+                representatives.len() - 1
+            })
+        })
+        .collect();
+    (representatives, assignment)
+}
+
+/// Single-linkage clusters of `n` items connected by `edges`. Returns each item's (0-based)
+/// cluster, with clusters numbered in order of their first item.
+pub fn single_linkage<I>(n: usize, edges: I) -> Vec<usize>
+where
+    I: IntoIterator<Item = (usize, usize)>,
+{
+    let mut parent: Vec<usize> = (0..n).collect();
+    fn find(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+    for (a, b) in edges {
+        let (root_a, root_b) = (find(&mut parent, a), find(&mut parent, b));
+        if root_a != root_b {
+            parent[root_a.max(root_b)] = root_a.min(root_b);
+        }
+    }
+
+    let mut cluster_of_root = HashMap::new();
+    (0..n)
+        .map(|i| {
+            let root = find(&mut parent, i);
+            let next_id = cluster_of_root.len();
+            *cluster_of_root.entry(root).or_insert(next_id)
+        })
+        .collect()
+}
+
+/// Whether each column has no ignored characters in any sequence
+fn complete_mask<'a, I>(seqs: I) -> Vec<bool>
+where
+    I: IntoIterator<Item = &'a Vec<u8>>,
+{
+    let mut seqs = seqs.into_iter().peekable();
+    let seqlen = seqs.peek().map_or(0, |s| s.len());
+    let mut keep = vec![true; seqlen];
+    for seq in seqs {
+        for (k, b) in keep.iter_mut().zip(seq.iter()) {
+            if *b == IGNORE {
+                *k = false;
+            }
+        }
+    }
+    keep
+}
+
+/// Number of columns with no ignored characters in any sequence - i.e., the columns kept by
+/// [`complete_deletion`]
+pub fn complete_columns<'a, I>(seqs: I) -> usize
+where
+    I: IntoIterator<Item = &'a Vec<u8>>,
+{
+    complete_mask(seqs).iter().filter(|k| **k).count()
+}
+
+/// Number of sites that would be compared between two sequences - i.e., where neither is ignored
+pub fn compared_sites(a: &[u8], b: &[u8]) -> u64 {
+    a.iter()
+        .zip(b)
+        .filter(|(x, y)| **x != IGNORE && **y != IGNORE)
+        .count() as u64
+}
+
+/// Remove the leading and trailing columns where every sequence has a gap (see [`is_gap`]).
+/// Returns the number of leading and trailing columns removed.
+pub fn trim_flanks(seqs: &mut [&mut Vec<u8>]) -> (usize, usize) {
+    let seqlen = seqs.first().map_or(0, |s| s.len());
+    let all_gaps = |i: usize| seqs.iter().all(|s| is_gap(s[i]));
+    let leading = (0..seqlen).take_while(|i| all_gaps(*i)).count();
+    let trailing = (leading..seqlen).rev().take_while(|i| all_gaps(*i)).count();
+
+    for seq in seqs.iter_mut() {
+        seq.truncate(seqlen - trailing);
+        seq.drain(..leading);
+    }
+    (leading, trailing)
+}
+
+/// Remove every column where any sequence has an ignored character ("complete deletion") so that
+/// all pairs are compared over the same set of sites. Returns the (0-based) original index of each
+/// column kept.
+pub fn complete_deletion(seqs: &mut [&mut Vec<u8>]) -> Vec<usize> {
+    let keep = complete_mask(seqs.iter().map(|s| &**s));
+
+    for seq in seqs.iter_mut() {
+        let mut keep_iter = keep.iter();
+        seq.retain(|_| keep_iter.next().copied().unwrap_or(false));
+    }
+    (0..keep.len()).filter(|i| keep[*i]).collect()
+}
+
+/// Remove every column with fewer than two alleles (ignoring ignored positions) and release the
+/// spare memory. Such columns never contribute to a SNP distance, so the raw distances are
+/// unchanged. Returns the (0-based) original index of each column kept.
+pub fn compact(seqs: &mut [&mut Vec<u8>]) -> Vec<usize> {
+    let kept: Vec<usize> = variable_sites(seqs.iter().map(|s| &**s))
+        .into_iter()
+        .map(|(i, _)| i)
+        .collect();
+
+    for seq in seqs.iter_mut() {
+        let compacted: Vec<u8> = kept.iter().map(|i| seq[*i]).collect();
+        **seq = compacted;
+    }
+    kept
+}
+
+/// The SNP distance function for the sequences - IUPAC-aware if `iupac`, otherwise the Hamming
+/// distance, skipping the ignored checks in the hot loop if no sequence has an ignored position
+pub fn raw_distance<'a, I>(seqs: I, iupac: bool) -> fn(&[u8], &[u8]) -> u64
+where
+    I: IntoIterator<Item = &'a Vec<u8>>,
+{
+    if iupac {
+        iupac_distance
+    } else if has_ignored(seqs) {
+        hamming_distance
+    } else {
+        info!("No ignored positions found; using the fast path");
+        hamming_distance_no_ignore
+    }
+}
+
+/// The SNP distance matrix between all sequences of a single alignment. This is the computation
+/// psdm runs for a single alignment - [`raw_distance`] over the pairs of [`triangle_indices`] -
+/// without its progress and checkpointing. Pairs are computed in parallel on the current rayon
+/// thread pool.
+pub fn pairwise_matrix(seqs: &[Vec<u8>]) -> Array2<u64> {
+    let n = seqs.len();
+    let distance = raw_distance(seqs, false);
+    let pairwise_indices: Vec<Vec<usize>> = triangle_indices(n).collect();
+    let dists: Vec<u64> = pairwise_indices
+        .par_iter()
+        .map(|ix| match (ix[0], ix[1]) {
+            (i, j) if i == j => 0,
+            (i, j) => distance(&seqs[i], &seqs[j]),
+        })
+        .collect();
+
+    let mut matrix = Array2::zeros((n, n));
+    for (ix, d) in pairwise_indices.iter().zip(dists) {
+        matrix[[ix[0], ix[1]]] = d;
+        matrix[[ix[1], ix[0]]] = d;
+    }
+    matrix
+}
+
+/// Rank the rows of a distance matrix by their mean distance, most distant first, and return the
+/// index and mean of the top `n` rows. If `skip_diagonal`, the diagonal (distance to self) is not
+/// included in the mean. If given, `weights` has a weight for each column, which is used to
+/// compute a weighted mean - e.g., to down-weight technical replicates.
+pub fn outliers(
+    matrix: &Array2<u64>,
+    n: usize,
+    skip_diagonal: bool,
+    weights: Option<&[f64]>,
+) -> Vec<(usize, f64)> {
+    let weight = |j: usize| weights.map_or(1.0, |w| w[j]);
+    let mut means: Vec<(usize, f64)> = matrix
+        .rows()
+        .into_iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let (sum, total_weight) = row
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| !skip_diagonal || i != *j)
+                .fold((0.0, 0.0), |(sum, total), (j, d)| {
+                    (sum + weight(j) * *d as f64, total + weight(j))
+                });
+            let mean = if total_weight > 0.0 {
+                sum / total_weight
+            } else {
+                0.0
+            };
+            (i, mean)
+        })
+        .collect();
+    means.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    means.truncate(n);
+    means
+}
+
+/// Exact (nearest-rank) quantiles of integer distances, computed from a histogram of the distances
+/// rather than a sorted copy. Each quantile should be in [0, 1]. Returns `None` if there are no
+/// distances.
+pub fn quantiles<I>(dists: I, qs: &[f64]) -> Option<Vec<u64>>
+where
+    I: IntoIterator<Item = u64>,
+{
+    let mut histogram: Vec<u64> = vec![];
+    let mut n = 0u64;
+    for d in dists {
+        let d = d as usize;
+        if d >= histogram.len() {
+            histogram.resize(d + 1, 0);
+        }
+        histogram[d] += 1;
+        n += 1;
+    }
+    if n == 0 {
+        return None;
+    }
+    let quantile = |q: f64| {
+        let rank = ((q * n as f64).ceil() as u64).max(1);
+        let mut cumulative = 0;
+        for (d, count) in histogram.iter().enumerate() {
+            cumulative += count;
+            if cumulative >= rank {
+                return d as u64;
+            }
+        }
+        (histogram.len() - 1) as u64
+    };
+    Some(qs.iter().map(|q| quantile(*q)).collect())
+}
+
+/// Indices of the sequences in a (square) distance matrix that have at least one other sequence
+/// within `max_dist` - i.e., everything that isn't a singleton
+pub fn non_singletons(matrix: &Array2<u64>, max_dist: u64) -> Vec<usize> {
+    matrix
+        .rows()
+        .into_iter()
+        .enumerate()
+        .filter(|(i, row)| {
+            row.iter()
+                .enumerate()
+                .any(|(j, d)| *i != j && *d <= max_dist)
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// The mean distance between all pairs of sequences from each pair of groups. `col_groups` and
+/// `row_groups` give the group index (if any) of each column and row of the matrix. The result is
+/// indexed by (row group, column group) and is `None` where there are no pairs to average. If
+/// `skip_diagonal`, the distance of a sequence to itself is not included.
+pub fn group_means(
+    matrix: &Array2<u64>,
+    col_groups: &[Option<usize>],
+    row_groups: &[Option<usize>],
+    n_groups: usize,
+    skip_diagonal: bool,
+) -> Array2<Option<f64>> {
+    let mut sums = Array2::<u64>::zeros((n_groups, n_groups));
+    let mut counts = Array2::<u64>::zeros((n_groups, n_groups));
+    for ((i, j), d) in matrix.indexed_iter() {
+        if skip_diagonal && i == j {
+            continue;
+        }
+        if let (Some(r), Some(c)) = (row_groups[i], col_groups[j]) {
+            sums[[r, c]] += d;
+            counts[[r, c]] += 1;
+        }
+    }
+    ndarray::Zip::from(&sums)
+        .and(&counts)
+        .map_collect(|s, n| (*n > 0).then(|| *s as f64 / *n as f64))
+}
+
+/// Truncate names to at most `width` characters for fixed-width output. Returns the names and the
+/// number that were truncated, or an error if truncation makes any names identical.
+pub fn truncate_names(names: &[Vec<u8>], width: usize) -> Result<(Vec<Vec<u8>>, usize)> {
+    let mut n_truncated = 0;
+    let mut truncated = Vec::with_capacity(names.len());
+    for name in names {
+        let name = String::from_utf8_lossy(name);
+        if name.chars().count() > width {
+            n_truncated += 1;
+        }
+        truncated.push(name.chars().take(width).collect::<String>().into_bytes());
+    }
+
+    let mut seen = HashSet::new();
+    for name in &truncated {
+        if !seen.insert(name) {
+            return Err(anyhow!(
+                "Names are not unique when truncated to {} characters [name: {}]",
+                width,
+                String::from_utf8_lossy(name)
+            ));
+        }
+    }
+    Ok((truncated, n_truncated))
+}
+
+/// The set of nucleotides an (upper-case) IUPAC code represents, as a bitmask over ACGT. Returns
+/// 0 for anything that is not a nucleotide code.
+fn iupac_bases(b: u8) -> u8 {
+    const A: u8 = 0b0001;
+    const C: u8 = 0b0010;
+    const G: u8 = 0b0100;
+    const T: u8 = 0b1000;
+    match b {
+        b'A' => A,
+        b'C' => C,
+        b'G' => G,
+        b'T' | b'U' => T,
+        b'R' => A | G,
+        b'Y' => C | T,
+        b'S' => C | G,
+        b'W' => A | T,
+        b'K' => G | T,
+        b'M' => A | C,
+        b'B' => C | G | T,
+        b'D' => A | G | T,
+        b'H' => A | C | T,
+        b'V' => A | C | G,
+        b'N' => A | C | G | T,
+        _ => 0,
+    }
+}
+
+/// The (upper-case) IUPAC code for a set of nucleotides - the reverse of [`iupac_bases`]
+fn iupac_code(bases: u8) -> u8 {
+    b"-ACMGRSVTWYHKDBN"[bases as usize & 0b1111]
+}
+
+/// Reverse complement a nucleotide sequence, keeping the case of each base. IUPAC ambiguity codes
+/// are complemented too - e.g., R (A or G) becomes Y (C or T). Anything that is not a nucleotide
+/// code, such as a gap or an ignored position, is left as is.
+pub fn reverse_complement(seq: &[u8]) -> Vec<u8> {
+    seq.iter()
+        .rev()
+        .map(|&b| match iupac_bases(b.to_ascii_uppercase()) {
+            0 => b,
+            // complementing swaps A with T and C with G, which reverses the bits of the mask
+            bases => {
+                let c = iupac_code(bases.reverse_bits() >> 4);
+                if b.is_ascii_lowercase() {
+                    c.to_ascii_lowercase()
+                } else {
+                    c
+                }
+            }
+        })
+        .collect()
+}
+
+/// Two positions are compatible if they are identical or are IUPAC codes sharing a base
+fn iupac_compatible(a: u8, b: u8) -> bool {
+    a == b || iupac_bases(a) & iupac_bases(b) != 0
+}
+
+fn iupac_dist(a: u8, b: u8) -> u64 {
+    (a != IGNORE && b != IGNORE && !iupac_compatible(a, b)) as u64
+}
+
+/// Hamming distance where IUPAC ambiguity codes are not a difference if they are compatible -
+/// e.g., dist(R, A) = 0, but dist(R, C) = 1
+pub fn iupac_distance(a: &[u8], b: &[u8]) -> u64 {
+    a.iter()
+        .zip(b)
+        .fold(0, |acc, (x, y)| acc + iupac_dist(*x, *y))
+}
+
+/// The (0-based) positions that contribute to the distance between two sequences - i.e., where
+/// they differ and neither is ignored. If `iupac`, compatible ambiguity codes are not a difference.
+pub fn differing_positions(a: &[u8], b: &[u8], iupac: bool) -> Vec<usize> {
+    let distance = if iupac { iupac_dist } else { dist };
+    a.iter()
+        .zip(b)
+        .enumerate()
+        .filter(|(_, (x, y))| distance(**x, **y) > 0)
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Number of each ordered (from, to) substitution between pairs of sequences
+pub type Spectrum = BTreeMap<(u8, u8), u64>;
+
+/// Add each substitution from `a` to `b` - i.e., sites where they differ and neither is ignored -
+/// to the spectrum
+pub fn add_substitutions(spectrum: &mut Spectrum, a: &[u8], b: &[u8]) {
+    for (x, y) in a.iter().zip(b) {
+        if dist(*x, *y) > 0 {
+            *spectrum.entry((*x, *y)).or_insert(0) += 1;
+        }
+    }
+}
+
+/// A name as a delimited table field. Names containing the delimiter, a double quote, or a newline
+/// are quoted (RFC 4180), with any double quotes doubled.
+pub fn quote_field(name: &[u8], delimiter: char) -> Cow<'_, str> {
+    let name = String::from_utf8_lossy(name);
+    if name.contains([delimiter, '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", name.replace('"', "\"\"")))
+    } else {
+        name
+    }
+}
+
+/// Always quote a name, doubling any quotes inside it, as R expects for character fields
+fn quote_r(name: &[u8]) -> String {
+    format!("\"{}\"", String::from_utf8_lossy(name).replace('"', "\"\""))
+}
+
+/// Split a line of a delimited table into fields, undoing the quoting of [`quote_field`]
+fn split_fields(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Read a delimited distance matrix, as written by [`ToTable::to_csv`] with a header (with or
+/// without the top-left corner cell). Empty distances (e.g., a blank diagonal) are read as 0.
+pub fn read_delimited_matrix<R: BufRead>(reader: R, delimiter: char) -> Result<BinaryMatrix> {
+    let mut lines = reader.lines();
+    let header = lines.next().context("Matrix is empty")??;
+    let header: Vec<Vec<u8>> = split_fields(header.trim_end_matches('\r'), delimiter)
+        .into_iter()
+        .map(String::into_bytes)
+        .collect();
+    // without the corner cell, every header field is a column name
+    let with_corner = |header: &[Vec<u8>]| header.iter().skip(1).cloned().collect::<Vec<_>>();
+    let mut column_names: Option<Vec<Vec<u8>>> = None;
+    let mut row_names = vec![];
+    let mut values = vec![];
+    for (lineno, line) in lines.enumerate() {
+        let line = line?;
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() {
+            continue;
+        }
+        let mut fields = split_fields(line, delimiter).into_iter();
+        row_names.push(fields.next().unwrap_or_default().into_bytes());
+        let row: Vec<u64> = fields
+            .map(|f| match f.trim() {
+                "" => Ok(0),
+                f => f.parse::<u64>(),
+            })
+            .collect::<std::result::Result<_, _>>()
+            .with_context(|| format!("Invalid distance on line {} of matrix", lineno + 2))?;
+        let column_names = column_names.get_or_insert_with(|| match row.len() == header.len() {
+            true => header.clone(),
+            false => with_corner(&header),
+        });
+        if row.len() != column_names.len() {
+            return Err(anyhow!(
+                "Expected {} distances on line {} of matrix, got {}",
+                column_names.len(),
+                lineno + 2,
+                row.len()
+            ));
+        }
+        values.extend(row);
+    }
+    let column_names = column_names.unwrap_or_else(|| with_corner(&header));
+    let matrix = Array2::from_shape_vec((row_names.len(), column_names.len()), values)?;
+    Ok(BinaryMatrix {
+        matrix,
+        column_names,
+        row_names,
+    })
+}
+
+/// How to round floating point values to the number of decimal places written
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoundMode {
+    /// Round to the nearest value (left to the formatting)
+    Nearest,
+    /// Round down (towards negative infinity)
+    Floor,
+    /// Round up (towards positive infinity)
+    Ceil,
+    /// Drop the extra digits (round towards zero)
+    Truncate,
+}
+
+impl RoundMode {
+    /// Round `x` to `precision` decimal places. `Nearest` returns `x` as is, as formatting it with
+    /// `precision` decimal places already rounds it to the nearest value.
+    pub fn round(&self, x: f64, precision: usize) -> f64 {
+        if *self == RoundMode::Nearest {
+            return x;
+        }
+        let scale = 10f64.powi(precision as i32);
+        let scaled = x * scale;
+        // values like 0.29 are just below their decimal value in binary (28.999... when scaled),
+        // so don't let that tip them over into the next value down
+        let nearest = scaled.round();
+        if (scaled - nearest).abs() < 1e-9 * nearest.abs().max(1.0) {
+            return nearest / scale;
+        }
+        let rounded = match self {
+            RoundMode::Nearest => nearest,
+            RoundMode::Floor => scaled.floor(),
+            RoundMode::Ceil => scaled.ceil(),
+            RoundMode::Truncate => scaled.trunc(),
+        };
+        rounded / scale
+    }
+}
+
+/// Format a floating point value with the given number of decimal places, or NA if it is `None`
+pub fn format_float(x: Option<f64>, precision: usize) -> String {
+    match x {
+        Some(x) => format!("{:.*}", precision, x),
+        None => "NA".to_string(),
+    }
+}
+
+/// Summary of the comparison between two sequences
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PairStats {
+    /// Number of differences
+    pub snps: u64,
+    /// Number of positions where neither sequence is ignored
+    pub sites: u64,
+    /// Number of positions that only match because of IUPAC ambiguity - e.g., R and A
+    pub soft_matches: u64,
+    /// Length of the longest run of positions where both sequences are gaps (or ignored)
+    pub max_gap_run: u64,
+    /// Number of positions that only differ by case - e.g., a and A
+    pub case_diffs: u64,
+}
+
+/// Optional columns, and the formatting of the percent identity, of the full report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReportColumns {
+    /// Number of IUPAC soft matches
+    pub soft_matches: bool,
+    /// Longest shared gap run
+    pub max_gap_run: bool,
+    /// Number of case-only differences
+    pub case_diffs: bool,
+    /// Number of decimal places for the percent identity
+    pub identity_precision: usize,
+}
+
+impl Default for ReportColumns {
+    fn default() -> Self {
+        ReportColumns {
+            soft_matches: false,
+            max_gap_run: false,
+            case_diffs: false,
+            identity_precision: 4,
+        }
+    }
+}
+
+impl PairStats {
+    /// Percent of compared sites that are not different. `None` if no sites were compared
+    pub fn identity(&self) -> Option<f64> {
+        if self.sites == 0 {
+            None
+        } else {
+            Some((self.sites - self.snps) as f64 / self.sites as f64 * 100.0)
+        }
+    }
+}
+
+/// The kinds of character counted by [`base_composition`], in order
+pub const COMPOSITION_KINDS: [&str; 8] = ["A", "C", "G", "T", "N", "gap", "ignored", "other"];
+
+/// Count each kind of character in [`COMPOSITION_KINDS`] across all sequences. Bases are counted
+/// regardless of case, and anything masked by `--ignored-chars` (including N and `-` by default)
+/// is counted as ignored.
+pub fn base_composition<'a, I>(seqs: I) -> [u64; 8]
+where
+    I: IntoIterator<Item = &'a Vec<u8>>,
+{
+    let mut counts = [0u64; 8];
+    for seq in seqs {
+        for b in seq {
+            let kind = match b.to_ascii_uppercase() {
+                b'A' => 0,
+                b'C' => 1,
+                b'G' => 2,
+                b'T' => 3,
+                b'N' => 4,
+                b'-' => 5,
+                IGNORE => 6,
+                _ => 7,
+            };
+            counts[kind] += 1;
+        }
+    }
+    counts
+}
+
+/// A gap is `-`, or an ignored character (as gaps are ignored by default)
+fn is_gap(b: u8) -> bool {
+    b == b'-' || b == IGNORE
+}
+
+/// Compare two sequences, collecting the number of differences and compared sites. If `iupac` is
+/// true, compatible ambiguity codes are counted as (soft) matches. If `case_aware` is true, the
+/// same base with a different case (e.g., a and A) is counted as a case difference rather than a
+/// SNP.
+pub fn pair_stats(a: &[u8], b: &[u8], iupac: bool, case_aware: bool) -> PairStats {
+    let mut stats = PairStats::default();
+    let mut gap_run = 0;
+    for (x, y) in a.iter().zip(b) {
+        if is_gap(*x) && is_gap(*y) {
+            gap_run += 1;
+            stats.max_gap_run = stats.max_gap_run.max(gap_run);
+        } else {
+            gap_run = 0;
+        }
+        if *x == IGNORE || *y == IGNORE {
+            continue;
+        }
+        stats.sites += 1;
+        if x == y {
+            continue;
+        }
+        if case_aware && x.eq_ignore_ascii_case(y) {
+            stats.case_diffs += 1;
+        } else if iupac && iupac_compatible(*x, *y) {
+            stats.soft_matches += 1;
+        } else {
+            stats.snps += 1;
+        }
+    }
+    stats
+}
+
+fn write_stats_line(
+    ostream: &mut Box<dyn Write>,
+    delimiter: char,
+    column_name: &[u8],
+    row_name: &[u8],
+    stats: &PairStats,
+    columns: ReportColumns,
+) -> Result<(), Error> {
+    let identity = format_float(stats.identity(), columns.identity_precision);
+    write!(
+        ostream,
+        "{}{d}{}{d}{}{d}{}{d}{}",
+        quote_field(column_name, delimiter),
+        quote_field(row_name, delimiter),
+        stats.snps,
+        stats.sites,
+        identity,
+        d = delimiter
+    )?;
+    if columns.soft_matches {
+        write!(ostream, "{}{}", delimiter, stats.soft_matches)?;
+    }
+    if columns.max_gap_run {
+        write!(ostream, "{}{}", delimiter, stats.max_gap_run)?;
+    }
+    if columns.case_diffs {
+        write!(ostream, "{}{}", delimiter, stats.case_diffs)?;
+    }
+    writeln!(ostream)
+}
+
+/// Write one line per pair with the SNP distance, number of compared sites and percent identity
+/// (and any optional `columns`).
+pub fn write_full_report(
+    ostream: &mut Box<dyn Write>,
+    delimiter: char,
+    stats: &Array2<PairStats>,
+    column_names: &[Vec<u8>],
+    row_names: &[Vec<u8>],
+    columns: ReportColumns,
+) -> Result<(), Error> {
+    for (i, j) in iproduct!(0..column_names.len(), 0..row_names.len()) {
+        write_stats_line(
+            ostream,
+            delimiter,
+            &column_names[i],
+            &row_names[j],
+            &stats[[j, i]],
+            columns,
+        )?;
+    }
+    Ok(())
+}
+
+/// The `[i, j]` index pairs, with `i <= j < n`, of a triangle (and diagonal) of an `n`x`n`
+/// matrix in lexicographic order - i.e., `[0, 0], [0, 1], ..., [0, n - 1], [1, 1], ...`. This order
+/// is generated explicitly, rather than relying on a library, as the long-form output depends on
+/// it.
+pub fn triangle_indices(n: usize) -> TriangleIndices {
+    TriangleIndices { n, i: 0, j: 0 }
+}
+
+/// The index pairs of a triangle (and diagonal) of a matrix - see [`triangle_indices`]
+#[derive(Debug, Clone)]
+pub struct TriangleIndices {
+    n: usize,
+    i: usize,
+    j: usize,
+}
+
+impl Iterator for TriangleIndices {
+    type Item = Vec<usize>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.i < self.n {
+            if self.j < self.n {
+                let ix = vec![self.i, self.j];
+                self.j += 1;
+                return Some(ix);
+            }
+            self.i += 1;
+            self.j = self.i;
+        }
+        None
+    }
+}
+
+/// Write the same long-form table as [`ToTable::to_long`] for an intra-alignment matrix, but
+/// straight from the lower-left triangle (and diagonal) rather than the square matrix. `dists` are
+/// in the order of [`triangle_indices`].
+pub fn write_long_triangle<T: Display>(
+    ostream: &mut Box<dyn Write>,
+    delimiter: char,
+    dists: &[T],
+    names: &[Vec<u8>],
+) -> Result<(), Error> {
+    let n = names.len();
+    // index of (a, b), with a <= b, in the triangle
+    let index = |a: usize, b: usize| a * (2 * n - a + 1) / 2 + (b - a);
+    for (i, j) in iproduct!(0..n, 0..n) {
+        writeln!(
+            ostream,
+            "{}{d}{}{d}{}",
+            quote_field(&names[i], delimiter),
+            quote_field(&names[j], delimiter),
+            dists[index(i.min(j), i.max(j))],
+            d = delimiter
+        )?;
+    }
+    Ok(())
+}
+
+/// Write a long-form table for an explicit list of (column, row) index pairs
+pub fn write_pairs<T: Display>(
+    ostream: &mut Box<dyn Write>,
+    delimiter: char,
+    pairs: &[Vec<usize>],
+    dists: &[T],
+    column_names: &[Vec<u8>],
+    row_names: &[Vec<u8>],
+) -> Result<(), Error> {
+    for (ix, dist) in pairs.iter().zip(dists) {
+        writeln!(
+            ostream,
+            "{}{d}{}{d}{}",
+            quote_field(&column_names[ix[0]], delimiter),
+            quote_field(&row_names[ix[1]], delimiter),
+            dist,
+            d = delimiter
+        )?;
+    }
+    Ok(())
+}
+
+/// Write a full report (see [`write_full_report`]) for an explicit list of (column, row) index
+/// pairs
+pub fn write_pairs_full_report(
+    ostream: &mut Box<dyn Write>,
+    delimiter: char,
+    pairs: &[Vec<usize>],
+    stats: &[PairStats],
+    column_names: &[Vec<u8>],
+    row_names: &[Vec<u8>],
+    columns: ReportColumns,
+) -> Result<(), Error> {
+    for (ix, st) in pairs.iter().zip(stats) {
+        write_stats_line(
+            ostream,
+            delimiter,
+            &column_names[ix[0]],
+            &row_names[ix[1]],
+            st,
+            columns,
+        )?;
+    }
+    Ok(())
+}
+
+/// Which header row to write with [`ToTable::to_csv`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Header {
+    /// No header row
+    Omit,
+    /// The column names, after an empty top-left corner cell
+    WithCorner,
+    /// Just the column names, so the header has one field less than the other rows
+    NoCorner,
+}
+
+pub trait ToTable {
+    fn to_csv(
+        &self,
+        ostream: &mut Box<dyn Write>,
+        delimiter: char,
+        column_names: &[Vec<u8>],
+        row_names: &[Vec<u8>],
+        blank_diagonal: bool,
+        header: Header,
+    ) -> Result<(), Error>;
+    fn to_long(
+        &self,
+        ostream: &mut Box<dyn Write>,
+        delimiter: char,
+        column_names: &[Vec<u8>],
+        row_names: &[Vec<u8>],
+    ) -> Result<(), Error>;
+    fn to_r(
+        &self,
+        ostream: &mut Box<dyn Write>,
+        delimiter: char,
+        column_names: &[Vec<u8>],
+        row_names: &[Vec<u8>],
+        blank_diagonal: bool,
+    ) -> Result<(), Error>;
+    fn to_condensed(
+        &self,
+        ostream: &mut Box<dyn Write>,
+        include_diagonal: bool,
+    ) -> Result<(), Error>;
+    fn to_pretty(
+        &self,
+        ostream: &mut Box<dyn Write>,
+        column_names: &[Vec<u8>],
+        row_names: &[Vec<u8>],
+    ) -> Result<(), Error>;
+}
+
+impl<T: Display> ToTable for ArrayBase<OwnedRepr<T>, Ix2> {
+    fn to_csv(
+        &self,
+        ostream: &mut Box<dyn Write>,
+        delimiter: char,
+        column_names: &[Vec<u8>],
+        row_names: &[Vec<u8>],
+        blank_diagonal: bool,
+        header: Header,
+    ) -> Result<(), Error> {
+        if header != Header::Omit {
+            if header == Header::WithCorner {
+                // write empty top-left corner cell
+                write!(ostream, "{}", delimiter)?;
+            }
+            let header = column_names
+                .iter()
+                .map(|x| quote_field(x, delimiter))
+                .collect::<Vec<_>>()
+                .join(&delimiter.to_string());
+            writeln!(ostream, "{}", header)?;
+        }
+
+        for (row_idx, row_name) in row_names.iter().enumerate() {
+            write!(ostream, "{}", quote_field(row_name, delimiter))?;
+            let row = self.row(row_idx);
+            let s = row
+                .iter()
+                .enumerate()
+                .fold(String::new(), |mut output, (col_idx, x)| {
+                    if blank_diagonal && col_idx == row_idx {
+                        output.push(delimiter);
+                    } else {
+                        let _ = write!(output, "{}{}", delimiter, x);
+                    }
+                    output
+                });
+            writeln!(ostream, "{}", s)?;
+        }
+        Ok(())
+    }
+
+    fn to_long(
+        &self,
+        ostream: &mut Box<dyn Write>,
+        delimiter: char,
+        column_names: &[Vec<u8>],
+        row_names: &[Vec<u8>],
+    ) -> Result<(), Error> {
+        for (i, j) in iproduct!(0..column_names.len(), 0..row_names.len()) {
+            let dist = &self[[j, i]];
+            let c_name = &column_names[i];
+            let r_name = &row_names[j];
+            writeln!(
+                ostream,
+                "{}{d}{}{d}{}",
+                quote_field(c_name, delimiter),
+                quote_field(r_name, delimiter),
+                dist,
+                d = delimiter
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Write the matrix for R's `read.csv(..., row.names = 1, check.names = FALSE)` - every name is
+    /// quoted, the top-left header cell is `""`, and a blanked diagonal is written as `NA`
+    fn to_r(
+        &self,
+        ostream: &mut Box<dyn Write>,
+        delimiter: char,
+        column_names: &[Vec<u8>],
+        row_names: &[Vec<u8>],
+        blank_diagonal: bool,
+    ) -> Result<(), Error> {
+        write!(ostream, "\"\"")?;
+        for name in column_names {
+            write!(ostream, "{}{}", delimiter, quote_r(name))?;
+        }
+        writeln!(ostream)?;
+
+        for (row_idx, row_name) in row_names.iter().enumerate() {
+            write!(ostream, "{}", quote_r(row_name))?;
+            for (col_idx, x) in self.row(row_idx).iter().enumerate() {
+                if blank_diagonal && col_idx == row_idx {
+                    write!(ostream, "{}NA", delimiter)?;
+                } else {
+                    write!(ostream, "{}{}", delimiter, x)?;
+                }
+            }
+            writeln!(ostream)?;
+        }
+        Ok(())
+    }
+
+    /// Write the upper triangle of a square matrix, one value per line, in row-major order - i.e.,
+    /// the same order as scipy's condensed distance matrix (`pdist`)
+    fn to_condensed(
+        &self,
+        ostream: &mut Box<dyn Write>,
+        include_diagonal: bool,
+    ) -> Result<(), Error> {
+        let offset = usize::from(!include_diagonal);
+        for i in 0..self.nrows() {
+            for j in (i + offset)..self.ncols() {
+                writeln!(ostream, "{}", self[[i, j]])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the matrix as an aligned table - row names are left-justified and the values are
+    /// right-justified under their column name
+    fn to_pretty(
+        &self,
+        ostream: &mut Box<dyn Write>,
+        column_names: &[Vec<u8>],
+        row_names: &[Vec<u8>],
+    ) -> Result<(), Error> {
+        let cells = self.map(|x| x.to_string());
+        let row_names: Vec<_> = row_names
+            .iter()
+            .map(|x| String::from_utf8_lossy(x))
+            .collect();
+        let name_width = row_names.iter().map(|x| x.chars().count()).max();
+        let name_width = name_width.unwrap_or(0);
+
+        write!(ostream, "{:name_width$}", "")?;
+        let mut widths = Vec::with_capacity(column_names.len());
+        for (col_idx, name) in column_names.iter().enumerate() {
+            let name = String::from_utf8_lossy(name);
+            let width = cells
+                .column(col_idx)
+                .iter()
+                .map(|x| x.chars().count())
+                .chain([name.chars().count()])
+                .max()
+                .unwrap_or(0);
+            write!(ostream, "  {:>width$}", name)?;
+            widths.push(width);
+        }
+        writeln!(ostream)?;
+
+        for (row_idx, row_name) in row_names.iter().enumerate() {
+            write!(ostream, "{:<name_width$}", row_name)?;
+            for (x, width) in cells.row(row_idx).iter().zip(&widths) {
+                write!(ostream, "  {:>width$}", x, width = *width)?;
+            }
+            writeln!(ostream)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn parse_chars() {
+        let s = "N-X";
+
+        let actual = parse_ignored_chars(s);
+        let expected: HashSet<u8> = HashSet::from_iter([b'N', b'-', b'X']);
+
+        assert_eq!(actual, expected)
+    }
+
+    #[test]
+    fn alignments_all_have_same_length() {
+        let data = b">s1\nACGT\n>s0\nCCCC\n";
+        let mut reader = fasta::Reader::new(&data[..]);
+        let t: Transformer = Default::default();
+
+        let actual = t.load_alignment(&mut reader, 0).unwrap();
+        let expected = (
+            vec![b"s1".to_vec(), b"s0".to_vec()],
+            vec![b"ACGT".to_vec(), b"CCCC".to_vec()],
+        );
+
+        assert_eq!(actual, expected)
+    }
+
+    #[test]
+    fn alignments_do_not_all_have_same_length() {
+        let data = b">s0\nACGT\n>s1\nCCCCC\n";
+        let mut reader = fasta::Reader::new(&data[..]);
+        let t: Transformer = Default::default();
+
+        let actual = t.load_alignment(&mut reader, 0).unwrap_err();
+        assert!(actual.to_string().contains("[id: s1]"))
+    }
+
+    #[test]
+    fn alignments_do_not_have_same_length_as_starting_seqlen() {
+        let data = b">s0\nACGT\n>s1\nCCCC\n";
+        let mut reader = fasta::Reader::new(&data[..]);
+        let t: Transformer = Default::default();
+
+        let actual = t.load_alignment(&mut reader, 1).unwrap_err();
+        assert!(actual.to_string().contains("[id: s0]"))
+    }
+
+    #[test]
+    fn alignments_report_all_length_errors() {
+        let data = b">s0\nACGT\n>s1\nCCC\n>s2\nCCCC\n>s3\nCCCCC\n";
+        let mut reader = fasta::Reader::new(&data[..]);
+        let t = Transformer {
+            report_all_errors: true,
+            ..Default::default()
+        };
+
+        let actual = t.load_alignment(&mut reader, 0).unwrap_err();
+        assert_eq!(
+            actual.to_string(),
+            "2 sequence(s) are not the expected length of 4bp: s1 (3bp), s3 (5bp)"
+        )
+    }
+
+    #[test]
+    fn alignments_sorted_by_id() {
+        let data = b">s10\nACGT\n>s51\nCCCC\n>s0\nGGCC\n";
+        let mut reader = fasta::Reader::new(&data[..]);
+        let t: Transformer = Transformer {
+            sort: true,
+            ..Default::default()
+        };
+
+        let actual = t.load_alignment(&mut reader, 0).unwrap();
+        let expected = (
+            vec![b"s0".to_vec(), b"s10".to_vec(), b"s51".to_vec()],
+            vec![b"GGCC".to_vec(), b"ACGT".to_vec(), b"CCCC".to_vec()],
+        );
+        assert_eq!(actual, expected)
+    }
+
+    #[test]
+    fn load_alignment_with_empty_name() {
+        let data = b">s0\nACGT\n>\nCCCC\n> desc\nGGGG\n";
+        let t: Transformer = Default::default();
+
+        let actual = t
+            .load_alignment(&mut fasta::Reader::new(&data[..]), 0)
+            .unwrap_err();
+        assert!(actual.to_string().contains("Record 2 has an empty name"));
+
+        let t = Transformer {
+            autoname: true,
+            ..Default::default()
+        };
+        let (names, seqs) = t
+            .load_alignment(&mut fasta::Reader::new(&data[..]), 0)
+            .unwrap();
+        assert_eq!(
+            names,
+            vec![b"s0".to_vec(), b"seq_2".to_vec(), b"seq_3".to_vec()]
+        );
+        assert_eq!(seqs[2], b"GGGG".to_vec())
+    }
+
+    #[test]
+    fn load_alignment_skip_bad_records() {
+        let data = b"junk\n>s0\nACGT\n>s1\nCCCC\n";
+        let t: Transformer = Default::default();
+
+        assert!(t
+            .load_alignment(&mut fasta::Reader::new(&data[..]), 0)
+            .is_err());
+
+        let t = Transformer {
+            skip_bad_records: true,
+            ..Default::default()
+        };
+        let (names, _) = t
+            .load_alignment(&mut fasta::Reader::new(&data[..]), 0)
+            .unwrap();
+        assert_eq!(names, vec![b"s0".to_vec(), b"s1".to_vec()])
+    }
+
+    #[test]
+    fn load_alignment_skip_bad_records_mid_file() {
+        let data = b"junk1\njunk2\njunk3\n>s0\nACGT\n>\nNNNN\n>s1\nACGA\n";
+        let t = Transformer {
+            skip_bad_records: true,
+            ..Default::default()
+        };
+
+        let (names, seqs) = t
+            .load_alignment(&mut fasta::Reader::new(&data[..]), 0)
+            .unwrap();
+
+        assert_eq!(names, vec![b"s0".to_vec(), b"s1".to_vec()]);
+        assert_eq!(seqs, vec![b"ACGT".to_vec(), b"ACGA".to_vec()])
+    }
+
+    #[test]
+    fn load_alignment_record_numbers_count_skipped_records() {
+        let data = b"junk\n>s0\nACGT\n>\nNNNN\n";
+        let t = Transformer {
+            skip_bad_records: true,
+            autoname: true,
+            ..Default::default()
+        };
+
+        let (names, _) = t
+            .load_alignment(&mut fasta::Reader::new(&data[..]), 0)
+            .unwrap();
+
+        assert_eq!(names, vec![b"s0".to_vec(), b"seq_3".to_vec()])
+    }
+
+    #[test]
+    fn load_alignment_with_full_header() {
+        let data = b">s0 foo bar\nACGT\n>s1\nCCCC\n";
+        let t = Transformer {
+            full_header: true,
+            ..Default::default()
+        };
+
+        let (names, _) = t
+            .load_alignment(&mut fasta::Reader::new(&data[..]), 0)
+            .unwrap();
+        assert_eq!(names, vec![b"s0 foo bar".to_vec(), b"s1".to_vec()]);
+
+        let t: Transformer = Default::default();
+        let (names, _) = t
+            .load_alignment(&mut fasta::Reader::new(&data[..]), 0)
+            .unwrap();
+        assert_eq!(names, vec![b"s0".to_vec(), b"s1".to_vec()])
+    }
+
+    #[test]
+    fn load_alignment_with_regions() {
+        let mut bed = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            bed,
+            "track name=genes\nchr\t6\t8\tgene2\nchr\t0\t2\nchr\t1\t3\n"
+        )
+        .unwrap();
+        let data = b">s0\nACGTACGT\n>s1\nTCGAACTA\n";
+        let t = Transformer {
+            regions: Some(bed.path().to_path_buf()),
+            zero_based: true,
+            ..Default::default()
+        };
+
+        let (_, seqs) = t
+            .load_alignment(&mut fasta::Reader::new(&data[..]), 0)
+            .unwrap();
+
+        assert_eq!(seqs, vec![b"ACGGT".to_vec(), b"TCGTA".to_vec()])
+    }
+
+    #[test]
+    fn regions_are_one_based_by_default() {
+        let mut bed = tempfile::NamedTempFile::new().unwrap();
+        writeln!(bed, "chr\t2\t3\nchr\t6\t6").unwrap();
+
+        let actual = read_regions(bed.path(), 7, false).unwrap();
+        assert_eq!(actual, vec![false, true, true, false, false, true, false]);
+
+        let actual = read_regions(bed.path(), 7, true).unwrap_err();
+        assert!(actual.to_string().contains("Invalid region on line 2"));
+    }
+
+    #[test]
+    fn select_columns_zero_based_offset() {
+        let t = Transformer {
+            stride: 3,
+            offset: Some(2),
+            zero_based: true,
+            ..Default::default()
+        };
+
+        assert_eq!(t.select_columns(b"ACGTTAGGC"), b"GAC".to_vec());
+    }
+
+    #[test]
+    fn regions_past_alignment_end() {
+        let mut bed = tempfile::NamedTempFile::new().unwrap();
+        writeln!(bed, "chr\t2\t9").unwrap();
+
+        let actual = read_regions(bed.path(), 8, true).unwrap_err();
+
+        assert!(actual
+            .to_string()
+            .contains("ends after the alignment (8bp)"))
+    }
+
+    #[test]
+    fn column_map_through_regions_and_stride() {
+        let mut bed = tempfile::NamedTempFile::new().unwrap();
+        writeln!(bed, "chr\t3\t5\nchr\t8\t10").unwrap();
+        let t = Transformer {
+            regions: Some(bed.path().to_path_buf()),
+            stride: 2,
+            offset: Some(2),
+            ..Default::default()
+        };
+
+        // regions keep columns 2, 3, 4, 7, 8, 9 of which the stride keeps every other from the
+        // second
+        assert_eq!(t.column_map(3).unwrap(), vec![3, 7, 9]);
+        assert_eq!(Transformer::default().column_map(2).unwrap(), vec![0, 1]);
+    }
+
+    #[test]
+    fn select_columns_with_stride_and_offset() {
+        let t = Transformer {
+            stride: 3,
+            offset: Some(3),
+            ..Default::default()
+        };
+
+        assert_eq!(t.select_columns(b"ACGTTAGGC"), b"GAC".to_vec());
+        assert_eq!(t.select_columns(b"AC"), b"".to_vec());
+    }
+
+    #[test]
+    fn zero_offset_is_an_error_unless_zero_based() {
+        let data = b">s0\nACGT\n>s1\nACGA\n";
+        let t = Transformer {
+            offset: Some(0),
+            ..Default::default()
+        };
+
+        let actual = t
+            .load_alignment(&mut fasta::Reader::new(&data[..]), 0)
+            .unwrap_err();
+        assert!(actual.to_string().contains("--offset is 1-based"));
+
+        let t = Transformer {
+            zero_based: true,
+            ..t
+        };
+        let (_, seqs) = t
+            .load_alignment(&mut fasta::Reader::new(&data[..]), 0)
+            .unwrap();
+        assert_eq!(seqs, vec![b"ACGT".to_vec(), b"ACGA".to_vec()]);
+    }
+
+    #[test]
+    fn load_alignment_with_order_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("order.txt");
+        std::fs::write(&path, "s2\nother\n\ns0\ns1\n").unwrap();
+        let data = b"3 2\ns0 AC\ns1 GT\ns2 TT\n";
+        let t = Transformer {
+            order_file: Some(path.clone()),
+            ..Default::default()
+        };
+
+        let actual = t.load_phylip(&data[..], 0).unwrap();
+        let expected = (
+            vec![b"s2".to_vec(), b"s0".to_vec(), b"s1".to_vec()],
+            vec![b"TT".to_vec(), b"AC".to_vec(), b"GT".to_vec()],
+        );
+        assert_eq!(actual, expected);
+
+        std::fs::write(&path, "s2\ns0\n").unwrap();
+        assert!(t.load_phylip(&data[..], 0).is_err())
+    }
+
+    #[test]
+    fn load_alignment_dot_is_ref() {
+        let data = b"3 5\nref AC-GT\ns1 .T..A\ns2 A....\n";
+        let t = Transformer {
+            dot_is_ref: true,
+            ..Default::default()
+        };
+
+        let (_, seqs) = t.load_phylip(&data[..], 0).unwrap();
+        let expected = vec![b"AC-GT".to_vec(), b"AT.GA".to_vec(), b"AC.GT".to_vec()];
+
+        assert_eq!(seqs, expected)
+    }
+
+    #[test]
+    fn load_alignment_drop_empty() {
+        let data = b"3 4\ns0 ACGT\ns1 NN-N\ns2 CCCC\n";
+        let t = Transformer {
+            ignored_chars: HashSet::from_iter(b"N-".to_vec()),
+            drop_empty: true,
+            ..Default::default()
+        };
+
+        let actual = t.load_phylip(&data[..], 0).unwrap();
+        let expected = (
+            vec![b"s0".to_vec(), b"s2".to_vec()],
+            vec![b"ACGT".to_vec(), b"CCCC".to_vec()],
+        );
+
+        assert_eq!(actual, expected)
+    }
+
+    #[test]
+    fn load_alignment_drop_empty_all_empty() {
+        let data = b"1 2\ns0 NN\n";
+        let t = Transformer {
+            ignored_chars: HashSet::from_iter(b"N".to_vec()),
+            drop_empty: true,
+            ..Default::default()
+        };
+
+        let actual = t.load_phylip(&data[..], 0);
+
+        assert!(actual.is_err())
+    }
+
+    #[test]
+    fn bootstrap_weights_resample_every_column() {
+        let mut rng = fastrand::Rng::with_seed(42);
+
+        let weights = bootstrap_weights(&mut rng, 10);
+
+        assert_eq!(weights.len(), 10);
+        assert_eq!(weights.iter().sum::<u32>(), 10)
+    }
+
+    #[test]
+    fn subsample_indices_are_distinct_and_sorted() {
+        let mut rng = fastrand::Rng::with_seed(7);
+
+        let chosen = subsample_indices(&mut rng, 100, 10);
+
+        assert_eq!(chosen.len(), 10);
+        assert!(chosen.windows(2).all(|w| w[0] < w[1]));
+        assert!(chosen.iter().all(|&i| i < 100))
+    }
+
+    #[test]
+    fn weighted_distance_counts_sites_by_weight() {
+        let a = b"ACGTR";
+        let b = b"ATGCA";
+
+        assert_eq!(weighted_distance(a, b, &[5, 2, 0, 1, 3], false), 6);
+        assert_eq!(weighted_distance(a, b, &[5, 2, 0, 1, 3], true), 3)
+    }
+
+    #[test]
+    fn mean_and_sample_sd() {
+        assert_eq!(mean_sd(&[2.0, 4.0, 6.0]), (4.0, 2.0));
+        assert_eq!(mean_sd(&[3.0]), (3.0, 0.0))
+    }
+
+    #[test]
+    fn pearson_correlation() {
+        assert_eq!(pearson(&[1.0, 2.0, 3.0], &[2.0, 4.0, 6.0]), Some(1.0));
+        assert_eq!(pearson(&[1.0, 2.0, 3.0], &[3.0, 2.0, 1.0]), Some(-1.0));
+        assert_eq!(pearson(&[1.0, 2.0, 3.0], &[5.0, 5.0, 5.0]), None);
+        assert_eq!(pearson(&[1.0], &[1.0]), None)
+    }
+
+    #[test]
+    fn group_digits_in_threes() {
+        assert_eq!(group_digits(0, ','), "0");
+        assert_eq!(group_digits(999, ','), "999");
+        assert_eq!(group_digits(1000, ','), "1,000");
+        assert_eq!(group_digits(1234567, '_'), "1_234_567")
+    }
+
+    #[test]
+    fn base_composition_counts_each_kind() {
+        let seqs = vec![b"ACgtN-".to_vec(), vec![b'A', IGNORE, b'R']];
+
+        assert_eq!(base_composition(&seqs), [2, 1, 1, 1, 1, 1, 1, 1])
+    }
+
+    #[test]
+    fn constant_sequences() {
+        assert!(is_constant(b"AAAA"));
+        assert!(is_constant(&[b'-', IGNORE, b'-']));
+        assert!(!is_constant(b"AACA"));
+        assert!(!is_constant(b"ACAA"));
+        assert!(!is_constant(&[b'A', IGNORE, IGNORE]));
+        assert!(!is_constant(&[IGNORE, IGNORE]))
+    }
+
+    #[test]
+    fn load_phylip_alignment() {
+        let data = b"2 4\ns0 ACGT\ns1 CCCC\n";
+        let t: Transformer = Default::default();
+
+        let actual = t.load_phylip(&data[..], 0).unwrap();
+        let expected = (
+            vec![b"s0".to_vec(), b"s1".to_vec()],
+            vec![b"ACGT".to_vec(), b"CCCC".to_vec()],
+        );
+
+        assert_eq!(actual, expected)
+    }
+
+    #[test]
+    fn n_penalty_distance_counts_n_as_partial_mismatch() {
+        let a = b"ACGNNn.T";
+        let b = b"ATGANnAT";
+
+        let actual = n_penalty_distance(a, b, 0.5);
+
+        assert_eq!(actual, 1.5)
+    }
+
+    #[test]
+    fn hamming_distance_no_ignore_matches_hamming_distance() {
+        let a = b"ACGTAC".to_vec();
+        let b = b"ACCTGG".to_vec();
+
+        assert!(!has_ignored([&a, &b]));
+        assert_eq!(hamming_distance_no_ignore(&a, &b), hamming_distance(&a, &b));
+        assert!(has_ignored([&a, &vec![b'A', IGNORE]]))
+    }
+
+    #[test]
+    fn pairwise_matrix_is_symmetric() {
+        let seqs = vec![b"ACGT".to_vec(), b"ACCT".to_vec(), b"TC.A".to_vec()];
+
+        let actual = pairwise_matrix(&seqs);
+        let expected = ndarray::arr2(&[[0, 1, 2], [1, 0, 2], [2, 2, 0]]);
+
+        assert_eq!(actual, expected)
+    }
+
+    #[test]
+    fn single_linkage_chains_clusters() {
+        let edges = vec![(4, 1), (0, 3), (3, 5)];
+
+        let actual = single_linkage(6, edges);
+        let expected = vec![0, 1, 2, 0, 1, 0];
+
+        assert_eq!(actual, expected)
+    }
+
+    #[test]
+    fn compact_keeps_only_variable_columns() {
+        let mut a = vec![b'A', b'C', IGNORE, b'T', b'G'];
+        let mut b = vec![b'A', b'G', b'A', IGNORE, b'G'];
+        let mut c = vec![b'A', b'C', b'A', b'A', IGNORE];
+
+        let actual = compact(&mut [&mut a, &mut b, &mut c]);
+
+        assert_eq!(actual, vec![1, 3]);
+        assert_eq!(a, vec![b'C', b'T']);
+        assert_eq!(b, vec![b'G', IGNORE]);
+        assert_eq!(c, vec![b'C', b'A']);
+    }
+
+    #[test]
+    fn trim_flanks_removes_all_gap_ends() {
+        let mut a = b"--AC-GT.".to_vec();
+        let mut b = vec![b'-', IGNORE, b'-', b'C', b'-', b'G', b'-', b'-'];
+
+        let actual = trim_flanks(&mut [&mut a, &mut b]);
+
+        assert_eq!(actual, (2, 1));
+        assert_eq!(a, b"AC-GT".to_vec());
+        assert_eq!(b, b"-C-G-".to_vec());
+
+        let mut c = b"---".to_vec();
+        assert_eq!(trim_flanks(&mut [&mut c]), (3, 0));
+        assert!(c.is_empty());
+    }
+
+    #[test]
+    fn complete_deletion_removes_columns_missing_in_any_sequence() {
+        let mut a = b"AC.GT".to_vec();
+        let mut b = b"TCGG.".to_vec();
+        let mut c = b"ACGAT".to_vec();
+
+        let actual = complete_deletion(&mut [&mut a, &mut b, &mut c]);
+
+        assert_eq!(actual, vec![0, 1, 3]);
+        assert_eq!(a, b"ACG".to_vec());
+        assert_eq!(b, b"TCG".to_vec());
+        assert_eq!(c, b"ACA".to_vec());
+    }
+
+    #[test]
+    fn outliers_ranked_by_mean_distance() {
+        let matrix = ndarray::arr2(&[[0, 1, 9], [1, 0, 7], [9, 7, 0]]);
+
+        let actual = outliers(&matrix, 2, true, None);
+        let expected = vec![(2, 8.0), (0, 5.0)];
+
+        assert_eq!(actual, expected);
+
+        let actual = outliers(&matrix, 5, false, None);
+        let expected = vec![(2, 16.0 / 3.0), (0, 10.0 / 3.0), (1, 8.0 / 3.0)];
+
+        assert_eq!(actual, expected)
+    }
+
+    #[test]
+    fn outliers_with_weights() {
+        let matrix = ndarray::arr2(&[[0, 1, 9], [1, 0, 7], [9, 7, 0]]);
+        let weights = [1.0, 1.0, 0.0];
+
+        let actual = outliers(&matrix, 3, true, Some(&weights));
+        let expected = vec![(2, 8.0), (0, 1.0), (1, 1.0)];
+
+        assert_eq!(actual, expected)
+    }
+
+    #[test]
+    fn quantiles_nearest_rank() {
+        let dists = vec![7, 1, 3, 3, 10, 2, 5, 4, 3, 8];
+
+        let actual = quantiles(dists, &[0.0, 0.25, 0.5, 0.75, 0.95, 1.0]);
+
+        assert_eq!(actual, Some(vec![1, 3, 3, 7, 10, 10]));
+        assert_eq!(quantiles(vec![], &[0.5]), None)
+    }
+
+    #[test]
+    fn non_singletons_within_max_dist() {
+        let matrix = ndarray::arr2(&[[0, 1, 9], [1, 0, 7], [9, 7, 0]]);
+
+        assert_eq!(non_singletons(&matrix, 1), vec![0, 1]);
+        assert_eq!(non_singletons(&matrix, 7), vec![0, 1, 2]);
+        assert_eq!(non_singletons(&matrix, 0), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn group_means_of_matrix() {
+        let matrix = ndarray::arr2(&[[0, 2, 6, 8], [2, 0, 4, 6], [6, 4, 0, 1], [8, 6, 1, 0]]);
+        let groups = vec![Some(0), Some(0), Some(1), None];
+
+        let actual = group_means(&matrix, &groups, &groups, 3, true);
+        let expected = ndarray::arr2(&[
+            [Some(2.0), Some(5.0), None],
+            [Some(5.0), None, None],
+            [None, None, None],
+        ]);
+
+        assert_eq!(actual, expected)
+    }
+
+    #[test]
+    fn truncate_names_to_width() {
+        let names = vec![b"sample1".to_vec(), b"s2".to_vec(), b"other".to_vec()];
+
+        let (actual, n_truncated) = truncate_names(&names, 4).unwrap();
+        let expected = vec![b"samp".to_vec(), b"s2".to_vec(), b"othe".to_vec()];
+
+        assert_eq!(actual, expected);
+        assert_eq!(n_truncated, 2)
+    }
+
+    #[test]
+    fn truncate_names_not_unique() {
+        let names = vec![b"sample1".to_vec(), b"sample2".to_vec()];
+
+        let actual = truncate_names(&names, 6).unwrap_err();
+
+        assert!(actual.to_string().contains("not unique"))
+    }
+
+    #[test]
+    fn distances_with_fewer_than_two_sequences_is_empty() {
+        assert_eq!(distances(&[]).count(), 0);
+        assert_eq!(distances(&[b"ACGT".to_vec()]).count(), 0);
+    }
+
+    #[test]
+    fn distances_match_pairwise_matrix() {
+        let seqs = vec![
+            b"ACGT".to_vec(),
+            b"ACCT".to_vec(),
+            b"TC.A".to_vec(),
+            b"TCGA".to_vec(),
+        ];
+        let matrix = pairwise_matrix(&seqs);
+
+        let actual: Vec<_> = distances(&seqs).collect();
+
+        assert_eq!(actual.len(), 6);
+        for (i, j, d) in actual {
+            assert!(i < j);
+            assert_eq!(d, matrix[[i, j]]);
         }
     }
-}
 
-fn parse_ignored_chars(s: &str) -> HashSet<u8> {
-    HashSet::from_iter(s.as_bytes().to_vec())
-}
+    #[test]
+    fn distances_in_triangle_order_without_diagonal() {
+        let seqs = vec![b"A".to_vec(), b"C".to_vec(), b"G".to_vec()];
 
-// A struct to hold all of the options for the transforming sequences
-#[derive(Parser, Debug, Default)]
-pub struct Transformer {
-    /// Case matters - i.e., dist(a, A) = 1
-    #[clap(short, long)]
-    case_sensitive: bool,
-    /// Sort the alignment(s) by ID
-    #[clap(short, long)]
-    sort: bool,
-    /// String of characters to ignore - e.g., `-e N-` -> dist(A, N) = 0 and dist(A, -) = 0
-    ///
-    /// Note, if using `--case-sensitive` the upper- and lower-case form of a character is needed.
-    /// To not ignore any characters, use `-e ''` or `-e ""`
-    #[clap(short = 'e', long, default_value="N-", parse(from_str=parse_ignored_chars), allow_hyphen_values = true)]
-    ignored_chars: HashSet<u8>,
-}
+        let actual: Vec<Vec<usize>> = distances(&seqs).map(|(i, j, _)| vec![i, j]).collect();
+        let expected: Vec<Vec<usize>> = triangle_indices(3).filter(|ix| ix[0] != ix[1]).collect();
 
-type NamesAndSeqs = (Vec<Vec<u8>>, Vec<Vec<u8>>);
+        assert_eq!(actual, expected)
+    }
 
-impl Transformer {
-    pub fn load_alignment<R: BufRead>(
-        &self,
-        reader: &mut fasta::Reader<R>,
-        starting_seqlen: usize,
-    ) -> Result<NamesAndSeqs, anyhow::Error> {
-        let mut seqlen: usize = starting_seqlen;
-        let mut names: Vec<Vec<u8>> = vec![];
-        let mut seqs: Vec<Vec<u8>> = vec![];
+    #[test]
+    fn dedup_identical_sequences() {
+        let seqs = vec![
+            b"ACGT".to_vec(),
+            b"ACGA".to_vec(),
+            b"ACGT".to_vec(),
+            b"ACGA".to_vec(),
+            b"TTTT".to_vec(),
+        ];
 
-        for result in reader.records() {
-            let record = result.context("Failed to parse record")?;
-            names.push(record.name().to_owned());
-            if seqlen > 0 && seqlen != record.sequence().len() {
-                return Err(anyhow!(format!(
-                    "Alignment sequences must all be the same length [id: {}]",
-                    String::from_utf8_lossy(record.name())
-                )));
-            } else if seqlen == 0 {
-                seqlen = record.sequence().len();
-            }
-            let seq = record.sequence().as_ref();
-            seqs.push(seq.to_vec());
-        }
+        let (representatives, assignment) = dedup(&seqs);
 
-        if self.sort {
-            let mut indices = names.argsort();
-            names.sort();
-            seqs.sort_by_indices(&mut indices);
-        }
+        assert_eq!(representatives, vec![0, 1, 4]);
+        assert_eq!(assignment, vec![0, 1, 0, 1, 2])
+    }
 
-        let skip_transform = self.ignored_chars.is_empty() && !self.case_sensitive;
-        if !skip_transform {
-            for seq in seqs.iter_mut() {
-                self.transform(seq);
-            }
-        }
+    #[test]
+    fn differing_positions_skip_ignored() {
+        let a = b"ACGTRA";
+        let b = [b'A', b'G', IGNORE, b'A', b'A', b'C'];
 
-        Ok((names, seqs))
+        assert_eq!(differing_positions(a, &b, false), vec![1, 3, 4, 5]);
+        assert_eq!(differing_positions(a, &b, true), vec![1, 3, 5])
     }
 
-    fn transform(&self, seq: &mut Vec<u8>) {
-        for b in seq {
-            if !self.case_sensitive {
-                b.make_ascii_uppercase();
-            }
-            if self.ignored_chars.contains(b) {
-                IGNORE.clone_into(b);
-            }
-        }
+    #[test]
+    fn triangle_indices_in_lexicographic_order() {
+        let actual: Vec<Vec<usize>> = triangle_indices(3).collect();
+        let expected = vec![
+            vec![0, 0],
+            vec![0, 1],
+            vec![0, 2],
+            vec![1, 1],
+            vec![1, 2],
+            vec![2, 2],
+        ];
+
+        assert_eq!(actual, expected)
     }
-}
 
-fn dist(a: u8, b: u8) -> u64 {
-    (a != b && a != IGNORE && b != IGNORE) as u64
-}
+    #[test]
+    fn long_triangle_matches_long_matrix() {
+        let dir = tempfile::tempdir().unwrap();
+        let names = vec![b"s0".to_vec(), b"s1".to_vec(), b"s2".to_vec()];
+        let matrix = ndarray::arr2(&[[0, 1, 2], [1, 0, 3], [2, 3, 0]]);
+        let dists: Vec<u64> = triangle_indices(3)
+            .map(|ix| matrix[[ix[0], ix[1]]])
+            .collect();
 
-pub fn hamming_distance(a: &[u8], b: &[u8]) -> u64 {
-    a.iter().zip(b).fold(0, |acc, (x, y)| acc + dist(*x, *y))
-}
+        let expected_path = dir.path().join("expected");
+        let mut ostream: Box<dyn Write> = Box::new(std::fs::File::create(&expected_path).unwrap());
+        matrix.to_long(&mut ostream, ',', &names, &names).unwrap();
+        let actual_path = dir.path().join("actual");
+        let mut ostream: Box<dyn Write> = Box::new(std::fs::File::create(&actual_path).unwrap());
+        write_long_triangle(&mut ostream, ',', &dists, &names).unwrap();
+        drop(ostream);
 
-pub trait ToTable {
-    fn to_csv(
-        &self,
-        ostream: &mut Box<dyn Write>,
-        delimiter: char,
-        column_names: &[Vec<u8>],
-        row_names: &[Vec<u8>],
-    ) -> Result<(), Error>;
-    fn to_long(
-        &self,
-        ostream: &mut Box<dyn Write>,
-        delimiter: char,
-        column_names: &[Vec<u8>],
-        row_names: &[Vec<u8>],
-    ) -> Result<(), Error>;
-}
+        assert_eq!(
+            std::fs::read(actual_path).unwrap(),
+            std::fs::read(expected_path).unwrap()
+        )
+    }
 
-impl ToTable for ArrayBase<OwnedRepr<u64>, Ix2> {
-    fn to_csv(
-        &self,
-        ostream: &mut Box<dyn Write>,
-        delimiter: char,
-        column_names: &[Vec<u8>],
-        row_names: &[Vec<u8>],
-    ) -> Result<(), Error> {
-        // write empty top-left corner cell
-        write!(ostream, "{}", delimiter)?;
-        let header = column_names
-            .iter()
-            .map(|x| String::from_utf8_lossy(x))
-            .collect::<Vec<_>>()
-            .join(&delimiter.to_string());
-        writeln!(ostream, "{}", header)?;
+    #[test]
+    fn read_delimited_matrix_with_quoted_names() {
+        let data = b",s1,\"a,\"\"b\"\"\"\ns1,,3\n\"a,\"\"b\"\"\",3,\n";
 
-        for (row_idx, row_name) in row_names.iter().enumerate() {
-            write!(ostream, "{}", String::from_utf8_lossy(row_name))?;
-            let row = self.row(row_idx);
-            let s = row.iter().fold(String::new(), |mut output, x| {
-                let _ = write!(output, "{}{}", delimiter, x);
-                output
-            });
-            writeln!(ostream, "{}", s)?;
-        }
-        Ok(())
+        let actual = read_delimited_matrix(&data[..], ',').unwrap();
+
+        assert_eq!(
+            actual.column_names,
+            vec![b"s1".to_vec(), b"a,\"b\"".to_vec()]
+        );
+        assert_eq!(actual.row_names, actual.column_names);
+        assert_eq!(actual.matrix, ndarray::arr2(&[[0, 3], [3, 0]]));
     }
 
-    fn to_long(
-        &self,
-        ostream: &mut Box<dyn Write>,
-        delimiter: char,
-        column_names: &[Vec<u8>],
-        row_names: &[Vec<u8>],
-    ) -> Result<(), Error> {
-        for (i, j) in iproduct!(0..column_names.len(), 0..row_names.len()) {
-            let dist = self[[j, i]];
-            let c_name = &column_names[i];
-            let r_name = &row_names[j];
-            writeln!(
-                ostream,
-                "{}{d}{}{d}{}",
-                String::from_utf8_lossy(c_name),
-                String::from_utf8_lossy(r_name),
-                dist,
-                d = delimiter
-            )?;
-        }
-        Ok(())
+    #[test]
+    fn read_delimited_matrix_without_corner() {
+        let data = b"s1\ts2\ns1\t0\t1\ns2\t1\t0\n";
+
+        let actual = read_delimited_matrix(&data[..], '\t').unwrap();
+
+        assert_eq!(actual.column_names, vec![b"s1".to_vec(), b"s2".to_vec()]);
+        assert_eq!(actual.matrix, ndarray::arr2(&[[0, 1], [1, 0]]));
     }
-}
 
-#[cfg(test)]
-mod tests {
+    #[test]
+    fn read_delimited_matrix_with_wrong_number_of_distances() {
+        let data = b",s1,s2\ns1,0,1\ns2,1\n";
 
-    use super::*;
+        let actual = read_delimited_matrix(&data[..], ',').unwrap_err();
+
+        assert!(actual
+            .to_string()
+            .contains("Expected 2 distances on line 3"))
+    }
 
     #[test]
-    fn parse_chars() {
-        let s = "N-X";
+    fn add_substitutions_counts_ordered_pairs() {
+        let mut spectrum = Spectrum::new();
 
-        let actual = parse_ignored_chars(s);
-        let expected: HashSet<u8> = HashSet::from_iter([b'N', b'-', b'X']);
+        add_substitutions(&mut spectrum, b"ACG.TA", b"GCTAAA");
+        add_substitutions(&mut spectrum, b"A", b"G");
 
-        assert_eq!(actual, expected)
+        let expected = Spectrum::from([((b'A', b'G'), 2), ((b'G', b'T'), 1), ((b'T', b'A'), 1)]);
+        assert_eq!(spectrum, expected)
     }
 
     #[test]
-    fn alignments_all_have_same_length() {
-        let data = b">s1\nACGT\n>s0\nCCCC\n";
-        let mut reader = fasta::Reader::new(&data[..]);
-        let t: Transformer = Default::default();
+    fn quote_field_when_needed() {
+        assert_eq!(quote_field(b"s1", ','), "s1");
+        assert_eq!(quote_field(b"a,b", ','), "\"a,b\"");
+        assert_eq!(quote_field(b"a,b", '\t'), "a,b");
+        assert_eq!(quote_field(b"say \"hi\"", ','), "\"say \"\"hi\"\"\"")
+    }
 
-        let actual = t.load_alignment(&mut reader, 0).unwrap();
-        let expected = (
-            vec![b"s1".to_vec(), b"s0".to_vec()],
-            vec![b"ACGT".to_vec(), b"CCCC".to_vec()],
+    #[test]
+    fn reverse_complement_keeps_case_and_gaps() {
+        assert_eq!(reverse_complement(b"ACGTn-R"), b"Y-nACGT".to_vec());
+        assert_eq!(
+            reverse_complement(&[b'A', IGNORE, b'g']),
+            vec![b'c', IGNORE, b'T']
         );
+    }
+
+    #[test]
+    fn quote_r_always_quotes() {
+        assert_eq!(quote_r(b"s1"), "\"s1\"");
+        assert_eq!(quote_r(b"say \"hi\""), "\"say \"\"hi\"\"\"")
+    }
+
+    #[test]
+    fn count_complete_columns_and_compared_sites() {
+        let seqs = vec![
+            vec![b'A', IGNORE, b'G', b'T'],
+            vec![b'A', b'C', IGNORE, b'T'],
+        ];
+
+        assert_eq!(complete_columns(&seqs), 2);
+        assert_eq!(compared_sites(&seqs[0], &seqs[1]), 2);
+        assert_eq!(compared_sites(&seqs[0], &seqs[0]), 3)
+    }
+
+    #[test]
+    fn consensus_majority_rule() {
+        let seqs = vec![
+            vec![b'A', b'C', IGNORE, b'T', IGNORE],
+            vec![b'A', b'G', IGNORE, b'A', IGNORE],
+            vec![b'C', b'G', b'T', IGNORE, IGNORE],
+        ];
+
+        let actual = consensus(&seqs, false);
+        let expected = vec![b'A', b'G', b'T', b'A', IGNORE];
 
         assert_eq!(actual, expected)
     }
 
     #[test]
-    fn alignments_do_not_all_have_same_length() {
-        let data = b">s0\nACGT\n>s1\nCCCCC\n";
-        let mut reader = fasta::Reader::new(&data[..]);
-        let t: Transformer = Default::default();
+    fn ambiguous_consensus_without_strict_majority() {
+        let seqs = vec![
+            vec![b'A', b'A', b'C', b'A', IGNORE],
+            vec![b'A', b'G', b'G', b'-', IGNORE],
+            vec![b'C', b'G', b'T', b'-', IGNORE],
+            vec![b'A', b'A', b'K', IGNORE, IGNORE],
+        ];
 
-        let actual = t.load_alignment(&mut reader, 0).unwrap_err();
-        assert!(actual.to_string().contains("[id: s1]"))
+        let actual = consensus(&seqs, true);
+        let expected = vec![b'A', b'R', b'B', b'-', IGNORE];
+
+        assert_eq!(actual, expected)
     }
 
     #[test]
-    fn alignments_do_not_have_same_length_as_starting_seqlen() {
-        let data = b">s0\nACGT\n>s1\nCCCC\n";
-        let mut reader = fasta::Reader::new(&data[..]);
-        let t: Transformer = Default::default();
+    fn iupac_code_is_reverse_of_iupac_bases() {
+        for code in b"ACGTRYSWKMBDHVN" {
+            assert_eq!(iupac_code(iupac_bases(*code)), *code)
+        }
+    }
 
-        let actual = t.load_alignment(&mut reader, 1).unwrap_err();
-        assert!(actual.to_string().contains("[id: s0]"))
+    #[test]
+    fn protein_alignment_detected() {
+        let protein = vec![b"MKVLAAGIVG".to_vec(), b"MKVLSAGIEG".to_vec()];
+        let nucleotide = vec![b"ACGTNACGTR".to_vec(), b"acgt--acgt".to_vec()];
+
+        assert!(looks_like_protein(&protein));
+        assert!(!looks_like_protein(&nucleotide));
+        assert!(!looks_like_protein(&[]))
     }
 
     #[test]
-    fn alignments_sorted_by_id() {
-        let data = b">s10\nACGT\n>s51\nCCCC\n>s0\nGGCC\n";
-        let mut reader = fasta::Reader::new(&data[..]);
-        let t: Transformer = Transformer {
-            sort: true,
-            ..Default::default()
-        };
+    fn variable_sites_skip_ignored() {
+        let seqs = vec![
+            vec![b'A', b'C', IGNORE, b'T'],
+            vec![b'A', b'G', b'T', b'T'],
+            vec![b'A', b'G', b'A', IGNORE],
+        ];
+
+        let actual = variable_sites(&seqs);
+        let expected = vec![
+            (1, vec![(b'C', 1), (b'G', 2)]),
+            (2, vec![(b'A', 1), (b'T', 1)]),
+        ];
 
-        let actual = t.load_alignment(&mut reader, 0).unwrap();
-        let expected = (
-            vec![b"s0".to_vec(), b"s10".to_vec(), b"s51".to_vec()],
-            vec![b"GGCC".to_vec(), b"ACGT".to_vec(), b"CCCC".to_vec()],
-        );
         assert_eq!(actual, expected)
     }
 
@@ -319,6 +2711,21 @@ mod tests {
         assert_eq!(s, expected)
     }
 
+    #[test]
+    fn transform_rna() {
+        let mut s = b"aUcuT".to_vec();
+        let t = Transformer {
+            rna: true,
+            case_sensitive: true,
+            ..Default::default()
+        };
+
+        t.transform(&mut s);
+        let expected = b"aTctT".to_vec();
+
+        assert_eq!(s, expected)
+    }
+
     #[test]
     fn transform_ignore_chars() {
         let ignore = HashSet::from_iter(b"N-x".to_vec());
@@ -367,6 +2774,83 @@ mod tests {
         assert_eq!(s, expected)
     }
 
+    #[test]
+    fn format_float_with_precision() {
+        assert_eq!(format_float(Some(0.123456), 2), "0.12");
+        assert_eq!(format_float(None, 2), "NA");
+    }
+
+    #[test]
+    fn round_modes() {
+        assert_eq!(
+            format_float(Some(RoundMode::Nearest.round(0.126, 2)), 2),
+            "0.13"
+        );
+        assert_eq!(RoundMode::Floor.round(0.129, 2), 0.12);
+        assert_eq!(RoundMode::Floor.round(-0.121, 2), -0.13);
+        assert_eq!(RoundMode::Ceil.round(0.121, 2), 0.13);
+        assert_eq!(RoundMode::Truncate.round(-0.129, 2), -0.12);
+        // 0.29 is slightly less than 0.29 in binary
+        assert_eq!(RoundMode::Floor.round(0.29, 2), 0.29);
+    }
+
+    #[test]
+    fn test_iupac_distance() {
+        let a = b"ARNCY".to_vec();
+        let b = b"GACTT".to_vec();
+
+        let actual = iupac_distance(&a, &b);
+        let expected = 2;
+
+        assert_eq!(actual, expected)
+    }
+
+    #[test]
+    fn pair_stats_counts_soft_matches() {
+        let a = vec![b'A', b'R', IGNORE, b'C', b'Y', b'T'];
+        let b = vec![b'G', b'A', b'C', b'C', b'T', b'W'];
+
+        let actual = pair_stats(&a, &b, true, false);
+        let expected = PairStats {
+            snps: 1,
+            sites: 5,
+            soft_matches: 3,
+            ..Default::default()
+        };
+
+        assert_eq!(actual, expected);
+        assert_eq!(pair_stats(&a, &b, false, false).snps, 4)
+    }
+
+    #[test]
+    fn pair_stats_counts_case_differences() {
+        let a = b"AcgTN".to_vec();
+        let b = b"aCgAn".to_vec();
+
+        let actual = pair_stats(&a, &b, false, true);
+
+        assert_eq!(actual.case_diffs, 3);
+        assert_eq!(actual.snps, 1);
+        assert_eq!(pair_stats(&a, &b, false, false).snps, 4)
+    }
+
+    #[test]
+    fn pair_stats_max_gap_run() {
+        let a = b"A--.-C--T".to_vec();
+        let b = b"A-.--CA-T".to_vec();
+
+        let actual = pair_stats(&a, &b, false, false);
+
+        assert_eq!(actual.max_gap_run, 4);
+        assert_eq!(actual.snps, 1)
+    }
+
+    #[test]
+    fn pair_stats_identity_with_no_sites() {
+        let stats = PairStats::default();
+        assert_eq!(stats.identity(), None)
+    }
+
     #[test]
     fn test_hamming_distance() {
         let a = vec![b'A', IGNORE, b't', b'C', b'-'];
@@ -1,27 +1,201 @@
 use itertools::{iproduct, Itertools};
-use ndarray::Array;
+use ndarray::{Array, Array2, Axis, ShapeBuilder};
 use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
 use std::ffi::OsStr;
+use std::fmt::Display;
 use std::fs::File;
-use std::io::{stdout, BufReader, BufWriter, Write};
-use std::path::PathBuf;
+use std::hash::{Hash, Hasher};
+use std::io::{stdout, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
-use anyhow::{Context, Result};
-use clap::Parser;
-use log::info;
-use log::LevelFilter;
+use anyhow::{bail, Context, Result};
+use clap::{ArgEnum, Parser};
+use log::{info, warn};
+use log::{Level, LevelFilter};
 use noodles_fasta as fasta;
-use psdm::{hamming_distance, ToTable, Transformer};
+#[cfg(feature = "hdf5")]
+use psdm::write_hdf5;
+use psdm::{
+    add_substitutions, base_composition, base_frequencies, bootstrap_weights, compact,
+    compared_sites, complete_columns, complete_deletion, consensus, dedup, differing_positions,
+    format_float, group_digits, group_means, hamming_distance, iupac_distance, mean_sd,
+    n_penalty_distance, neighbour_joining, non_singletons, outliers, pair_stats, pearson,
+    quantiles, quote_field, raw_distance, read_binary, read_delimited_matrix, reverse_complement,
+    single_linkage, subsample_indices, substitution_counts, triangle_indices, trim_flanks,
+    truncate_names, variable_sites, weighted_distance, write_binary, write_edgelist,
+    write_full_report, write_graphml, write_long_triangle, write_pairs, write_pairs_full_report,
+    write_sparse, Alleles, BinaryMatrix, Checkpoint, Fingerprint, Header, Model, NamesAndSeqs,
+    ReportColumns, RoundMode, Spectrum, ToTable, Transformer, BINARY_MAGIC, COMPOSITION_KINDS,
+};
 
 /// A utility function that allows the CLI to error if a path doesn't exist
 fn path_exists<S: AsRef<OsStr> + ?Sized>(s: &S) -> Result<PathBuf, String> {
     let path = PathBuf::from(s);
-    if path.exists() {
-        Ok(path)
-    } else {
-        Err(format!("{:?} does not exist", path))
+    #[cfg(feature = "url")]
+    if is_url(&path) {
+        return Ok(path);
+    }
+    // `/` is not a separator in verbatim (`\\?\`) paths, so they can only use `\`
+    #[cfg(windows)]
+    let path = match path.to_str() {
+        Some(p) if p.starts_with(r"\\?\") => PathBuf::from(p.replace('/', r"\")),
+        _ => path,
+    };
+    match path.try_exists() {
+        Ok(true) => Ok(path),
+        Ok(false) => Err(format!("{} does not exist", path.display())),
+        Err(e) => Err(format!("Could not access {}: {}", path.display(), e)),
+    }
+}
+
+/// Whether the path is actually a http(s) URL
+#[cfg(feature = "url")]
+fn is_url(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|s| s.starts_with("http://") || s.starts_with("https://"))
+}
+
+/// Open a (possibly compressed) alignment file, or URL if the `url` feature is enabled
+fn open_alignment(path: &Path) -> Result<Box<dyn Read>> {
+    #[cfg(feature = "url")]
+    if is_url(path) {
+        let url = path.to_string_lossy();
+        let response = ureq::get(&url)
+            .call()
+            .with_context(|| format!("Failed to fetch {}", url))?;
+        let (reader, _) = niffler::get_reader(Box::new(response.into_reader()))?;
+        return Ok(reader);
+    }
+    let (reader, _) = niffler::from_path(path)?;
+    Ok(reader)
+}
+
+/// Parse pairs of sequence IDs - one whitespace-separated pair per line - into indices. The first
+/// ID of each pair is looked up in `column_names` and the second in `row_names`.
+fn parse_pairs<R: BufRead>(
+    reader: R,
+    column_names: &[Vec<u8>],
+    row_names: &[Vec<u8>],
+) -> Result<Vec<Vec<usize>>> {
+    let index = |names: &[Vec<u8>]| -> HashMap<Vec<u8>, usize> {
+        let mut idx = HashMap::new();
+        for (i, n) in names.iter().enumerate() {
+            idx.entry(n.to_owned()).or_insert(i);
+        }
+        idx
+    };
+    let column_idx = index(column_names);
+    let row_idx = index(row_names);
+
+    let mut pairs = vec![];
+    for (lineno, line) in reader.lines().enumerate() {
+        let line = line?;
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            [] => continue,
+            [a, b] => {
+                let i = column_idx
+                    .get(a.as_bytes())
+                    .with_context(|| format!("Unknown sequence ID {} on line {}", a, lineno + 1))?;
+                let j = row_idx
+                    .get(b.as_bytes())
+                    .with_context(|| format!("Unknown sequence ID {} on line {}", b, lineno + 1))?;
+                pairs.push(vec![*i, *j]);
+            }
+            _ => bail!("Expected two IDs on line {} of pairs file", lineno + 1),
+        }
+    }
+    Ok(pairs)
+}
+
+/// Parse sample weights - one whitespace-separated ID and weight per line - into a weight for each
+/// of `names`. Sequences without a weight have a weight of 1.
+fn parse_weights<R: BufRead>(reader: R, names: &[Vec<u8>]) -> Result<Vec<f64>> {
+    let mut weights_by_id: HashMap<Vec<u8>, f64> = HashMap::new();
+    for (lineno, line) in reader.lines().enumerate() {
+        let line = line?;
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            [] => continue,
+            [id, weight] => {
+                let weight: f64 = weight
+                    .parse()
+                    .ok()
+                    .filter(|w: &f64| *w >= 0.0)
+                    .with_context(|| format!("Invalid weight {} on line {}", weight, lineno + 1))?;
+                weights_by_id.insert(id.as_bytes().to_vec(), weight);
+            }
+            _ => bail!(
+                "Expected an ID and weight on line {} of weights file",
+                lineno + 1
+            ),
+        }
+    }
+    let unknown = weights_by_id
+        .keys()
+        .filter(|id| !names.contains(id))
+        .count();
+    if unknown > 0 {
+        warn!(
+            "{} ID(s) in the weights file are not in the alignment",
+            unknown
+        );
+    }
+    Ok(names
+        .iter()
+        .map(|n| weights_by_id.get(n).copied().unwrap_or(1.0))
+        .collect())
+}
+
+/// Group names and the index of each ID's group
+type Groups = (Vec<Vec<u8>>, HashMap<Vec<u8>, usize>);
+
+/// Parse groups - one whitespace-separated ID and group name per line. Returns the group names, in
+/// the order they first appear, and the index of each ID's group.
+fn parse_groups<R: BufRead>(reader: R) -> Result<Groups> {
+    let mut group_names: Vec<Vec<u8>> = vec![];
+    let mut groups_by_id: HashMap<Vec<u8>, usize> = HashMap::new();
+    for (lineno, line) in reader.lines().enumerate() {
+        let line = line?;
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            [] => continue,
+            [id, group] => {
+                let group = group.as_bytes().to_vec();
+                let idx = match group_names.iter().position(|g| *g == group) {
+                    Some(idx) => idx,
+                    None => {
+                        group_names.push(group);
+                        group_names.len() - 1
+                    }
+                };
+                groups_by_id.insert(id.as_bytes().to_vec(), idx);
+            }
+            _ => bail!(
+                "Expected an ID and group on line {} of groups file",
+                lineno + 1
+            ),
+        }
+    }
+    Ok((group_names, groups_by_id))
+}
+
+/// Whether two paths point to the same file
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+fn parse_fraction(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(x) if (0.0..=1.0).contains(&x) => Ok(x),
+        _ => Err(format!("Expected a number between 0 and 1, got {}", s)),
     }
 }
 
@@ -40,6 +214,246 @@ fn parse_delim(s: &str) -> Result<char, String> {
     }
 }
 
+/// The number of pairs computed between checkpoints
+const CHECKPOINT_CHUNK_SIZE: usize = 100_000;
+
+/// The maximum number of sequences (on either axis) rendered by `--pretty`
+const PRETTY_MAX_SEQS: usize = 100;
+
+/// Line terminator for the output
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum LineEnding {
+    Lf,
+    Crlf,
+}
+
+/// Converts every `\n` written to it into `\r\n`
+struct CrlfWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> Write for CrlfWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut lines = buf.split(|b| *b == b'\n');
+        if let Some(first) = lines.next() {
+            self.inner.write_all(first)?;
+        }
+        for line in lines {
+            self.inner.write_all(b"\r\n")?;
+            self.inner.write_all(line)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Flushes the inner writer after every `every` lines written to it
+struct FlushingWriter<W: Write> {
+    inner: W,
+    every: usize,
+    lines: usize,
+}
+
+impl<W: Write> Write for FlushingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.inner.write_all(buf)?;
+        self.lines += buf.iter().filter(|b| **b == b'\n').count();
+        if self.lines >= self.every {
+            self.lines = 0;
+            self.inner.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Compression format for the output
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputCompression {
+    Gzip,
+    Zstd,
+    Bzip2,
+    Xz,
+    None,
+}
+
+impl OutputCompression {
+    /// Guess the compression format from the extension of the path
+    fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(OsStr::to_str) {
+            Some("gz") => OutputCompression::Gzip,
+            Some("zst") => OutputCompression::Zstd,
+            Some("bz2") => OutputCompression::Bzip2,
+            Some("xz") => OutputCompression::Xz,
+            _ => OutputCompression::None,
+        }
+    }
+
+    /// The file extension for the compression format, if any
+    fn extension(&self) -> Option<&'static str> {
+        match self {
+            OutputCompression::Gzip => Some("gz"),
+            OutputCompression::Zstd => Some("zst"),
+            OutputCompression::Bzip2 => Some("bz2"),
+            OutputCompression::Xz => Some("xz"),
+            OutputCompression::None => None,
+        }
+    }
+}
+
+impl From<OutputCompression> for niffler::compression::Format {
+    fn from(c: OutputCompression) -> Self {
+        match c {
+            OutputCompression::Gzip => niffler::compression::Format::Gzip,
+            OutputCompression::Zstd => niffler::compression::Format::Zstd,
+            OutputCompression::Bzip2 => niffler::compression::Format::Bzip,
+            OutputCompression::Xz => niffler::compression::Format::Lzma,
+            OutputCompression::None => niffler::compression::Format::No,
+        }
+    }
+}
+
+/// The deflate level of an HDF5 dataset for the output compression level
+#[cfg(feature = "hdf5")]
+fn hdf5_level(level: niffler::Level) -> u8 {
+    match level {
+        niffler::Level::One => 1,
+        niffler::Level::Two => 2,
+        niffler::Level::Three => 3,
+        niffler::Level::Four => 4,
+        niffler::Level::Five => 5,
+        niffler::Level::Six => 6,
+        niffler::Level::Seven => 7,
+        niffler::Level::Eight => 8,
+        _ => 9,
+    }
+}
+
+fn parse_compression_level(s: &str) -> Result<niffler::Level, String> {
+    let level = match s.parse::<u8>() {
+        Ok(1) => niffler::Level::One,
+        Ok(2) => niffler::Level::Two,
+        Ok(3) => niffler::Level::Three,
+        Ok(4) => niffler::Level::Four,
+        Ok(5) => niffler::Level::Five,
+        Ok(6) => niffler::Level::Six,
+        Ok(7) => niffler::Level::Seven,
+        Ok(8) => niffler::Level::Eight,
+        Ok(9) => niffler::Level::Nine,
+        _ => return Err(format!("Compression level must be 1-9, got {}", s)),
+    };
+    Ok(level)
+}
+
+/// Format of the input alignment(s)
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum InputFormat {
+    Fasta,
+    /// Relaxed PHYLIP - sequential or interleaved
+    Phylip,
+}
+
+impl InputFormat {
+    /// Infer the format from the file extension (ignoring any compression extension). Anything
+    /// that isn't a PHYLIP extension is assumed to be FASTA.
+    fn from_path(path: &Path) -> Self {
+        let mut path = path.to_path_buf();
+        if let Some("gz" | "zst" | "bz2" | "xz") = path.extension().and_then(|e| e.to_str()) {
+            path.set_extension("");
+        }
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("phy" | "phylip") => InputFormat::Phylip,
+            _ => InputFormat::Fasta,
+        }
+    }
+}
+
+/// The file name of an alignment without its compression and format extensions
+fn alignment_stem(path: &Path) -> String {
+    let mut name = PathBuf::from(path.file_name().unwrap_or_default());
+    if let Some("gz" | "zst" | "bz2" | "xz") = name.extension().and_then(|e| e.to_str()) {
+        name.set_extension("");
+    }
+    name.set_extension("");
+    name.to_string_lossy().into_owned()
+}
+
+/// Load an alignment in the given format
+fn load_alignment<R: BufRead>(
+    transformer: &Transformer,
+    reader: R,
+    format: InputFormat,
+    starting_seqlen: usize,
+) -> Result<NamesAndSeqs> {
+    match format {
+        InputFormat::Fasta => {
+            transformer.load_alignment(&mut fasta::Reader::new(reader), starting_seqlen)
+        }
+        InputFormat::Phylip => transformer.load_phylip(reader, starting_seqlen),
+    }
+}
+
+/// Layout of the output table
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    /// N x N or N x M matrix
+    Matrix,
+    /// Upper triangle, one distance per line, in the order of scipy's `pdist`
+    Condensed,
+    /// Compact, self-describing binary matrix (with names) that psdm can read back
+    Bin,
+    /// Weighted edge list (source, target, weight) for network tools
+    Edgelist,
+    /// GraphML graph for network tools - e.g., Cytoscape or Gephi
+    Graphml,
+    /// Sparse (row index, column index, distance) triplets with the names in a sidecar file
+    Sparse,
+    /// Matrix with every name quoted, for R's `read.csv(row.names = 1, check.names = FALSE)`
+    R,
+    /// HDF5 file with the matrix and names as datasets (needs the `hdf5` feature)
+    Hdf5,
+}
+
+impl OutputFormat {
+    /// Whether the format is only available for the raw SNP distance
+    fn snps_only(&self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Bin
+                | OutputFormat::Edgelist
+                | OutputFormat::Graphml
+                | OutputFormat::Sparse
+                | OutputFormat::Hdf5
+        )
+    }
+}
+
+/// What to report for each pair of sequences
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Report {
+    /// Only the SNP distance
+    Snps,
+    /// SNP distance, number of compared sites, and percent identity (long-form only)
+    Full,
+}
+
+/// The denominator of the normalized (proportion) distance
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum NormalizeBy {
+    /// Sites compared between the pair - i.e., where neither sequence is ignored
+    Compared,
+    /// Alignment length
+    Total,
+    /// Non-ignored sites of whichever sequence in the pair has fewer
+    MinPresent,
+}
+
 /// Compute a pairwise SNP distance matrix from one or two alignment(s)
 #[derive(Parser, Debug)]
 #[clap(author, version, about, verbatim_doc_comment)]
@@ -48,15 +462,115 @@ struct Opt {
     ///
     /// Providing two files will compute the distances for all sequences in one file against all
     /// sequences from the other file - i.e., not between sequences in the same file. The first
-    /// file will be the column names, while the second is the row names.
-    /// The alignment file(s) can be compressed.
-    #[clap(min_values = 1, max_values = 2, parse(try_from_os_str = path_exists))]
+    /// file will be the column names, while the second is the row names. Providing the same file
+    /// twice is treated as providing it once.
+    /// The alignment file(s) can be compressed. PHYLIP alignments are also supported - see
+    /// `--format-in`. If psdm was built with the `url` feature, http(s)
+    /// URLs are also accepted. More than two files can be given with `--all-pairs`.
+    /// Each file is read once from start to end, so pipes (e.g., `<(zcat aln.fa.gz)`) work too,
+    /// but as they have no extension, PHYLIP input through a pipe needs `--format-in`.
+    #[clap(
+        min_values = 1,
+        required_unless_present_any = &[
+            "version-json", "from-matrix", "compare-matrices", "self-test"
+        ],
+        parse(try_from_os_str = path_exists)
+    )]
     alignments: Vec<PathBuf>,
 
-    /// Output file name [default: stdout]
+    /// Read a precomputed distance matrix instead of computing one from alignment(s)
+    ///
+    /// The matrix can be psdm's binary format or a delimited matrix with a header (using
+    /// `--delim`), and can be compressed. The distances are then written in the chosen output
+    /// format, and `--quantiles`, `--outliers`, `--assert-max`, and `--cluster` apply to them.
+    #[clap(
+        long,
+        value_name = "FILE",
+        parse(try_from_os_str = path_exists),
+        conflicts_with_all = &[
+            "alignments", "also-normalized", "adjust-missing", "n-penalty", "pairs-file", "dedup",
+            "groups", "within-groups", "drop-singletons", "checkpoint", "vs-consensus", "panel",
+            "explain", "count-sites-only", "shape"
+        ]
+    )]
+    from_matrix: Option<PathBuf>,
+
+    /// Compare two precomputed distance matrices (e.g., from different options) cell by cell
+    ///
+    /// The matrices are read as for `--from-matrix` and must have the same sequences, in any
+    /// order. Summary statistics of the differences are printed to stderr and the pairs whose
+    /// distance changed are written as a table, largest absolute difference first.
+    #[clap(
+        long,
+        number_of_values = 2,
+        value_names = &["A", "B"],
+        parse(try_from_os_str = path_exists),
+        conflicts_with_all = &["alignments", "from-matrix", "also-normalized"]
+    )]
+    compare_matrices: Vec<PathBuf>,
+
+    /// Format of the alignment file(s) [default: inferred from the file extension]
+    ///
+    /// Files ending in `.phy` or `.phylip` (optionally compressed) are read as PHYLIP, anything
+    /// else as FASTA.
+    #[clap(long, arg_enum)]
+    format_in: Option<InputFormat>,
+
+    /// Output file name, or `-` for stdout [default: stdout]
+    ///
+    /// A path ending in a `/` is treated as a directory (created if needed) and the output is
+    /// written to a file in it named after the output format - e.g., `distances.csv`,
+    /// `distances.long.tsv`, or `distances.bin`.
+    ///
+    /// This only routes the main table. Side outputs (e.g., `--variant-report` or `--spectrum`)
+    /// always go to their own path, which can also be `-` to write them to stdout instead.
     #[clap(short, long, parse(from_os_str))]
     output: Option<PathBuf>,
 
+    /// Compute the inter-alignment distances for every pair of alignment files
+    ///
+    /// Each pair's matrix is written to `<output>.<a>_vs_<b>.csv` (or `.tsv` for tab-delimited
+    /// output), where `<a>` and `<b>` are the file names without extensions. Useful for comparing
+    /// multiple loci.
+    #[clap(
+        long,
+        requires = "output",
+        conflicts_with_all = &["pairs-file", "checkpoint", "dedup", "groups", "panel", "explain", "vs-consensus"]
+    )]
+    all_pairs: bool,
+
+    /// Capacity (in bytes) of the write buffer used when writing to an output file
+    ///
+    /// Larger buffers can help when writing large (e.g., long-form) outputs to network
+    /// filesystems. Defaults to the standard library's default capacity.
+    #[clap(long, value_name = "BYTES")]
+    buffer_size: Option<usize>,
+
+    /// Flush the output file after every N lines
+    ///
+    /// By default, output is only written to the file when the write buffer is full, so a long
+    /// (e.g., long-form) output can't be followed with `tail -f` while psdm is running.
+    #[clap(long, value_name = "N", requires = "output")]
+    flush_interval: Option<usize>,
+
+    /// Write output files to a temporary file next to them and only move them into place once
+    /// psdm has finished successfully
+    ///
+    /// Prevents tools watching the output path from reading a partially-written file after a
+    /// crash or kill.
+    #[clap(long, requires = "output")]
+    atomic_output: bool,
+
+    /// Compression format for the output [default: inferred from the output file extension -
+    /// `.gz`, `.zst`, `.bz2`, or `.xz`]
+    #[clap(long, arg_enum)]
+    output_compression: Option<OutputCompression>,
+
+    /// Compression level (1-9) for compressed output, including the HDF5 matrix. Higher is smaller,
+    /// but slower
+    #[clap(long, default_value = "6", parse(try_from_str = parse_compression_level))]
+    compression_level: niffler::Level,
+
     /// Number of threads to use. Setting to 0 will use all available
     #[clap(short, long, default_value = "1")]
     threads: usize,
@@ -68,24 +582,2025 @@ struct Opt {
     #[clap(short, long = "long")]
     long_form: bool,
 
+    /// Layout of the output table
+    ///
+    /// `condensed` is only available for a single alignment and writes the upper triangle of the
+    /// matrix (excluding the diagonal) one distance per line - i.e., the input expected by scipy's
+    /// `squareform`. `bin` writes the full matrix and names in psdm's binary format, which avoids
+    /// lossy round-trips through text for large matrices. `edgelist` and `graphml` write the pairs
+    /// (within `--max-dist`) as a graph for network tools. `sparse` writes the pairs (within
+    /// `--max-dist`) as 0-based (row, column, distance) triplets, and requires `--output` as the
+    /// names are written to `<output>.names.txt` (or `<output>.row_names.txt` and
+    /// `<output>.col_names.txt` for two alignments), one per line. For a single alignment, network
+    /// and sparse formats only include each pair once. `hdf5` writes the matrix and names as the
+    /// `matrix` and `names` (or `column_names` and `row_names`) datasets of the `--output` file,
+    /// and needs psdm to be built with the `hdf5` feature. `bin`, `edgelist`, `graphml`, `sparse`,
+    /// and `hdf5` are only available for the raw SNP distance. `--long` takes precedence over this
+    /// option.
+    #[clap(long, arg_enum, default_value = "matrix")]
+    format: OutputFormat,
+
+    /// Only output pairs with a distance of at most N (network and sparse formats only)
+    #[clap(long, value_name = "N")]
+    max_dist: Option<u64>,
+
+    /// Include the diagonal in the condensed output
+    #[clap(long)]
+    include_diagonal: bool,
+
+    /// Write the matrix as an aligned, human-readable table
+    ///
+    /// Only used for matrices with at most 100 sequences on each axis; larger matrices are written
+    /// as normal. Useful for exploring small alignments in the terminal.
+    #[clap(long, conflicts_with_all = &["long-form", "format"])]
+    pretty: bool,
+
+    /// Truncate names to at most N characters (and pad row names to N) in fixed-width output
+    ///
+    /// A warning is given if any names are truncated, and an error if the truncated names are no
+    /// longer unique.
+    #[clap(long, value_name = "N", requires = "pretty")]
+    name_width: Option<usize>,
+
+    /// Write SNP distances with their digits grouped in threes - e.g., 1,234,567
+    ///
+    /// Only applies to the raw SNP distance in the matrix, long-form, and condensed formats.
+    #[clap(long, conflicts_with = "drop-singletons")]
+    group_digits: bool,
+
+    /// Character to group digits with `--group-digits` [default: `,`, or `_` if the delimiter is
+    /// `,`]
+    ///
+    /// This cannot be the same as the delimiter, as the fields would then be ambiguous.
+    #[clap(long, value_name = "CHAR", requires = "group-digits", parse(try_from_str=parse_delim))]
+    group_separator: Option<char>,
+
     /// Delimiting character for the output table
     #[clap(short, long = "delim", default_value = ",", parse(try_from_str=parse_delim))]
     delimiter: char,
 
+    /// Line terminator for the output table - e.g., `crlf` for some Windows software
+    #[clap(long, arg_enum, default_value = "lf")]
+    line_ending: LineEnding,
+
+    /// Leave the diagonal cells of the matrix empty instead of writing 0
+    ///
+    /// Only applies to the (default) matrix output for a single alignment.
+    #[clap(long)]
+    blank_diagonal: bool,
+
+    /// Don't write the header row (column names) of the matrix
+    ///
+    /// Useful for appending to an existing file. Only applies to the (default) matrix output, as
+    /// the long-form table has no header.
+    #[clap(long)]
+    no_header: bool,
+
+    /// Don't write the empty top-left corner cell of the matrix header
+    ///
+    /// The header then starts with the first column name, which some matrix parsers expect. Only
+    /// applies to the (default) matrix output.
+    #[clap(long, conflicts_with = "no-header")]
+    no_corner: bool,
+
+    /// Only compute distances for the pairs of sequence IDs in this file (implies `--long`)
+    ///
+    /// Each line should contain two whitespace-separated IDs. If two alignments are given, the
+    /// first ID is from the first alignment and the second ID from the second alignment. The file
+    /// can be compressed.
+    #[clap(long, parse(try_from_os_str = path_exists))]
+    pairs_file: Option<PathBuf>,
+
+    /// Drop sequences whose closest other sequence is more than MAXDIST SNPs away
+    ///
+    /// Only available for a single alignment. Useful for pruning outbreak datasets to sequences
+    /// with close relatives.
+    #[clap(long, value_name = "MAXDIST", conflicts_with = "pairs-file")]
+    drop_singletons: Option<u64>,
+
+    /// Print the N sequences with the highest mean distance to all others to stderr
+    ///
+    /// Sequences (rows of the matrix) are ranked by their mean SNP distance, excluding the distance
+    /// to themselves. Useful for flagging contamination or misassemblies.
+    #[clap(long, value_name = "N", conflicts_with = "pairs-file")]
+    outliers: Option<usize>,
+
+    /// Exit with an error if any distance (other than a sequence to itself) is more than N SNPs
+    ///
+    /// A sanity check for pipelines where all sequences are expected to be closely related - a
+    /// large distance is often a sign of contamination or the wrong input file.
+    #[clap(long, value_name = "N")]
+    assert_max: Option<u64>,
+
+    /// Print quantiles (min, 25%, median, 75%, 95%, max) of the distances to stderr
+    ///
+    /// The distance of a sequence to itself is not included. Quantiles are exact and computed from
+    /// a histogram of the distances, so no sorted copy is needed.
+    #[clap(long)]
+    quantiles: bool,
+
+    /// Weights for sequences used in summary statistics (i.e., `--outliers`)
+    ///
+    /// Each line should contain a whitespace-separated ID and (non-negative) weight. Sequences not
+    /// in the file have a weight of 1. Useful for down-weighting technical replicates. The distance
+    /// matrix itself is not affected.
+    #[clap(long, parse(try_from_os_str = path_exists), value_name = "FILE", requires = "outliers")]
+    sample_weights: Option<PathBuf>,
+
+    /// Write a group-by-group matrix of mean pairwise SNP distances instead of the full matrix
+    ///
+    /// Each line of FILE should contain a whitespace-separated ID and group name. The distance
+    /// between two groups is the mean distance over all pairs of sequences between them (excluding
+    /// the distance of a sequence to itself). Sequences not in the file are left out.
+    #[clap(long, parse(try_from_os_str = path_exists), value_name = "FILE", conflicts_with = "pairs-file")]
+    groups: Option<PathBuf>,
+
+    /// Only compute distances between sequences in the same group
+    ///
+    /// Each line of FILE should contain a whitespace-separated ID and group name (as for
+    /// `--groups`). Writes a long-form table with the columns: group, column name, row name, and
+    /// SNP distance. Sequences not in the file are left out.
+    #[clap(
+        long,
+        parse(try_from_os_str = path_exists),
+        value_name = "FILE",
+        conflicts_with_all = &["pairs-file", "groups", "dedup", "drop-singletons", "outliers"]
+    )]
+    within_groups: Option<PathBuf>,
+
+    /// What to report for each pair of sequences
+    ///
+    /// `full` writes a long-form table with the columns: column name, row name, SNP distance,
+    /// number of compared (non-ignored) sites, and percent identity (NA if no sites were
+    /// compared). With `--iupac`, an extra column with the number of positions that only matched
+    /// due to ambiguity compatibility is added. With `--gap-runs`, an extra column with the longest
+    /// run of positions where both sequences are gaps is added. With `--case-aware`, an extra
+    /// (final) column with the number of case-only differences is added.
+    #[clap(long, arg_enum, default_value = "snps")]
+    report: Report,
+
+    /// Add the longest run of positions where both sequences are gaps to the full report
+    ///
+    /// As gaps are ignored by default, any ignored character is also considered a gap. Useful for
+    /// checking whether a pair's similarity is driven by large shared gaps.
+    #[clap(long)]
+    gap_runs: bool,
+
+    /// Count differences in case only (e.g., a and A) separately from SNPs in the full report
+    ///
+    /// For alignments where case encodes extra information - e.g., phase or strand. Requires
+    /// `--case-sensitive`.
+    #[clap(long, requires = "case-sensitive")]
+    case_aware: bool,
+
+    /// Distance model to use
+    ///
+    /// All models except `raw` only consider positions where both sequences have an A, C, G, or T
+    /// and are written as floats (NA if the distance is undefined). `tn93` uses base frequencies
+    /// estimated from all sequences.
+    ///
+    /// Most other ways of computing or writing distances only apply to `raw`: `--iupac`,
+    /// `--also-normalized`, `--adjust-missing`, `--n-penalty`, `--bootstrap`, `--all-pairs`,
+    /// `--expand`, `--checkpoint`, `--drop-singletons`, `--groups`, `--within-groups`,
+    /// `--assert-max`, `--quantiles`, `--from-matrix`, `--tree`, `--orient`, `--compact`,
+    /// `--outliers`, `--cluster`, `--vs-consensus`, `--panel`, and the SNP-only `--format`s.
+    #[clap(short, long, arg_enum, default_value = "raw")]
+    model: Model,
+
+    /// Number of decimal places for floating point distances
+    #[clap(long, default_value = "6")]
+    precision: usize,
+
+    /// How to round floating point distances to `--precision` decimal places
+    #[clap(long, arg_enum, default_value = "nearest")]
+    round_mode: RoundMode,
+
+    /// Number of decimal places for the percent identity in the full report
+    #[clap(long, value_name = "N", default_value = "4")]
+    identity_precision: usize,
+
+    /// Output each sequence's distance to the majority-rule consensus of the alignment
+    ///
+    /// Writes a two-column table of sequence name and distance. Ignored positions do not count
+    /// towards the consensus. Only available for a single alignment.
+    #[clap(long)]
+    vs_consensus: bool,
+
+    /// Use an IUPAC ambiguity code in the consensus for columns without a strict majority
+    ///
+    /// The code represents all of the bases observed in the column - e.g., R for a column with
+    /// two As and two Gs. Columns with non-nucleotide characters keep the majority-rule character.
+    /// Combine with `--iupac` so that a base is not a difference from a compatible code.
+    #[clap(long, requires = "vs-consensus")]
+    ambiguous_consensus: bool,
+
+    /// Output each sequence's distance to its nearest sequence in a reference panel alignment
+    ///
+    /// Writes a three-column table of sequence name, name of the nearest panel sequence, and
+    /// distance. Ties go to the panel sequence that comes first. The panel must be aligned to the
+    /// alignment. Only available for a single alignment. Useful for rapid lineage assignment.
+    #[clap(
+        long,
+        parse(try_from_os_str = path_exists),
+        value_name = "FILE",
+        conflicts_with_all = &["vs-consensus", "complete-deletion"]
+    )]
+    panel: Option<PathBuf>,
+
+    /// List every position where two sequences differ, instead of computing distances
+    ///
+    /// Takes the two names separated by a comma. If two alignments are given, the first name is
+    /// from the first alignment and the second from the second alignment. Writes a tab-delimited
+    /// table of the position (see `--zero-based`) and the character of each sequence. Positions
+    /// are relative to the compared sites - i.e., after `--stride` and `--complete-deletion` are
+    /// applied.
+    #[clap(
+        long,
+        value_name = "A,B",
+        conflicts_with_all = &["vs-consensus", "panel"]
+    )]
+    explain: Option<String>,
+
+    /// Only count the sites that would be compared, without computing distances
+    ///
+    /// Writes a two-column table with the alignment length, the number of columns with no ignored
+    /// characters, the number of pairs, and quantiles of the number of compared (non-ignored)
+    /// sites across pairs. A fast check of whether missing data will undermine the analysis.
+    #[clap(long, conflicts_with_all = &["vs-consensus", "panel", "explain"])]
+    count_sites_only: bool,
+
+    /// Print the shape of the distance matrix and exit without computing distances
+    ///
+    /// Writes the number of rows and columns and whether the distances are intra- or
+    /// inter-alignment as `rows<TAB>cols<TAB>intra|inter`.
+    #[clap(
+        long,
+        conflicts_with_all = &["vs-consensus", "panel", "explain", "count-sites-only"]
+    )]
+    shape: bool,
+
+    /// Combine two alignments into one and compute intra-alignment distances
+    ///
+    /// Sequences whose ID is in both alignments are only included once (from the first alignment),
+    /// giving a symmetric matrix with a zero diagonal over all samples. Without this option, a
+    /// warning is given when the alignments share IDs.
+    #[clap(long)]
+    intra_from_pair: bool,
+
+    /// Cluster the sequences (single-linkage) where a pair is linked if at most N SNPs apart
+    ///
+    /// Writes a `sequence` and `cluster` table, with clusters numbered from 0, instead of the
+    /// matrix. Only the pairs within N SNPs are kept in memory. Only available for a single
+    /// alignment.
+    #[clap(
+        long,
+        value_name = "N",
+        conflicts_with_all = &["vs-consensus", "panel", "explain", "count-sites-only", "shape"]
+    )]
+    cluster: Option<u64>,
+
+    /// Remove any column where any sequence has an ignored character before computing distances
+    ///
+    /// By default, ignored positions are skipped pairwise - i.e., each pair is compared over the
+    /// sites where neither sequence is ignored. With this option ("complete deletion"), all pairs
+    /// are compared over the same set of sites. When two alignments are given, columns are
+    /// removed based on the sequences in both.
+    #[clap(long)]
+    complete_deletion: bool,
+
+    /// Remove the leading and trailing columns where every sequence has a gap or ignored character
+    ///
+    /// Ragged alignment ends add nothing to the distances, but inflate the number of sites used by
+    /// `--adjust-missing` and `--normalize-by total`. When two alignments are given, columns are
+    /// removed based on the sequences in both.
+    #[clap(long)]
+    trim: bool,
+
+    /// Only store the variable columns of the alignment(s) while computing distances
+    ///
+    /// Columns where all sequences have the same character (or an ignored one) never contribute to
+    /// a SNP distance, so dropping them gives the same distances using much less memory for mostly
+    /// invariant alignments.
+    #[clap(
+        long,
+        conflicts_with_all = &["panel", "explain", "count-sites-only"]
+    )]
+    compact: bool,
+
+    /// Collapse identical sequences into a single representative before computing distances
+    ///
+    /// Sequences are compared after transformation (e.g., case and ignored characters) and the
+    /// first occurrence of each distinct sequence is kept. This can massively reduce the number of
+    /// pairs for datasets with many duplicates. See `--dedup-map` and `--expand`.
+    #[clap(long, conflicts_with = "pairs-file")]
+    dedup: bool,
+
+    /// Write each sequence's name and the name of its representative to FILE
+    #[clap(long, value_name = "FILE", requires = "dedup")]
+    dedup_map: Option<PathBuf>,
+
+    /// Expand the deduplicated distances back to all sequences
+    ///
+    /// The output is the same as without `--dedup`, but only distances between distinct sequences
+    /// are computed.
+    #[clap(long, requires = "dedup")]
+    expand: bool,
+
+    /// Write a table of the variable sites (columns) of the alignment(s) to FILE
+    ///
+    /// Each line has the position (see `--zero-based`), the observed alleles, and the number of
+    /// sequences with each allele. Ignored characters are not alleles. Positions are relative to
+    /// the compared sites - i.e., after `--stride` and `--complete-deletion` are applied.
+    #[clap(long, value_name = "FILE")]
+    variant_report: Option<PathBuf>,
+
+    /// Write the number of each substitution type (e.g., A>G), summed over all pairs, to FILE
+    ///
+    /// The table has a row per "from" character and a column per "to" character. Substitutions are
+    /// from the first alignment to the second or, for a single alignment, from the earlier sequence
+    /// in the pair to the later one. With `--vs-consensus`, they are from the consensus to each
+    /// sequence. Ignored positions are not counted.
+    #[clap(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = &[
+            "panel", "explain", "count-sites-only", "shape", "cluster", "from-matrix", "dedup"
+        ]
+    )]
+    spectrum: Option<PathBuf>,
+
+    /// Write the counts and fractions of A, C, G, T, N, gaps, ignored, and other characters to
+    /// FILE
+    ///
+    /// These are counted over all sequences after `--ignored-chars` and the other transformations
+    /// are applied, so N and gaps are counted as ignored by default.
+    #[clap(long, value_name = "FILE")]
+    base_freqs: Option<PathBuf>,
+
+    /// Write the alignment length and the number of sites compared to FILE
+    ///
+    /// The alignment length is the number of columns read (after `--regions`, `--stride`, and
+    /// `--offset`), and the compared sites are those left after `--trim` and
+    /// `--complete-deletion`. These are needed to interpret proportions and model distances.
+    #[clap(long, value_name = "FILE")]
+    metadata: Option<PathBuf>,
+
+    /// Also write the number of sites compared for each pair to FILE as a matrix
+    ///
+    /// A site is compared if neither sequence has an ignored character there. The rows and
+    /// columns are the same as the distance matrix, so it can be used to weight or filter it.
+    /// Cannot be used with `--compact`, as that removes the invariant sites, or with options that
+    /// change the rows and columns of the distance output or do not write a distance matrix.
+    #[clap(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = &[
+            "pairs-file", "within-groups", "expand", "all-pairs", "from-matrix",
+            "compare-matrices", "vs-consensus", "panel", "count-sites-only", "compact", "cluster",
+            "shape", "explain", "drop-singletons", "groups"
+        ]
+    )]
+    sites_matrix: Option<PathBuf>,
+
+    /// Also build a neighbour-joining tree from the SNP distances and write it to FILE as Newick
+    ///
+    /// Only available for a single alignment, and needs the distances between every pair of
+    /// sequences - i.e., it cannot be used with options that skip or drop pairs or sequences.
+    #[clap(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = &[
+            "pairs-file", "all-pairs", "cluster", "from-matrix", "compare-matrices",
+            "vs-consensus", "panel", "explain", "count-sites-only", "shape", "within-groups",
+            "groups", "drop-singletons"
+        ]
+    )]
+    tree: Option<PathBuf>,
+
+    /// Also write the proportion of compared sites that differ
+    ///
+    /// `--output` is used as a prefix and the SNP distances and proportions are written to
+    /// `<prefix>.snps.csv` and `<prefix>.prop.csv` (`.tsv` if tab-delimited), respectively, from
+    /// the same computation. Proportions are NA if no sites were compared. See `--normalize-by`
+    /// for the denominator.
+    #[clap(long, requires = "output")]
+    also_normalized: bool,
+
+    /// Denominator of the proportions written with `--also-normalized`
+    ///
+    /// `compared` is the number of sites where neither sequence is ignored, `total` is the
+    /// alignment length, and `min-present` is the number of non-ignored sites of whichever sequence
+    /// in the pair has fewer.
+    #[clap(
+        long,
+        arg_enum,
+        default_value = "compared",
+        value_name = "DENOMINATOR",
+        requires = "also-normalized"
+    )]
+    normalize_by: NormalizeBy,
+
+    /// Scale each SNP distance by the number of sites over the number of compared sites
+    ///
+    /// This estimates the distance over the full alignment length for pairs that share many
+    /// ignored (e.g., masked) positions. Distances are NA if no sites were compared.
+    #[clap(
+        long,
+        conflicts_with_all = &["also-normalized", "checkpoint", "drop-singletons", "groups", "outliers"]
+    )]
+    adjust_missing: bool,
+
+    /// Count a site with an N in one sequence and a base in the other as FRAC of a SNP
+    ///
+    /// N (and n) is no longer ignored, but N vs N is not a difference. Distances are written as
+    /// decimals with `--precision`.
+    #[clap(
+        long,
+        value_name = "FRAC",
+        parse(try_from_str = parse_fraction),
+        conflicts_with_all = &[
+            "also-normalized", "adjust-missing", "iupac", "checkpoint", "drop-singletons",
+            "groups", "within-groups", "outliers", "assert-max", "quantiles", "expand", "compact",
+            "all-pairs"
+        ]
+    )]
+    n_penalty: Option<f64>,
+
+    /// Take the smaller distance of each pair over both orientations of the second sequence
+    ///
+    /// For sequences whose strand is unknown, the distance is also computed against the reverse
+    /// complement of the second sequence of the pair. This is only meaningful for ungapped
+    /// sequences covering the same region, as the alignment columns are reversed too, and for
+    /// the same reason cannot be combined with options that remove columns.
+    #[clap(
+        long,
+        conflicts_with_all = &[
+            "cluster", "compact", "explain", "from-matrix", "panel", "spectrum", "vs-consensus",
+            "complete-deletion", "trim", "regions", "stride", "offset"
+        ]
+    )]
+    orient: bool,
+
+    /// Write the mean distance over N bootstrap replicates of the alignment columns
+    ///
+    /// Each replicate resamples the columns with replacement and recomputes the distances.
+    /// Distances are written as decimals with `--precision`. See `--bootstrap-sd` for their
+    /// variability.
+    #[clap(
+        long,
+        value_name = "N",
+        conflicts_with_all = &[
+            "also-normalized", "adjust-missing", "n-penalty", "orient", "checkpoint",
+            "drop-singletons", "groups", "within-groups", "outliers", "assert-max", "quantiles",
+            "expand", "compact", "all-pairs", "cluster", "explain", "panel", "spectrum",
+            "from-matrix", "vs-consensus"
+        ]
+    )]
+    bootstrap: Option<usize>,
+
+    /// Also write the standard deviation of the bootstrap distances to FILE
+    #[clap(long, value_name = "FILE", requires = "bootstrap")]
+    bootstrap_sd: Option<PathBuf>,
+
+    /// Seed for `--bootstrap` and `--subsample`, for reproducible results [default: random]
+    #[clap(long)]
+    seed: Option<u64>,
+
+    /// Only compute distances for a random sample of N sequences from each alignment
+    ///
+    /// This is for a quick look at a large alignment, not for final results. The chosen
+    /// sequences are logged and keep their order in the alignment. See `--seed`.
+    #[clap(
+        long,
+        value_name = "N",
+        conflicts_with_all = &["pairs-file", "all-pairs", "from-matrix", "compare-matrices"]
+    )]
+    subsample: Option<usize>,
+
+    /// Treat compatible IUPAC ambiguity codes as matches - e.g., dist(R, A) = 0
+    ///
+    /// Note, ignored characters (see `--ignored-chars`) are still ignored.
+    #[clap(long)]
+    iupac: bool,
+
+    /// Error before computing any distances if there are more than N pairs [default: unlimited]
+    ///
+    /// A guard against accidentally starting a computation that would never finish.
+    #[clap(long, value_name = "N")]
+    max_pairs: Option<usize>,
+
+    /// Give each thread an equal, contiguous chunk of the pairs instead of balancing the work
+    /// dynamically
+    ///
+    /// The results are the same, but the scheduling no longer depends on timing, which makes
+    /// benchmarks more comparable between runs. This can be slower if some pairs take longer.
+    #[clap(long)]
+    static_schedule: bool,
+
     /// Show a progress bar
     #[clap(short = 'P', long = "progress")]
     show_progress: bool,
 
+    /// Format of the progress line shown with `-P`
+    ///
+    /// `{percent}`, `{done}`, `{total}`, `{elapsed}`, and `{eta}` are replaced with the percentage
+    /// of pairs computed, the number of pairs computed, the total number of pairs, the elapsed time
+    /// and the estimated time remaining. Each line starts with a carriage return so that it
+    /// overwrites the previous one.
+    #[clap(
+        long,
+        value_name = "FORMAT",
+        default_value = "Progress: {percent}% ({done} / {total}) ETA: {eta}"
+    )]
+    progress_format: String,
+
+    /// Number of decimal places for the progress percentage
+    #[clap(long, value_name = "N", default_value = "2")]
+    progress_precision: usize,
+
+    /// Update the progress line every N pairs
+    ///
+    /// Defaults to every 1% of pairs or every 100 pairs, whichever is more frequent.
+    #[clap(long, value_name = "N", requires = "show-progress")]
+    progress_every: Option<usize>,
+
     /// No logging (except progress info if `-P` is given)
     #[clap(short, long)]
     quiet: bool,
 
+    /// Also write warnings and errors to this file (even with `--quiet`)
+    ///
+    /// Each line is tab-delimited with the seconds since the Unix epoch, the level, and the message.
+    #[clap(long, parse(from_os_str))]
+    log_file: Option<PathBuf>,
+
+    /// Periodically record completed pairs in this file so an interrupted run can be resumed
+    ///
+    /// Distances are computed in chunks of 100,000 pairs and each completed chunk is appended to
+    /// the checkpoint. The file is removed once the output has been written.
+    #[clap(long, parse(from_os_str), value_name = "FILE")]
+    checkpoint: Option<PathBuf>,
+
+    /// Resume from the `--checkpoint` file, skipping pairs that were already computed
+    ///
+    /// The inputs and options must be the same as the interrupted run - the checkpoint records a
+    /// fingerprint of the loaded sequences and options, and resuming a different run is an error.
+    #[clap(long, requires = "checkpoint")]
+    resume: bool,
+
+    /// Print version and build information as JSON and exit
+    #[clap(long)]
+    version_json: bool,
+
+    /// Check psdm works by computing the distances for a small built-in alignment and exit
+    ///
+    /// Exits with an error if the distances are not the expected ones. Useful for checking an
+    /// installation or container without any input files.
+    #[clap(long, conflicts_with_all = &["alignments", "from-matrix", "compare-matrices"])]
+    self_test: bool,
+
     #[clap(flatten)]
     transformer: Transformer,
 }
 
-fn main() -> Result<()> {
-    let opts = Opt::parse();
+impl Opt {
+    /// The options that compute something other than the raw SNP distance, and whether each is
+    /// given. At most one of them is used.
+    fn distance_options(&self) -> [(&'static str, bool); 6] {
+        [
+            ("--report full", self.report == Report::Full),
+            ("--model", self.model != Model::Raw),
+            ("--also-normalized", self.also_normalized),
+            ("--adjust-missing", self.adjust_missing),
+            ("--n-penalty", self.n_penalty.is_some()),
+            ("--bootstrap", self.bootstrap.is_some()),
+        ]
+    }
+
+    /// The options and modes that only apply to the raw SNP distance, whether each is given, and
+    /// which of the other `distance_options` (besides `--report full` and `--model`) it also
+    /// cannot be combined with
+    fn raw_only_options(&self) -> Vec<(String, bool, &'static [&'static str])> {
+        const NORMALIZED: &[&str] = &["--also-normalized"];
+        const ADJUSTED: &[&str] = &["--adjust-missing"];
+        const TRANSFORMED: &[&str] = &["--also-normalized", "--adjust-missing"];
+        const ALL: &[&str] = &[
+            "--also-normalized",
+            "--adjust-missing",
+            "--n-penalty",
+            "--bootstrap",
+        ];
+        let options: [(&str, bool, &[&str]); 20] = [
+            ("--also-normalized", self.also_normalized, &[]),
+            ("--adjust-missing", self.adjust_missing, &[]),
+            ("--n-penalty", self.n_penalty.is_some(), &[]),
+            ("--bootstrap", self.bootstrap.is_some(), &[]),
+            ("--all-pairs", self.all_pairs, TRANSFORMED),
+            ("--expand", self.expand, TRANSFORMED),
+            ("--checkpoint", self.checkpoint.is_some(), NORMALIZED),
+            ("--drop-singletons", self.drop_singletons.is_some(), &[]),
+            ("--groups", self.groups.is_some(), NORMALIZED),
+            ("--within-groups", self.within_groups.is_some(), TRANSFORMED),
+            ("--assert-max", self.assert_max.is_some(), ADJUSTED),
+            ("--quantiles", self.quantiles, ADJUSTED),
+            ("--from-matrix", self.from_matrix.is_some(), &[]),
+            ("--tree", self.tree.is_some(), ALL),
+            (
+                "--orient",
+                self.orient,
+                &["--also-normalized", "--adjust-missing", "--n-penalty"],
+            ),
+            ("--compact", self.compact, TRANSFORMED),
+            ("--outliers", self.outliers.is_some(), &[]),
+            // these return before any distance is computed, so ignore every distance option
+            ("--cluster", self.cluster.is_some(), ALL),
+            ("--vs-consensus", self.vs_consensus, ALL),
+            ("--panel", self.panel.is_some(), ALL),
+        ];
+        let format = (
+            format!("{:?} output", self.format),
+            self.format.snps_only(),
+            NORMALIZED,
+        );
+        options
+            .iter()
+            .map(|(o, given, also)| (o.to_string(), *given, *also))
+            .chain([format])
+            .collect()
+    }
+
+    /// Error if an option that only applies to the raw SNP distance is given with one that
+    /// computes something else, rather than silently ignoring one of them
+    fn check_raw_only(&self) -> Result<()> {
+        let distance_options = self.distance_options();
+        for (option, _, also) in self.raw_only_options().iter().filter(|o| o.1) {
+            let other = distance_options.iter().find(|(o, given)| {
+                *given && (*o == "--report full" || *o == "--model" || also.contains(o))
+            });
+            if let Some((other, _)) = other {
+                bail!(
+                    "{} can only be used with the raw SNP distance, not with {}",
+                    option,
+                    other
+                );
+            }
+        }
+        if self.iupac && self.model != Model::Raw {
+            bail!("--iupac can only be used with the raw SNP distance, not with --model");
+        }
+        Ok(())
+    }
+
+    /// The name of the output file when `--output` is a directory
+    fn output_file_name(&self) -> String {
+        let delimited = match self.delimiter {
+            '\t' => "tsv",
+            _ => "csv",
+        };
+        let mut name = match self.format {
+            // the output is a prefix for several files
+            _ if self.also_normalized || self.all_pairs => "distances".to_string(),
+            _ if self.long_form => format!("distances.long.{}", delimited),
+            _ if self.pretty => "distances.txt".to_string(),
+            OutputFormat::Matrix | OutputFormat::R => format!("distances.{}", delimited),
+            OutputFormat::Condensed => "distances.condensed.txt".to_string(),
+            OutputFormat::Bin => "distances.bin".to_string(),
+            OutputFormat::Edgelist => format!("distances.edgelist.{}", delimited),
+            OutputFormat::Graphml => "distances.graphml".to_string(),
+            OutputFormat::Sparse => format!("distances.sparse.{}", delimited),
+            OutputFormat::Hdf5 => "distances.h5".to_string(),
+        };
+        if let Some(ext) = self.output_compression.and_then(|c| c.extension()) {
+            if !(self.also_normalized || self.all_pairs) {
+                name = format!("{}.{}", name, ext);
+            }
+        }
+        name
+    }
+
+    /// Format a floating point distance with `--precision` and `--round-mode`
+    fn format_float(&self, x: Option<f64>) -> String {
+        let x = x.map(|x| self.round_mode.round(x, self.precision));
+        format_float(x, self.precision)
+    }
+
+    /// The `--seed`, or a random one if it was not given
+    fn seed(&self) -> u64 {
+        self.seed.unwrap_or_else(|| fastrand::u64(..))
+    }
+
+    /// The header row to write for the matrix - see `--no-header` and `--no-corner`
+    fn header(&self) -> Header {
+        match (self.no_header, self.no_corner) {
+            (true, _) => Header::Omit,
+            (false, true) => Header::NoCorner,
+            (false, false) => Header::WithCorner,
+        }
+    }
+
+    /// The character to group digits with - see `--group-separator`
+    fn digit_separator(&self) -> char {
+        match self.group_separator {
+            Some(c) => c,
+            None if self.delimiter == ',' => '_',
+            None => ',',
+        }
+    }
+
+    /// How to show progress, if at all
+    fn progress(&self) -> Option<Progress<'_>> {
+        self.show_progress.then(|| Progress {
+            format: &self.progress_format,
+            precision: self.progress_precision,
+            every: self.progress_every,
+        })
+    }
+}
+
+/// Logs everything to stderr (via env_logger), and also writes warnings and errors to a file as
+/// tab-delimited lines of seconds since the Unix epoch, level, and message.
+struct TeeLogger {
+    inner: env_logger::Logger,
+    file: Mutex<File>,
+}
+
+impl log::Log for TeeLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= Level::Warn || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        self.inner.log(record);
+        if record.level() <= Level::Warn {
+            let secs = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+            let msg = record.args().to_string().replace(['\t', '\n'], " ");
+            if let Ok(mut file) = self.file.lock() {
+                let _ = writeln!(file, "{}\t{}\t{}", secs, record.level(), msg);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+        if let Ok(mut file) = self.file.lock() {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// Version and build information - crate version, git commit, rustc version, and enabled features
+fn version_json() -> String {
+    let mut features: Vec<&str> = vec![];
+    if cfg!(feature = "url") {
+        features.push("url");
+    }
+    if cfg!(feature = "hdf5") {
+        features.push("hdf5");
+    }
+    let features = features
+        .iter()
+        .map(|f| format!("\"{}\"", f))
+        .collect::<Vec<_>>()
+        .join(",");
+    format!(
+        r#"{{"name":"{}","version":"{}","commit":"{}","rustc":"{}","features":[{}]}}"#,
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        env!("PSDM_GIT_COMMIT"),
+        env!("PSDM_RUSTC_VERSION"),
+        features
+    )
+}
+
+/// Format a number of seconds as HH:MM:SS
+fn format_duration(secs: f64) -> String {
+    let secs = secs.round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}",
+        secs / 3600,
+        (secs % 3600) / 60,
+        secs % 60
+    )
+}
+
+/// How to show progress on stderr - see `--progress-format`
+struct Progress<'a> {
+    format: &'a str,
+    precision: usize,
+    /// Number of pairs between updates of the progress line, if not the default
+    every: Option<usize>,
+}
+
+impl Progress<'_> {
+    /// The progress line after `done` of `total` pairs have been computed in `elapsed` seconds
+    fn render(&self, done: usize, total: usize, elapsed: f64) -> String {
+        let percent = done as f64 / total as f64 * 100.0;
+        // assume the remaining pairs will be processed at the same rate as the others
+        let eta = elapsed * (total - done) as f64 / done as f64;
+        self.format
+            .replace("{percent}", &format!("{:.*}", self.precision, percent))
+            .replace("{done}", &done.to_string())
+            .replace("{total}", &total.to_string())
+            .replace("{elapsed}", &format_duration(elapsed))
+            .replace("{eta}", &format_duration(eta))
+    }
+}
+
+/// Compute `f` for every pair of indices in parallel, optionally showing progress on stderr. With
+/// `--static-schedule`, thread `i` of the pool computes the `i`th contiguous chunk of the pairs
+/// rather than rayon's work stealing.
+fn compute_pairwise<T, F>(pairwise_indices: &[Vec<usize>], opts: &Opt, f: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(usize, usize) -> T + Sync + Send,
+{
+    let progress = opts.progress();
+    let progress = progress.as_ref();
+    let num_items = pairwise_indices.len();
+    let counter = AtomicUsize::new(0);
+    // unless given, make the progress interval every 100 pairwise operations or every 1%, whichever
+    // is smaller
+    let progress_interval = progress
+        .and_then(|p| p.every)
+        .unwrap_or_else(|| std::cmp::min((num_items as f64 / 100.0).ceil() as usize, 100))
+        .max(1);
+    let start = Instant::now();
+    let work = |ix: &Vec<usize>| {
+        let result = f(ix[0], ix[1]);
+
+        // Update the counter
+        let current_count = counter.fetch_add(1, Ordering::SeqCst) + 1;
+
+        // Optionally print progress every 1%
+        match progress {
+            Some(p) if current_count.is_multiple_of(progress_interval) => {
+                let line = p.render(current_count, num_items, start.elapsed().as_secs_f64());
+                eprint!("\r{}", line);
+                match std::io::stderr().flush() {
+                    Ok(_) => (),
+                    Err(e) => eprintln!("Error occurred when flushing stderr: {:?}", e),
+                }
+            }
+            _ => (),
+        }
+
+        result
+    };
+    let results = if opts.static_schedule {
+        let chunk_size = num_items.div_ceil(rayon::current_num_threads()).max(1);
+        // thread `i` of the pool computes chunk `i`, so no chunk can be stolen by another thread
+        let chunks: Vec<Vec<T>> = rayon::broadcast(|ctx| {
+            pairwise_indices
+                .chunks(chunk_size)
+                .nth(ctx.index())
+                .map_or_else(Vec::new, |chunk| chunk.iter().map(work).collect())
+        });
+        chunks.into_iter().flatten().collect()
+    } else {
+        pairwise_indices.into_par_iter().map(work).collect()
+    };
+
+    // Finish the progress bar
+    if progress.is_some() {
+        eprintln!();
+    }
+
+    results
+}
+
+/// Compute the distances in chunks, recording each completed chunk in a checkpoint file. If
+/// `resume` is true, chunks already in the checkpoint are not computed again. `fingerprint` is
+/// the hash of the run's inputs, which the checkpoint must match.
+fn compute_with_checkpoint<F>(
+    path: &Path,
+    resume: bool,
+    pairwise_indices: &[Vec<usize>],
+    fingerprint: u64,
+    opts: &Opt,
+    f: F,
+) -> Result<Vec<u64>>
+where
+    F: Fn(usize, usize) -> u64 + Sync + Send,
+{
+    let mut checkpoint = Checkpoint::open(
+        path,
+        pairwise_indices.len(),
+        CHECKPOINT_CHUNK_SIZE,
+        fingerprint,
+        resume,
+    )?;
+    if resume {
+        info!("Resuming {} completed chunk(s)", checkpoint.len());
+    }
+    let n_chunks = pairwise_indices.len().div_ceil(CHECKPOINT_CHUNK_SIZE);
+    let mut dists = Vec::with_capacity(pairwise_indices.len());
+    for (idx, chunk) in pairwise_indices.chunks(CHECKPOINT_CHUNK_SIZE).enumerate() {
+        match checkpoint.get(idx) {
+            Some(values) => dists.extend_from_slice(values),
+            None => {
+                let values = compute_pairwise(chunk, opts, &f);
+                checkpoint
+                    .record(idx, &values)
+                    .context("Failed to write checkpoint")?;
+                info!("Checkpointed chunk {} of {}", idx + 1, n_chunks);
+                dists.extend(values);
+            }
+        }
+    }
+    Ok(dists)
+}
+
+/// Error if any distance, other than that of a sequence to itself, is more than `max`. The error
+/// names the most distant pair.
+fn assert_max_distance(
+    dists: &[u64],
+    pairwise_indices: &[Vec<usize>],
+    col_names: &[Vec<u8>],
+    row_names: &[Vec<u8>],
+    intra: bool,
+    max: u64,
+) -> Result<()> {
+    let exceeding: Vec<(&Vec<usize>, u64)> = pairwise_indices
+        .iter()
+        .zip(dists.iter().copied())
+        .filter(|(ix, d)| *d > max && !(intra && ix[0] == ix[1]))
+        .collect();
+    match exceeding.iter().max_by_key(|(_, d)| *d) {
+        None => Ok(()),
+        Some((ix, d)) => bail!(
+            "{} pair(s) have a distance above {}; the largest is {} between {} and {}",
+            exceeding.len(),
+            max,
+            d,
+            String::from_utf8_lossy(&col_names[ix[0]]),
+            String::from_utf8_lossy(&row_names[ix[1]])
+        ),
+    }
+}
+
+/// The (column, row) index of every pair of sequences. For intra-alignment distances, this is only
+/// the lower-left triangle and the diagonal.
+fn all_pairwise_indices(n_seqs1: usize, n_seqs2: usize) -> Vec<Vec<usize>> {
+    match n_seqs2 {
+        0 => triangle_indices(n_seqs1).collect(),
+        i => iproduct!(0..n_seqs1, 0..i)
+            .map(|t| vec![t.0, t.1])
+            .collect(),
+    }
+}
+
+/// The sequences of an alignment before identical sequences were collapsed - see `--dedup`
+struct Dedup {
+    /// Names of all sequences
+    names: Vec<Vec<u8>>,
+    /// Index of each sequence's representative in the deduplicated alignment
+    assignment: Vec<usize>,
+}
+
+/// Collapse identical sequences into their first occurrence
+fn dedup_alignment(names: &mut Vec<Vec<u8>>, seqs: &mut Vec<Vec<u8>>) -> Dedup {
+    let (representatives, assignment) = dedup(seqs);
+    info!(
+        "Collapsed {} sequences into {} distinct sequences",
+        seqs.len(),
+        representatives.len()
+    );
+    let all_names = std::mem::take(names);
+    *names = representatives
+        .iter()
+        .map(|i| all_names[*i].clone())
+        .collect();
+    let is_representative = |i: usize| representatives[assignment[i]] == i;
+    *seqs = std::mem::take(seqs)
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, seq)| is_representative(i).then_some(seq))
+        .collect();
+    Dedup {
+        names: all_names,
+        assignment,
+    }
+}
+
+/// Write one line per sequence with its name and the name of its representative
+fn write_dedup_map(
+    ostream: &mut Box<dyn Write>,
+    delimiter: char,
+    dedup: &Dedup,
+    representative_names: &[Vec<u8>],
+) -> std::io::Result<()> {
+    for (name, rep) in dedup.names.iter().zip(&dedup.assignment) {
+        writeln!(
+            ostream,
+            "{}{}{}",
+            quote_field(name, delimiter),
+            delimiter,
+            quote_field(&representative_names[*rep], delimiter)
+        )?;
+    }
+    ostream.flush()
+}
+
+/// Expand distances between deduplicated sequences to every pair of the original sequences.
+/// Returns the distances and (column, row) indices of the pairs of original sequences.
+fn expand_dedup(
+    dists: Vec<u64>,
+    pairwise_indices: &[Vec<usize>],
+    n_seqs1: usize,
+    n_seqs2: usize,
+    cols: &Dedup,
+    rows: Option<&Dedup>,
+) -> Result<(Vec<u64>, Vec<Vec<usize>>)> {
+    let matrix = to_matrix(dists, pairwise_indices, n_seqs1, n_seqs2)?;
+    let rows = rows.unwrap_or(cols);
+    let n_rows = if n_seqs2 > 0 { rows.names.len() } else { 0 };
+    let pairwise_indices = all_pairwise_indices(cols.names.len(), n_rows);
+    let dists = pairwise_indices
+        .iter()
+        .map(|ix| matrix[[rows.assignment[ix[1]], cols.assignment[ix[0]]]])
+        .collect();
+    Ok((dists, pairwise_indices))
+}
+
+/// Keep a random sample of `n` sequences, in their original order - see `--subsample`
+fn subsample(names: &mut Vec<Vec<u8>>, seqs: &mut Vec<Vec<u8>>, n: usize, rng: &mut fastrand::Rng) {
+    if n >= names.len() {
+        warn!(
+            "Asked to subsample {} sequences, but there are only {}; using them all",
+            n,
+            names.len()
+        );
+        return;
+    }
+    let chosen = subsample_indices(rng, names.len(), n);
+    *names = chosen
+        .iter()
+        .map(|&i| std::mem::take(&mut names[i]))
+        .collect();
+    *seqs = chosen
+        .iter()
+        .map(|&i| std::mem::take(&mut seqs[i]))
+        .collect();
+    info!(
+        "Chose {}",
+        names
+            .iter()
+            .map(|name| String::from_utf8_lossy(name))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+}
+
+/// The alignment for `--self-test`, with ignored characters (N and -) and a lower-case base
+const SELF_TEST_ALIGNMENT: &[u8] = b">s1\nACGTACGT\n>s2 description\nACGAACGN\n>s3\ntCGTAC-T\n";
+
+/// The expected SNP distances for [`SELF_TEST_ALIGNMENT`]
+const SELF_TEST_EXPECTED: [[u64; 3]; 3] = [[0, 1, 1], [1, 0, 2], [1, 2, 0]];
+
+/// Load the built-in alignment with the default options and check its distances - see
+/// `--self-test`
+fn self_test(opts: &Opt) -> Result<()> {
+    info!("Running self-test...");
+    let transformer = Transformer::try_parse_from(["psdm"])?;
+    let (names, seqs) = load_alignment(&transformer, SELF_TEST_ALIGNMENT, InputFormat::Fasta, 0)
+        .context("Self-test failed to load the alignment")?;
+    let expected_names = [b"s1".to_vec(), b"s2".to_vec(), b"s3".to_vec()];
+    if names != expected_names {
+        bail!(
+            "Self-test failed: expected the names s1, s2, s3 but got {}",
+            names
+                .iter()
+                .map(|n| String::from_utf8_lossy(n))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    let pairwise_indices = all_pairwise_indices(seqs.len(), 0);
+    let dists = compute_pairwise(&pairwise_indices, opts, |i, j| {
+        hamming_distance(&seqs[i], &seqs[j])
+    });
+    let matrix = to_matrix(dists, &pairwise_indices, seqs.len(), 0)?;
+    if matrix != ndarray::arr2(&SELF_TEST_EXPECTED) {
+        bail!(
+            "Self-test failed: expected the distances {:?} but got {:?}",
+            SELF_TEST_EXPECTED,
+            matrix.outer_iter().map(|r| r.to_vec()).collect::<Vec<_>>()
+        );
+    }
+    info!("Self-test passed");
+    Ok(())
+}
+
+/// Error if there are more pairs to compute than `--max-pairs` allows
+fn check_max_pairs(opts: &Opt, n_pairs: usize) -> Result<()> {
+    match opts.max_pairs {
+        Some(max) if n_pairs > max => bail!(
+            "{} pairwise distances would be computed, which is more than --max-pairs {}. Raise \
+             --max-pairs to run it anyway",
+            n_pairs,
+            max
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Arrange the pairwise results into a matrix where the columns are the first alignment and the
+/// rows are the second alignment (or the first again for intra-alignment distances).
+fn to_matrix<T: Clone + Default>(
+    values: Vec<T>,
+    pairwise_indices: &[Vec<usize>],
+    n_seqs1: usize,
+    n_seqs2: usize,
+) -> Result<Array2<T>> {
+    if n_seqs2 > 0 {
+        // values are ordered by column (first alignment) then row, which is column-major order for
+        // the (rows x columns) matrix, so no transpose/copy is needed
+        Array::from_shape_vec((n_seqs2, n_seqs1).f(), values).context(
+            "Failed to create matrix. This shouldn't happen, please raise an issue on GitHub",
+        )
+    } else {
+        let mut mtx = Array2::from_elem((n_seqs1, n_seqs1), T::default());
+        for (ix, v) in pairwise_indices.iter().zip(values) {
+            let i = ix[0];
+            let j = ix[1];
+            if i != j {
+                mtx[[j, i]] = v.clone();
+            }
+            mtx[[i, j]] = v;
+        }
+        Ok(mtx)
+    }
+}
+
+/// Write SNP distances, dropping singletons and using the binary format if requested, otherwise
+/// see [`write_distances`]
+fn write_snps(
+    ostream: &mut Box<dyn Write>,
+    opts: &Opt,
+    dists: Vec<u64>,
+    pairwise_indices: &[Vec<usize>],
+    col_names: &[Vec<u8>],
+    row_names: &[Vec<u8>],
+    n_seqs2: usize,
+) -> Result<()> {
+    if opts.group_digits {
+        let separator = opts.digit_separator();
+        let dists = dists
+            .into_iter()
+            .map(|d| group_digits(d, separator))
+            .collect();
+        return write_distances(
+            ostream,
+            opts,
+            dists,
+            pairwise_indices,
+            col_names,
+            row_names,
+            n_seqs2,
+        );
+    }
+    if opts.pairs_file.is_some() || (opts.long_form && opts.drop_singletons.is_none()) {
+        return write_distances(
+            ostream,
+            opts,
+            dists,
+            pairwise_indices,
+            col_names,
+            row_names,
+            n_seqs2,
+        );
+    }
+    let mut matrix = to_matrix(dists, pairwise_indices, col_names.len(), n_seqs2)?;
+    let mut names: Vec<Vec<u8>> = col_names.to_vec();
+    if let Some(max_dist) = opts.drop_singletons {
+        if n_seqs2 > 0 {
+            bail!("--drop-singletons is only available for a single alignment");
+        }
+        let keep = non_singletons(&matrix, max_dist);
+        info!(
+            "Dropping {} sequence(s) with no other sequence within {} SNPs",
+            names.len() - keep.len(),
+            max_dist
+        );
+        matrix = matrix.select(Axis(0), &keep).select(Axis(1), &keep);
+        names = keep.iter().map(|&i| names[i].clone()).collect();
+    }
+    let (col_names, row_names) = match opts.drop_singletons {
+        Some(_) => (&names[..], &names[..]),
+        None => (col_names, row_names),
+    };
+
+    let intra = n_seqs2 == 0;
+    match opts.format {
+        _ if opts.long_form => write_matrix(ostream, opts, &matrix, col_names, row_names, intra),
+        OutputFormat::Bin => {
+            info!("Writing binary matrix...");
+            write_binary(ostream, &matrix, col_names, row_names)
+                .context("Failed to write output table")
+        }
+        OutputFormat::Edgelist => {
+            info!("Writing edge list...");
+            write_edgelist(
+                ostream,
+                opts.delimiter,
+                &matrix,
+                col_names,
+                row_names,
+                intra,
+                opts.max_dist,
+            )
+            .context("Failed to write output table")
+        }
+        #[cfg(feature = "hdf5")]
+        OutputFormat::Hdf5 => {
+            info!("Writing HDF5...");
+            let path = opts
+                .output
+                .as_deref()
+                .context("HDF5 output requires --output")?;
+            let level = hdf5_level(opts.compression_level);
+            write_hdf5(path, &matrix, col_names, row_names, intra, level)
+                .context("Failed to write output table")
+        }
+        OutputFormat::Graphml => {
+            info!("Writing GraphML...");
+            write_graphml(ostream, &matrix, col_names, row_names, intra, opts.max_dist)
+                .context("Failed to write output table")
+        }
+        OutputFormat::Sparse => {
+            info!("Writing sparse triplets...");
+            write_sparse(ostream, opts.delimiter, &matrix, intra, opts.max_dist)
+                .context("Failed to write output table")?;
+            let sidecars = if intra {
+                vec![("names", col_names)]
+            } else {
+                vec![("row_names", row_names), ("col_names", col_names)]
+            };
+            for (kind, names) in sidecars {
+                let mut path = opts.output.clone().unwrap_or_default().into_os_string();
+                path.push(format!(".{}.txt", kind));
+                let mut names_ostream = create_output(opts, Some(Path::new(&path)))?;
+                for name in names {
+                    writeln!(names_ostream, "{}", String::from_utf8_lossy(name))
+                        .context("Failed to write names")?;
+                }
+            }
+            Ok(())
+        }
+        _ => write_matrix(ostream, opts, &matrix, col_names, row_names, intra),
+    }
+}
+
+/// Write quantiles of the distances, other than that of a sequence to itself, to stderr as a
+/// tab-delimited table
+fn report_quantiles(dists: &[u64], pairwise_indices: &[Vec<usize>], intra: bool) {
+    let labels = ["min", "25%", "50%", "75%", "95%", "max"];
+    let dists = pairwise_indices
+        .iter()
+        .zip(dists)
+        .filter(|(ix, _)| !intra || ix[0] != ix[1])
+        .map(|(_, d)| *d);
+    match quantiles(dists, &[0.0, 0.25, 0.5, 0.75, 0.95, 1.0]) {
+        None => warn!("No distances to summarise"),
+        Some(values) => {
+            eprintln!("quantile\tdistance");
+            for (label, value) in labels.iter().zip(values) {
+                eprintln!("{}\t{}", label, value);
+            }
+        }
+    }
+}
+
+/// Write each sequence's (single-linkage) cluster - see `--cluster`
+fn write_clusters(
+    ostream: &mut Box<dyn Write>,
+    delimiter: char,
+    names: &[Vec<u8>],
+    clusters: &[usize],
+) -> std::io::Result<()> {
+    info!(
+        "Found {} clusters",
+        clusters.iter().max().map_or(0, |c| c + 1)
+    );
+    writeln!(ostream, "sequence{}cluster", delimiter)?;
+    for (name, cluster) in names.iter().zip(clusters) {
+        writeln!(
+            ostream,
+            "{}{}{}",
+            quote_field(name, delimiter),
+            delimiter,
+            cluster
+        )?;
+    }
+    Ok(())
+}
+
+/// Load a psdm binary matrix or a delimited matrix with a header, possibly compressed
+fn load_matrix(path: &Path, delimiter: char) -> Result<BinaryMatrix> {
+    let mut reader = niffler::from_path(path)
+        .map(|(r, _)| BufReader::new(r))
+        .context("Could not open matrix file")?;
+    info!("Loading matrix {}...", path.display());
+    let is_binary = reader.fill_buf()?.starts_with(BINARY_MAGIC);
+    match is_binary {
+        true => read_binary(reader),
+        false => read_delimited_matrix(reader, delimiter),
+    }
+    .context("Failed to load matrix")
+}
+
+/// Compare two matrices cell by cell - see `--compare-matrices`
+fn compare_matrices(opts: &Opt, ostream: &mut Box<dyn Write>, a: &Path, b: &Path) -> Result<()> {
+    let a = load_matrix(a, opts.delimiter)?;
+    let b = load_matrix(b, opts.delimiter)?;
+    let index = |names: &[Vec<u8>]| -> HashMap<Vec<u8>, usize> {
+        names
+            .iter()
+            .cloned()
+            .enumerate()
+            .map(|(i, n)| (n, i))
+            .collect()
+    };
+    let (b_cols, b_rows) = (index(&b.column_names), index(&b.row_names));
+    if b_cols.len() != a.column_names.len() || b_rows.len() != a.row_names.len() {
+        bail!("The matrices do not have the same sequences");
+    }
+    let lookup = |map: &HashMap<Vec<u8>, usize>, name: &[u8]| {
+        map.get(name).copied().with_context(|| {
+            format!(
+                "{} is not in the second matrix",
+                String::from_utf8_lossy(name)
+            )
+        })
+    };
+
+    let intra = a.column_names == a.row_names;
+    let n_seqs2 = if intra { 0 } else { a.row_names.len() };
+    let mut pairs = vec![];
+    for ix in all_pairwise_indices(a.column_names.len(), n_seqs2) {
+        let (col, row) = (ix[0], ix[1]);
+        if intra && col == row {
+            continue;
+        }
+        let b_col = lookup(&b_cols, &a.column_names[col])?;
+        let b_row = lookup(&b_rows, &a.row_names[row])?;
+        pairs.push((col, row, a.matrix[[row, col]], b.matrix[[b_row, b_col]]));
+    }
+
+    let xs: Vec<f64> = pairs.iter().map(|p| p.2 as f64).collect();
+    let ys: Vec<f64> = pairs.iter().map(|p| p.3 as f64).collect();
+    let abs_diffs: Vec<f64> = xs.iter().zip(&ys).map(|(x, y)| (y - x).abs()).collect();
+    let n_changed = abs_diffs.iter().filter(|d| **d > 0.0).count();
+    let mean_diff = (!abs_diffs.is_empty()).then(|| mean_sd(&abs_diffs).0);
+    let max_diff = abs_diffs.iter().copied().reduce(f64::max);
+    eprintln!("statistic\tvalue");
+    eprintln!("pairs\t{}", pairs.len());
+    eprintln!("changed\t{}", n_changed);
+    eprintln!("mean_abs_difference\t{}", opts.format_float(mean_diff));
+    eprintln!("max_abs_difference\t{}", opts.format_float(max_diff));
+    eprintln!("correlation\t{}", opts.format_float(pearson(&xs, &ys)));
+
+    pairs.retain(|p| p.2 != p.3);
+    pairs.sort_by_key(|p| std::cmp::Reverse(p.2.abs_diff(p.3)));
+    writeln!(
+        ostream,
+        "sequence1{d}sequence2{d}distance_a{d}distance_b{d}difference",
+        d = opts.delimiter
+    )?;
+    for (col, row, x, y) in pairs {
+        writeln!(
+            ostream,
+            "{}{d}{}{d}{}{d}{}{d}{}",
+            quote_field(&a.column_names[col], opts.delimiter),
+            quote_field(&a.row_names[row], opts.delimiter),
+            x,
+            y,
+            y as i128 - x as i128,
+            d = opts.delimiter
+        )?;
+    }
+    Ok(())
+}
+
+/// Load a precomputed distance matrix and summarise and/or rewrite it - see `--from-matrix`
+fn analyse_matrix(opts: &Opt, ostream: &mut Box<dyn Write>, path: &Path) -> Result<()> {
+    let BinaryMatrix {
+        matrix,
+        column_names,
+        row_names,
+    } = load_matrix(path, opts.delimiter)?;
+    let intra = column_names == row_names;
+    let n_seqs1 = column_names.len();
+    let n_seqs2 = if intra { 0 } else { row_names.len() };
+    info!("Loaded a {}x{} matrix", row_names.len(), n_seqs1);
+
+    let pairwise_indices = all_pairwise_indices(n_seqs1, n_seqs2);
+    let dists: Vec<u64> = pairwise_indices
+        .iter()
+        .map(|ix| matrix[[ix[1], ix[0]]])
+        .collect();
+
+    if opts.quantiles {
+        report_quantiles(&dists, &pairwise_indices, intra);
+    }
+    if let Some(max) = opts.assert_max {
+        assert_max_distance(
+            &dists,
+            &pairwise_indices,
+            &column_names,
+            &row_names,
+            intra,
+            max,
+        )?;
+    }
+    if let Some(n) = opts.outliers {
+        let weights = match opts.sample_weights {
+            Some(ref p) => {
+                let reader = BufReader::new(File::open(p).context("Failed to open weights file")?);
+                Some(parse_weights(reader, &column_names).context("Failed to parse weights file")?)
+            }
+            None => None,
+        };
+        report_outliers(&matrix, &row_names, n, intra, weights.as_deref());
+    }
+    if let Some(threshold) = opts.cluster {
+        if !intra {
+            bail!("--cluster is only available for a single alignment");
+        }
+        let links = pairwise_indices
+            .iter()
+            .zip(&dists)
+            .filter(|(ix, d)| ix[0] != ix[1] && **d <= threshold)
+            .map(|(ix, _)| (ix[0], ix[1]));
+        let clusters = single_linkage(n_seqs1, links);
+        return write_clusters(ostream, opts.delimiter, &column_names, &clusters)
+            .context("Failed to write output table");
+    }
+    write_snps(
+        ostream,
+        opts,
+        dists,
+        &pairwise_indices,
+        &column_names,
+        &row_names,
+        n_seqs2,
+    )
+}
+
+/// Compute the inter-alignment SNP distances for every pair of alignment files, writing each matrix
+/// to its own file - see `--all-pairs`
+fn all_pairs(opts: &Opt) -> Result<()> {
+    if opts.alignments.len() < 2 {
+        bail!("--all-pairs requires at least two alignments");
+    }
+    let mut alignments = Vec::with_capacity(opts.alignments.len());
+    for p in &opts.alignments {
+        let reader = open_alignment(p)
+            .map(BufReader::new)
+            .with_context(|| format!("Could not open alignment file {}", p.display()))?;
+        info!("Loading {}...", p.display());
+        let format = opts.format_in.unwrap_or_else(|| InputFormat::from_path(p));
+        let (names, seqs) = load_alignment(&opts.transformer, reader, format, 0)
+            .with_context(|| format!("Failed to load alignment file {}", p.display()))?;
+        alignments.push((alignment_stem(p), names, seqs));
+    }
+
+    let distance = if opts.iupac {
+        iupac_distance
+    } else {
+        hamming_distance
+    };
+    for ((stem1, names1, seqs1), (stem2, names2, seqs2)) in alignments.iter().tuple_combinations() {
+        if seqs1.first().map(Vec::len) != seqs2.first().map(Vec::len) {
+            bail!("{} and {} have different alignment lengths", stem1, stem2);
+        }
+        let pairwise_indices = all_pairwise_indices(seqs1.len(), seqs2.len());
+        check_max_pairs(opts, pairwise_indices.len())?;
+        info!(
+            "Calculating {} pairwise distances for {} vs {}...",
+            pairwise_indices.len(),
+            stem1,
+            stem2
+        );
+        let dists = compute_pairwise(&pairwise_indices, opts, |i, j| {
+            distance(&seqs1[i], &seqs2[j])
+        });
+        let path = normalized_output_path(opts, &format!("{}_vs_{}", stem1, stem2));
+        let mut ostream = create_output(opts, Some(&path))?;
+        write_snps(
+            &mut ostream,
+            opts,
+            dists,
+            &pairwise_indices,
+            names1,
+            names2,
+            seqs2.len(),
+        )?;
+    }
+    Ok(())
+}
+
+/// Write the `n` most distant rows of the matrix to stderr as a tab-delimited table
+fn report_outliers(
+    matrix: &Array2<u64>,
+    row_names: &[Vec<u8>],
+    n: usize,
+    intra: bool,
+    weights: Option<&[f64]>,
+) {
+    eprintln!("name\tmean_distance");
+    for (i, mean) in outliers(matrix, n, intra, weights) {
+        eprintln!("{}\t{:.2}", String::from_utf8_lossy(&row_names[i]), mean);
+    }
+}
+
+/// The group of each column and row sequence, from a groups file
+struct GroupLabels {
+    /// Group names, in the order they first appear in the file
+    names: Vec<Vec<u8>>,
+    /// Index of each column sequence's group, if it has one
+    cols: Vec<Option<usize>>,
+    /// Index of each row sequence's group, if it has one
+    rows: Vec<Option<usize>>,
+}
+
+/// Load the groups file and look up the group of each sequence, warning about any sequences
+/// without a group
+fn load_group_labels(
+    path: &Path,
+    col_names: &[Vec<u8>],
+    row_names: &[Vec<u8>],
+    intra: bool,
+) -> Result<GroupLabels> {
+    let reader = BufReader::new(File::open(path).context("Failed to open groups file")?);
+    let (names, groups_by_id) = parse_groups(reader).context("Failed to parse groups file")?;
+    let lookup = |names: &[Vec<u8>]| -> Vec<Option<usize>> {
+        names.iter().map(|n| groups_by_id.get(n).copied()).collect()
+    };
+    let cols = lookup(col_names);
+    let rows = lookup(row_names);
+    let ungrouped = cols
+        .iter()
+        .chain(if intra { &[] } else { &rows[..] })
+        .filter(|g| g.is_none())
+        .count();
+    if ungrouped > 0 {
+        warn!(
+            "{} sequence(s) are not in the groups file and will be left out",
+            ungrouped
+        );
+    }
+    Ok(GroupLabels { names, cols, rows })
+}
+
+/// Collapse a SNP distance matrix into a matrix of mean distances between the groups in the groups
+/// file and write it
+fn write_group_means(
+    ostream: &mut Box<dyn Write>,
+    opts: &Opt,
+    groups_path: &Path,
+    matrix: &Array2<u64>,
+    col_names: &[Vec<u8>],
+    row_names: &[Vec<u8>],
+    intra: bool,
+) -> Result<()> {
+    let groups = load_group_labels(groups_path, col_names, row_names, intra)?;
+    let means = group_means(
+        matrix,
+        &groups.cols,
+        &groups.rows,
+        groups.names.len(),
+        intra,
+    )
+    .map(|d| opts.format_float(*d));
+    write_matrix(ostream, opts, &means, &groups.names, &groups.names, intra)
+}
+
+/// The (column, row) index of every pair of sequences in the same group. For intra-alignment
+/// distances, each pair is only given once and a sequence is not paired with itself.
+fn within_group_indices(groups: &GroupLabels, intra: bool) -> Vec<Vec<usize>> {
+    let same_group = |ix: &Vec<usize>| match (groups.cols[ix[0]], groups.rows[ix[1]]) {
+        (Some(a), Some(b)) => a == b && !(intra && ix[0] == ix[1]),
+        _ => false,
+    };
+    let n_seqs2 = if intra { 0 } else { groups.rows.len() };
+    all_pairwise_indices(groups.cols.len(), n_seqs2)
+        .into_iter()
+        .filter(same_group)
+        .collect()
+}
+
+/// Write one line per pair with the group, column name, row name, and distance
+fn write_within_groups(
+    ostream: &mut Box<dyn Write>,
+    delimiter: char,
+    groups: &GroupLabels,
+    pairwise_indices: &[Vec<usize>],
+    dists: &[u64],
+    col_names: &[Vec<u8>],
+    row_names: &[Vec<u8>],
+) -> Result<()> {
+    info!("Writing within-group distances...");
+    for (ix, dist) in pairwise_indices.iter().zip(dists) {
+        // pairs are only ever within a group
+        let group = groups.cols[ix[0]].unwrap_or_default();
+        writeln!(
+            ostream,
+            "{}{d}{}{d}{}{d}{}",
+            quote_field(&groups.names[group], delimiter),
+            quote_field(&col_names[ix[0]], delimiter),
+            quote_field(&row_names[ix[1]], delimiter),
+            dist,
+            d = delimiter
+        )
+        .context("Failed to write output table")?;
+    }
+    Ok(())
+}
+
+/// Write one line per variable site with the position (counting from `base`), alleles, and allele
+/// counts
+fn write_variant_report(
+    ostream: &mut Box<dyn Write>,
+    delimiter: char,
+    sites: &[(usize, Alleles)],
+    base: usize,
+) -> std::io::Result<()> {
+    writeln!(ostream, "position{d}alleles{d}counts", d = delimiter)?;
+    for (i, alleles) in sites {
+        writeln!(
+            ostream,
+            "{}{d}{}{d}{}",
+            i + base,
+            alleles.iter().map(|(b, _)| *b as char).join("/"),
+            alleles.iter().map(|(_, n)| n).join("/"),
+            d = delimiter
+        )?;
+    }
+    ostream.flush()
+}
+
+/// Output files being written to a temporary path with `--atomic-output`, as (temporary, final)
+/// paths. They are moved into place by [`finish_atomic_outputs`].
+static ATOMIC_OUTPUTS: Mutex<Vec<(PathBuf, PathBuf)>> = Mutex::new(Vec::new());
+
+/// The hidden sibling path `.<name>.<pid>.tmp` that an output file is written to with
+/// `--atomic-output`
+fn atomic_temp_path(path: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".{}.tmp", std::process::id()));
+    path.with_file_name(name)
+}
+
+/// Move the atomic outputs into place if psdm succeeded, otherwise remove them
+fn finish_atomic_outputs(success: bool) -> Result<()> {
+    let outputs = std::mem::take(&mut *ATOMIC_OUTPUTS.lock().unwrap());
+    for (tmp, path) in outputs {
+        if success {
+            std::fs::rename(&tmp, &path).with_context(|| {
+                format!("Failed to move output into place at {}", path.display())
+            })?;
+        } else {
+            let _ = std::fs::remove_file(&tmp);
+        }
+    }
+    Ok(())
+}
+
+/// Sum the substitutions between each pair of sequences - see `--spectrum`
+fn pairwise_spectrum(
+    pairwise_indices: &[Vec<usize>],
+    col_seqs: &[Vec<u8>],
+    row_seqs: &[Vec<u8>],
+) -> Spectrum {
+    pairwise_indices
+        .par_iter()
+        .fold(Spectrum::new, |mut spectrum, ix| {
+            add_substitutions(&mut spectrum, &col_seqs[ix[0]], &row_seqs[ix[1]]);
+            spectrum
+        })
+        .reduce(Spectrum::new, |mut a, b| {
+            for (k, n) in b {
+                *a.entry(k).or_insert(0) += n;
+            }
+            a
+        })
+}
+
+/// Write the substitution spectrum as a matrix of "from" (rows) by "to" (columns) characters
+fn write_spectrum(
+    ostream: &mut Box<dyn Write>,
+    delimiter: char,
+    spectrum: &Spectrum,
+) -> std::io::Result<()> {
+    let chars: Vec<u8> = spectrum
+        .keys()
+        .flat_map(|(from, to)| [*from, *to])
+        .sorted()
+        .dedup()
+        .collect();
+    let header = chars
+        .iter()
+        .map(|c| *c as char)
+        .join(&delimiter.to_string());
+    writeln!(ostream, "{}{}", delimiter, header)?;
+    for from in &chars {
+        let counts = chars
+            .iter()
+            .map(|to| spectrum.get(&(*from, *to)).unwrap_or(&0))
+            .join(&delimiter.to_string());
+        writeln!(ostream, "{}{}{}", *from as char, delimiter, counts)?;
+    }
+    ostream.flush()
+}
+
+/// Whether an output path ends with a separator, so is a directory - see `--output`
+fn is_dir_path(path: &Path) -> bool {
+    path.as_os_str()
+        .to_str()
+        .is_some_and(|p| p.ends_with(std::path::is_separator))
+}
+
+/// Whether an output path is `-` - i.e., stdout
+fn is_stdout(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Create a (possibly compressed) output stream for a file, or stdout if no path (or `-`) is given
+fn create_output(opts: &Opt, path: Option<&Path>) -> Result<Box<dyn Write>> {
+    let path = path.filter(|p| !is_stdout(p));
+    let ostream: Box<dyn Write> = match path {
+        None => Box::new(stdout()),
+        Some(p) => {
+            let file = if opts.atomic_output {
+                let tmp = atomic_temp_path(p);
+                let file = File::create(&tmp)
+                    .with_context(|| format!("Failed to create output file {}", tmp.display()))?;
+                ATOMIC_OUTPUTS.lock().unwrap().push((tmp, p.to_path_buf()));
+                file
+            } else {
+                File::create(p)
+                    .with_context(|| format!("Failed to create output file {}", p.display()))?
+            };
+            match opts.buffer_size {
+                Some(n) => Box::new(BufWriter::with_capacity(n, file)),
+                None => Box::new(BufWriter::new(file)),
+            }
+        }
+    };
+    let compression = match (opts.output_compression, path) {
+        (Some(c), _) => c,
+        (None, Some(p)) => OutputCompression::from_path(p),
+        (None, None) => OutputCompression::None,
+    };
+    let ostream = niffler::get_writer(ostream, compression.into(), opts.compression_level)
+        .context("Failed to create compressed output stream")?;
+    let ostream: Box<dyn Write> = match opts.line_ending {
+        LineEnding::Lf => ostream,
+        LineEnding::Crlf => Box::new(CrlfWriter { inner: ostream }),
+    };
+    match (opts.flush_interval, path) {
+        (Some(every), Some(_)) => Ok(Box::new(FlushingWriter {
+            inner: ostream,
+            every: every.max(1),
+            lines: 0,
+        })),
+        _ => Ok(ostream),
+    }
+}
+
+/// The path `<prefix>.<kind>.csv` (or `.tsv` for tab-delimited output) used with
+/// `--also-normalized`, where the prefix is the `--output` option
+fn normalized_output_path(opts: &Opt, kind: &str) -> PathBuf {
+    let ext = match opts.delimiter {
+        '\t' => "tsv",
+        _ => "csv",
+    };
+    let mut path = opts.output.clone().unwrap_or_default().into_os_string();
+    path.push(format!(".{}.{}", kind, ext));
+    PathBuf::from(path)
+}
+
+/// Write the distances as a long-form table or matrix, depending on the options. `n_seqs2` is 0
+/// for intra-alignment distances.
+fn write_distances<T: Display + Clone + Default>(
+    ostream: &mut Box<dyn Write>,
+    opts: &Opt,
+    dists: Vec<T>,
+    pairwise_indices: &[Vec<usize>],
+    col_names: &[Vec<u8>],
+    row_names: &[Vec<u8>],
+    n_seqs2: usize,
+) -> Result<()> {
+    if opts.pairs_file.is_some() {
+        info!("Writing long-form table...");
+        write_pairs(
+            ostream,
+            opts.delimiter,
+            pairwise_indices,
+            &dists,
+            col_names,
+            row_names,
+        )
+        .context("Failed to write output table")
+    } else if opts.long_form && n_seqs2 == 0 && is_full_triangle(pairwise_indices, col_names.len())
+    {
+        // no need for the square matrix, which can use a lot of memory
+        info!("Writing long-form table...");
+        write_long_triangle(ostream, opts.delimiter, &dists, col_names)
+            .context("Failed to write output table")
+    } else {
+        let matrix = to_matrix(dists, pairwise_indices, col_names.len(), n_seqs2)?;
+        write_matrix(ostream, opts, &matrix, col_names, row_names, n_seqs2 == 0)
+    }
+}
+
+/// Whether the pairs are the full lower-left triangle (and diagonal) of an intra-alignment matrix
+/// of `n` sequences, in the order given by [`all_pairwise_indices`]
+fn is_full_triangle(pairwise_indices: &[Vec<usize>], n: usize) -> bool {
+    pairwise_indices.len() == n * (n + 1) / 2
+        && pairwise_indices
+            .iter()
+            .zip(triangle_indices(n))
+            .all(|(a, b)| *a == b)
+}
+
+type ColumnAndRowNames = (Vec<Vec<u8>>, Vec<Vec<u8>>);
+
+/// Truncate the column and row names to `width` characters, and pad the row names to exactly
+/// `width`, for fixed-width output. Warns if any names were truncated.
+fn fixed_width_names(
+    col_names: &[Vec<u8>],
+    row_names: &[Vec<u8>],
+    width: usize,
+) -> Result<ColumnAndRowNames> {
+    let (col_names, n_col_truncated) = truncate_names(col_names, width)?;
+    let (row_names, n_row_truncated) = truncate_names(row_names, width)?;
+    if n_col_truncated + n_row_truncated > 0 {
+        warn!(
+            "Truncated {} column and {} row name(s) to {} characters",
+            n_col_truncated, n_row_truncated, width
+        );
+    }
+    let row_names = row_names
+        .into_iter()
+        .map(|n| format!("{:<width$}", String::from_utf8_lossy(&n)).into_bytes())
+        .collect();
+    Ok((col_names, row_names))
+}
+
+/// Write a distance matrix as a long-form table or matrix, depending on the options
+fn write_matrix<T: Display>(
+    ostream: &mut Box<dyn Write>,
+    opts: &Opt,
+    matrix: &Array2<T>,
+    col_names: &[Vec<u8>],
+    row_names: &[Vec<u8>],
+    intra: bool,
+) -> Result<()> {
+    if opts.long_form {
+        info!("Writing long-form table...");
+        matrix
+            .to_long(ostream, opts.delimiter, col_names, row_names)
+            .context("Failed to write output table")?;
+    } else if opts.pretty && matrix.nrows().max(matrix.ncols()) <= PRETTY_MAX_SEQS {
+        info!("Writing pretty table...");
+        match opts.name_width {
+            None => matrix.to_pretty(ostream, col_names, row_names),
+            Some(width) => {
+                let (col_names, row_names) = fixed_width_names(col_names, row_names, width)?;
+                matrix.to_pretty(ostream, &col_names, &row_names)
+            }
+        }
+        .context("Failed to write output table")?;
+    } else if opts.format.snps_only() {
+        bail!(
+            "{:?} output is only available for the raw SNP distance",
+            opts.format
+        );
+    } else if opts.format == OutputFormat::Condensed {
+        if !intra {
+            bail!("Condensed output is only available for a single alignment");
+        }
+        info!("Writing condensed matrix...");
+        matrix
+            .to_condensed(ostream, opts.include_diagonal)
+            .context("Failed to write output table")?;
+    } else if opts.format == OutputFormat::R {
+        info!("Writing matrix for R...");
+        matrix
+            .to_r(
+                ostream,
+                opts.delimiter,
+                col_names,
+                row_names,
+                opts.blank_diagonal && intra,
+            )
+            .context("Failed to write output table")?;
+    } else {
+        if opts.pretty {
+            warn!(
+                "Matrix is too large for --pretty (> {} sequences); writing the plain matrix",
+                PRETTY_MAX_SEQS
+            );
+        }
+        info!("Writing matrix...");
+        matrix
+            .to_csv(
+                ostream,
+                opts.delimiter,
+                col_names,
+                row_names,
+                opts.blank_diagonal && intra,
+                opts.header(),
+            )
+            .context("Failed to write output table")?;
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let opts = Opt::parse();
+    // outputs are only flushed and closed once `run` returns, so they are moved into place here
+    let result = run(opts);
+    finish_atomic_outputs(result.is_ok())?;
+    result
+}
+
+fn run(mut opts: Opt) -> Result<()> {
+    if opts.version_json {
+        println!("{}", version_json());
+        return Ok(());
+    }
+    if opts.report == Report::Full && opts.model != Model::Raw {
+        bail!("A distance model cannot be used with the full report");
+    }
+    opts.check_raw_only()?;
+    if opts.max_dist.is_some()
+        && !matches!(
+            opts.format,
+            OutputFormat::Edgelist | OutputFormat::Graphml | OutputFormat::Sparse
+        )
+    {
+        bail!("--max-dist can only be used with the edgelist, graphml, or sparse formats");
+    }
+    if opts.group_separator == Some(opts.delimiter) {
+        bail!("--group-separator cannot be the same as the delimiter");
+    }
+    if opts.group_digits && opts.format.snps_only() {
+        bail!(
+            "--group-digits cannot be used with {:?} output",
+            opts.format
+        );
+    }
+    if opts.output.as_deref().is_some_and(is_stdout)
+        && (opts.also_normalized || opts.atomic_output || opts.format == OutputFormat::Sparse)
+    {
+        bail!(
+            "--also-normalized, --atomic-output, and sparse output need a file path for --output"
+        );
+    }
+    if opts.format == OutputFormat::Sparse && opts.output.is_none() {
+        bail!("Sparse output requires --output as the names are written to a sidecar file");
+    }
+    if opts.format == OutputFormat::R && opts.no_header {
+        bail!("--no-header cannot be used with R output as R needs the header for the names");
+    }
+    if opts.format == OutputFormat::Hdf5 {
+        if !cfg!(feature = "hdf5") {
+            bail!("HDF5 output requires psdm to be built with the hdf5 feature");
+        }
+        // the HDF5 file is written directly to the path, so it cannot be compressed or moved
+        match opts.output.as_deref() {
+            Some(p) if !is_stdout(p) => {
+                if opts.atomic_output
+                    || opts.output_compression.is_some()
+                    || OutputCompression::from_path(p) != OutputCompression::None
+                {
+                    bail!("HDF5 output cannot be used with compressed or atomic output");
+                }
+            }
+            _ => bail!("HDF5 output requires a file path for --output"),
+        }
+    }
+    if opts.format == OutputFormat::Bin && opts.line_ending != LineEnding::Lf {
+        bail!("--line-ending cannot be used with binary output");
+    }
+    if opts.alignments.len() > 2 && !opts.all_pairs {
+        bail!("More than two alignments can only be given with --all-pairs");
+    }
+    if opts.gap_runs && opts.report != Report::Full {
+        bail!("--gap-runs can only be used with the full report");
+    }
+    if opts.case_aware && opts.report != Report::Full {
+        bail!("--case-aware can only be used with the full report");
+    }
+    if opts.bootstrap == Some(0) {
+        bail!("--bootstrap needs at least one replicate");
+    }
 
     // setup logging
     let log_lvl = if opts.quiet {
@@ -94,10 +2609,19 @@ fn main() -> Result<()> {
         LevelFilter::Info
     };
     let mut log_builder = env_logger::builder();
-    log_builder
-        .filter(None, log_lvl)
-        .format_module_path(false)
-        .init();
+    log_builder.filter(None, log_lvl).format_module_path(false);
+    match opts.log_file {
+        None => log_builder.init(),
+        Some(ref p) => {
+            let file = File::create(p).context("Failed to create log file")?;
+            let logger = TeeLogger {
+                inner: log_builder.build(),
+                file: Mutex::new(file),
+            };
+            log::set_max_level(std::cmp::max(logger.inner.filter(), LevelFilter::Warn));
+            log::set_boxed_logger(Box::new(logger))?;
+        }
+    }
 
     // set the global default number of threads for rayon
     rayon::ThreadPoolBuilder::new()
@@ -105,123 +2629,450 @@ fn main() -> Result<()> {
         .build_global()?;
 
     info!("Using {} thread(s)", rayon::current_num_threads());
-
-    let mut ostream: Box<dyn Write> = match opts.output {
-        None => Box::new(stdout()),
-        Some(ref p) => {
-            let file = File::create(p).context("Failed to create output file")?;
-            Box::new(BufWriter::new(file))
+    if let Ok(n_cpus) = std::thread::available_parallelism() {
+        if opts.threads > n_cpus.get() {
+            warn!(
+                "Requested {} threads, but only {} cores are available. This may slow things down",
+                opts.threads, n_cpus
+            );
         }
-    };
+    }
 
-    let mut reader1 = niffler::from_path(&opts.alignments[0])
-        .map(|(r, _)| BufReader::new(r))
-        .map(fasta::Reader::new)
+    if opts.self_test {
+        return self_test(&opts);
+    }
+
+    if let Some(dir) = opts.output.as_deref().filter(|p| is_dir_path(p)) {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create output directory {}", dir.display()))?;
+        let path = dir.join(opts.output_file_name());
+        info!("Writing output to {}", path.display());
+        opts.output = Some(path);
+    }
+
+    if opts.all_pairs {
+        all_pairs(&opts)?;
+        info!("Done!");
+        return Ok(());
+    }
+
+    let mut ostream = match opts.also_normalized {
+        true => create_output(&opts, Some(&normalized_output_path(&opts, "snps"))),
+        false => create_output(&opts, opts.output.as_deref()),
+    }?;
+
+    if let [ref a, ref b] = opts.compare_matrices[..] {
+        compare_matrices(&opts, &mut ostream, a, b)?;
+        info!("Done!");
+        return Ok(());
+    }
+    if let Some(ref p) = opts.from_matrix {
+        analyse_matrix(&opts, &mut ostream, p)?;
+        info!("Done!");
+        return Ok(());
+    }
+
+    if opts.n_penalty.is_some() {
+        // N is scored by the penalty, so it must not be masked as ignored
+        opts.transformer.unignore(b"Nn");
+    }
+
+    let input_format = |p: &Path| opts.format_in.unwrap_or_else(|| InputFormat::from_path(p));
+
+    let reader1 = open_alignment(&opts.alignments[0])
+        .map(BufReader::new)
         .context("Could not open first alignment file")?;
 
     info!("Loading first alignment file...");
-    let (names1, seqs1) = opts
-        .transformer
-        .load_alignment(&mut reader1, 0)
-        .context("Failed to load first alignment file")?;
+    let (mut names1, mut seqs1) = load_alignment(
+        &opts.transformer,
+        reader1,
+        input_format(&opts.alignments[0]),
+        0,
+    )
+    .context("Failed to load first alignment file")?;
     info!(
         "Loaded {} sequences with length {}bp",
         seqs1.len(),
         seqs1[0].len()
     );
 
-    let (names2, seqs2) = match opts.alignments.get(1) {
+    // passing the same file twice would otherwise compute the full NxN matrix
+    let second_alignment = match opts.alignments.get(1) {
+        Some(p) if same_file(&opts.alignments[0], p) => {
+            warn!("Both alignments are the same file; computing intra-alignment distances instead");
+            None
+        }
+        p => p,
+    };
+
+    let (mut names2, mut seqs2) = match second_alignment {
         Some(p) => {
-            let mut reader2 = niffler::from_path(p)
-                .map(|(r, _)| BufReader::new(r))
-                .map(fasta::Reader::new)
+            let reader2 = open_alignment(p)
+                .map(BufReader::new)
                 .context("Could not open second alignment file")?;
             info!("Loading second alignment file...");
-            let (n, s) = opts
-                .transformer
-                .load_alignment(&mut reader2, seqs1[0].len())
-                .context("Failed to load second alignment file")?;
+            let (n, s) =
+                load_alignment(&opts.transformer, reader2, input_format(p), seqs1[0].len())
+                    .context("Failed to load second alignment file")?;
             info!("Loaded {} sequences with length {}bp", s.len(), s[0].len());
             (Some(n), Some(s))
         }
         None => (None, None),
     };
 
-    let n_seqs1 = seqs1.len();
-    let n_seqs2: usize = match seqs2 {
-        None => 0,
-        Some(ref s) => s.len(),
-    };
+    if let (Some(n2), Some(s2)) = (&mut names2, &mut seqs2) {
+        let first_ids: HashMap<&[u8], usize> = names1
+            .iter()
+            .enumerate()
+            .map(|(i, n)| (n.as_slice(), i))
+            .collect();
+        let shared: Vec<(usize, usize)> = n2
+            .iter()
+            .enumerate()
+            .filter_map(|(j, n)| first_ids.get(n.as_slice()).map(|&i| (i, j)))
+            .collect();
+        if opts.intra_from_pair {
+            let conflicting = shared.iter().filter(|&&(i, j)| seqs1[i] != s2[j]).count();
+            if conflicting > 0 {
+                warn!(
+                    "{} shared sequence ID(s) have different sequences in the two alignments; \
+                     using the sequence from the first alignment",
+                    conflicting
+                );
+            }
+            let is_shared: HashSet<usize> = shared.iter().map(|&(_, j)| j).collect();
+            for (j, (n, s)) in n2.drain(..).zip(s2.drain(..)).enumerate() {
+                if !is_shared.contains(&j) {
+                    names1.push(n);
+                    seqs1.push(s);
+                }
+            }
+            names2 = None;
+            seqs2 = None;
+            info!(
+                "Combined the alignments into {} sequences for intra-alignment distances",
+                seqs1.len()
+            );
+        } else if !shared.is_empty() {
+            warn!(
+                "{} sequence ID(s) are in both alignments, so the matrix is not symmetric and \
+                 their distances to themselves may be non-zero. Use --intra-from-pair to combine \
+                 the alignments instead",
+                shared.len()
+            );
+        }
+    }
 
-    // for intra-alignment distances, we don't need to compute the whole NxN matrix so we just
-    // generate the lower-left triangle (and the diagonal for labelling reasons).
-    let pairwise_indices: Vec<Vec<usize>> = match n_seqs2 {
-        0 => (0..n_seqs1).combinations_with_replacement(2).collect(),
-        i => iproduct!(0..n_seqs1, 0..i)
-            .map(|t| vec![t.0, t.1])
-            .collect(),
-    };
+    if let Some(n) = opts.subsample {
+        let seed = opts.seed();
+        info!("Subsampling {} sequences (seed {})...", n, seed);
+        let mut rng = fastrand::Rng::with_seed(seed);
+        subsample(&mut names1, &mut seqs1, n, &mut rng);
+        if let (Some(names2), Some(seqs2)) = (&mut names2, &mut seqs2) {
+            subsample(names2, seqs2, n, &mut rng);
+        }
+    }
 
-    let num_items = pairwise_indices.len();
-    let counter = Arc::new(AtomicUsize::new(0));
-    // make the progress interval every 50 pairwise operations or every 1%, whichever is smaller
-    let progress_interval = std::cmp::min((num_items as f64 / 100.0).ceil() as usize, 100);
-    info!("Calculating {num_items} pairwise distances...",);
-    let dists: Vec<u64> = pairwise_indices
-        .as_slice()
-        .into_par_iter()
-        .map_with(Arc::clone(&counter), |counter, ix| {
-            let i = ix[0];
-            let j = ix[1];
-            let distance = match &seqs2 {
-                None if i == j => 0, // distance between a sequence and itself
-                None => hamming_distance(&seqs1[i], &seqs1[j]),
-                Some(ref s) => hamming_distance(&seqs1[i], &s[j]),
-            };
+    let alignment_length = seqs1.first().map_or(0, Vec::len);
+
+    if let Some(ref p) = opts.base_freqs {
+        let counts = base_composition(seqs1.iter().chain(seqs2.iter().flatten()));
+        let total: u64 = counts.iter().sum();
+        let mut freqs_ostream = create_output(&opts, Some(p))?;
+        writeln!(
+            freqs_ostream,
+            "character{d}count{d}fraction",
+            d = opts.delimiter
+        )
+        .context("Failed to write base frequencies")?;
+        for (kind, count) in COMPOSITION_KINDS.iter().zip(counts) {
+            let fraction = (total > 0).then(|| count as f64 / total as f64);
+            writeln!(
+                freqs_ostream,
+                "{}{d}{}{d}{}",
+                kind,
+                count,
+                opts.format_float(fraction),
+                d = opts.delimiter
+            )
+            .context("Failed to write base frequencies")?;
+        }
+    }
 
-            // Update the counter
-            let current_count = counter.fetch_add(1, Ordering::SeqCst) + 1;
+    // the column of the alignment as read for each column left, to report positions with
+    let mut columns = opts.transformer.column_map(alignment_length)?;
 
-            // Optionally print progress every 1%
-            if opts.show_progress && current_count % progress_interval == 0 {
-                let progress = (current_count as f64 / num_items as f64) * 100.0;
-                eprint!(
-                    "\rProgress: {:.2}% ({} / {})",
-                    progress, current_count, num_items
-                );
-                match std::io::stderr().flush() {
-                    Ok(_) => (),
-                    Err(e) => eprintln!("Error occurred when flushing stderr: {:?}", e),
-                }
+    if opts.trim {
+        let mut all_seqs: Vec<&mut Vec<u8>> =
+            seqs1.iter_mut().chain(seqs2.iter_mut().flatten()).collect();
+        let (leading, trailing) = trim_flanks(&mut all_seqs);
+        columns.truncate(columns.len() - trailing);
+        columns.drain(..leading);
+        info!(
+            "Trimmed {} leading and {} trailing all-gap columns",
+            leading, trailing
+        );
+    }
+
+    if opts.complete_deletion {
+        let seqlen = seqs1[0].len();
+        let mut all_seqs: Vec<&mut Vec<u8>> =
+            seqs1.iter_mut().chain(seqs2.iter_mut().flatten()).collect();
+        let kept = complete_deletion(&mut all_seqs);
+        info!(
+            "Complete deletion kept {} of {} sites for comparison",
+            kept.len(),
+            seqlen
+        );
+        columns = kept.iter().map(|i| columns[*i]).collect();
+    }
+
+    if let Some(ref p) = opts.metadata {
+        let mut metadata = create_output(&opts, Some(p))?;
+        let compared_sites = seqs1.first().map_or(0, Vec::len);
+        writeln!(
+            metadata,
+            "alignment_length{d}{}\ncompared_sites{d}{}",
+            alignment_length,
+            compared_sites,
+            d = opts.delimiter
+        )
+        .context("Failed to write metadata")?;
+    }
+
+    if let Some(ref p) = opts.variant_report {
+        let sites: Vec<(usize, Alleles)> =
+            variable_sites(seqs1.iter().chain(seqs2.iter().flatten()))
+                .into_iter()
+                .map(|(i, alleles)| (columns[i], alleles))
+                .collect();
+        info!("Found {} variable sites", sites.len());
+        let mut report = create_output(&opts, Some(p))?;
+        let base = opts.transformer.coordinate_base();
+        write_variant_report(&mut report, opts.delimiter, &sites, base)
+            .context("Failed to write variant report")?;
+    }
+
+    if opts.compact {
+        let seqlen = seqs1[0].len();
+        let mut all_seqs: Vec<&mut Vec<u8>> =
+            seqs1.iter_mut().chain(seqs2.iter_mut().flatten()).collect();
+        let kept = compact(&mut all_seqs);
+        info!(
+            "Compact storage kept {} of {} sites (the variable ones)",
+            kept.len(),
+            seqlen
+        );
+        columns = kept.iter().map(|i| columns[*i]).collect();
+    }
+
+    if opts.vs_consensus {
+        if seqs2.is_some() {
+            bail!("--vs-consensus is only available for a single alignment");
+        }
+        info!("Computing distances to the consensus...");
+        let consensus = consensus(&seqs1, opts.ambiguous_consensus);
+        if let Some(ref p) = opts.spectrum {
+            let mut spectrum = Spectrum::new();
+            for seq in &seqs1 {
+                add_substitutions(&mut spectrum, &consensus, seq);
             }
+            let mut spectrum_ostream = create_output(&opts, Some(p))?;
+            write_spectrum(&mut spectrum_ostream, opts.delimiter, &spectrum)
+                .context("Failed to write substitution spectrum")?;
+        }
+        let distance = if opts.iupac {
+            iupac_distance
+        } else {
+            hamming_distance
+        };
+        for (name, seq) in names1.iter().zip(&seqs1) {
+            writeln!(
+                ostream,
+                "{}{}{}",
+                quote_field(name, opts.delimiter),
+                opts.delimiter,
+                distance(seq, &consensus)
+            )
+            .context("Failed to write output table")?;
+        }
+        info!("Done!");
+        return Ok(());
+    }
 
-            distance
-        })
-        .collect();
+    if let Some(ref p) = opts.panel {
+        if seqs2.is_some() {
+            bail!("--panel is only available for a single alignment");
+        }
+        let reader = open_alignment(p)
+            .map(BufReader::new)
+            .context("Could not open panel file")?;
+        info!("Loading panel...");
+        let (panel_names, panel_seqs) =
+            load_alignment(&opts.transformer, reader, input_format(p), seqs1[0].len())
+                .context("Failed to load panel")?;
+        if panel_seqs.is_empty() {
+            bail!("Panel has no sequences");
+        }
+        info!("Loaded {} panel sequences", panel_seqs.len());
+        info!("Computing distances to the nearest panel sequence...");
+        let distance = if opts.iupac {
+            iupac_distance
+        } else {
+            hamming_distance
+        };
+        let nearest: Vec<(usize, u64)> = seqs1
+            .par_iter()
+            .map(|seq| {
+                panel_seqs
+                    .iter()
+                    .map(|p| distance(seq, p))
+                    .enumerate()
+                    .min_by_key(|(_, d)| *d)
+                    .unwrap_or_default()
+            })
+            .collect();
+        for (name, (i, d)) in names1.iter().zip(nearest) {
+            writeln!(
+                ostream,
+                "{}{d}{}{d}{}",
+                quote_field(name, opts.delimiter),
+                quote_field(&panel_names[i], opts.delimiter),
+                d,
+                d = opts.delimiter
+            )
+            .context("Failed to write output table")?;
+        }
+        info!("Done!");
+        return Ok(());
+    }
 
-    // Finish the progress bar
-    if opts.show_progress {
-        eprintln!();
+    if let Some(ref pair) = opts.explain {
+        let (a, b) = pair.split_once(',').with_context(|| {
+            format!("Expected two comma-separated names for --explain, got {pair}")
+        })?;
+        let find = |names: &[Vec<u8>], name: &str| {
+            names
+                .iter()
+                .position(|n| n == name.as_bytes())
+                .with_context(|| format!("{name} is not in the alignment"))
+        };
+        let i = find(&names1, a)?;
+        let (row_names, row_seqs) = match (&names2, &seqs2) {
+            (Some(n), Some(s)) => (n, s),
+            _ => (&names1, &seqs1),
+        };
+        let j = find(row_names, b)?;
+        let (seq_a, seq_b) = (&seqs1[i], &row_seqs[j]);
+        writeln!(ostream, "position\t{a}\t{b}").context("Failed to write output table")?;
+        for pos in differing_positions(seq_a, seq_b, opts.iupac) {
+            writeln!(
+                ostream,
+                "{}\t{}\t{}",
+                columns[pos] + opts.transformer.coordinate_base(),
+                seq_a[pos] as char,
+                seq_b[pos] as char
+            )
+            .context("Failed to write output table")?;
+        }
+        info!("Done!");
+        return Ok(());
     }
 
-    let matrix =
-        if n_seqs2 > 0 {
-            Array::from_shape_vec((n_seqs1, n_seqs2), dists).context(
-            "Failed to create matrix. This shouldn't happen, please raise an issue on GitHub",
-        )?.t().to_owned()
+    if opts.count_sites_only {
+        info!("Counting compared sites...");
+        let n_seqs2 = seqs2.as_ref().map_or(0, Vec::len);
+        let pairwise_indices: Vec<Vec<usize>> = all_pairwise_indices(seqs1.len(), n_seqs2)
+            .into_iter()
+            .filter(|ix| n_seqs2 > 0 || ix[0] != ix[1])
+            .collect();
+        check_max_pairs(&opts, pairwise_indices.len())?;
+        let row_seqs = seqs2.as_ref().unwrap_or(&seqs1);
+        let sites = compute_pairwise(&pairwise_indices, &opts, |i, j| {
+            compared_sites(&seqs1[i], &row_seqs[j])
+        });
+        let labels = ["min", "25%", "50%", "75%", "95%", "max"];
+        let site_quantiles = quantiles(sites, &[0.0, 0.25, 0.5, 0.75, 0.95, 1.0]);
+        let mut rows = vec![
+            ("columns".to_string(), seqs1[0].len().to_string()),
+            (
+                "complete_columns".to_string(),
+                complete_columns(seqs1.iter().chain(seqs2.iter().flatten())).to_string(),
+            ),
+            ("pairs".to_string(), pairwise_indices.len().to_string()),
+        ];
+        for (label, q) in labels.iter().zip(site_quantiles.iter().flatten()) {
+            rows.push((format!("{}_sites", label), q.to_string()));
+        }
+        writeln!(ostream, "statistic{}value", opts.delimiter)
+            .context("Failed to write output table")?;
+        for (statistic, value) in rows {
+            writeln!(ostream, "{}{}{}", statistic, opts.delimiter, value)
+                .context("Failed to write output table")?;
+        }
+        info!("Done!");
+        return Ok(());
+    }
+
+    if let Some(threshold) = opts.cluster {
+        if seqs2.is_some() {
+            bail!("--cluster is only available for a single alignment");
+        }
+        let n = seqs1.len();
+        check_max_pairs(&opts, n * n.saturating_sub(1) / 2)?;
+        info!("Clustering sequences within {} SNPs...", threshold);
+        let distance = if opts.iupac {
+            iupac_distance
         } else {
-            let mut mtx = Array::zeros((n_seqs1, n_seqs1));
-            for (ix, d) in pairwise_indices.iter().zip(dists) {
-                let i = ix[0];
-                let j = ix[1];
-                mtx[[i, j]] = d;
-                if i != j {
-                    mtx[[j, i]] = d;
-                }
-            }
-            mtx
+            hamming_distance
+        };
+        let links: Vec<(usize, usize)> = (0..n)
+            .into_par_iter()
+            .flat_map_iter(|i| (0..i).map(move |j| (i, j)))
+            .filter(|&(i, j)| distance(&seqs1[i], &seqs1[j]) <= threshold)
+            .collect();
+        let clusters = single_linkage(n, links);
+        write_clusters(&mut ostream, opts.delimiter, &names1, &clusters)
+            .context("Failed to write output table")?;
+        info!("Done!");
+        return Ok(());
+    }
+
+    let dedup1 = opts.dedup.then(|| dedup_alignment(&mut names1, &mut seqs1));
+    let dedup2 = match (&mut names2, &mut seqs2) {
+        (Some(n), Some(s)) if opts.dedup => Some(dedup_alignment(n, s)),
+        _ => None,
+    };
+    if let Some(ref p) = opts.dedup_map {
+        let mut map_ostream = create_output(&opts, Some(p))?;
+        let deduped = dedup1.iter().zip([&names1]);
+        let deduped = deduped.chain(dedup2.iter().zip(names2.iter()));
+        for (d, names) in deduped {
+            write_dedup_map(&mut map_ostream, opts.delimiter, d, names)
+                .context("Failed to write dedup map")?;
+        }
+    }
+
+    let n_seqs1 = seqs1.len();
+    let n_seqs2: usize = match seqs2 {
+        None => 0,
+        Some(ref s) => s.len(),
+    };
+
+    if opts.shape {
+        let expanded = |n: usize, d: &Option<Dedup>| match d {
+            Some(d) if opts.expand => d.names.len(),
+            _ => n,
+        };
+        let cols = expanded(n_seqs1, &dedup1);
+        let (rows, kind) = match n_seqs2 {
+            0 => (cols, "intra"),
+            n => (expanded(n, &dedup2), "inter"),
         };
-    info!("Finished computing distances");
+        writeln!(ostream, "{rows}\t{cols}\t{kind}").context("Failed to write output")?;
+        return Ok(());
+    }
 
     let row_names: &Vec<Vec<u8>> = match &names2 {
         Some(n) => n,
@@ -229,16 +3080,416 @@ fn main() -> Result<()> {
     };
     let col_names: &Vec<Vec<u8>> = &names1;
 
-    if opts.long_form {
-        info!("Writing long-form table...");
-        matrix
-            .to_long(&mut ostream, opts.delimiter, col_names, row_names)
-            .context("Failed to write output table")?;
-    } else {
-        info!("Writing matrix...");
+    // for intra-alignment distances, we don't need to compute the whole NxN matrix so we just
+    // generate the lower-left triangle (and the diagonal for labelling reasons).
+    let within_groups = match opts.within_groups {
+        Some(ref p) => Some(load_group_labels(p, col_names, row_names, n_seqs2 == 0)?),
+        None => None,
+    };
+    let pairwise_indices: Vec<Vec<usize>> = match (&opts.pairs_file, &within_groups) {
+        (Some(p), _) => {
+            let reader = niffler::from_path(p)
+                .map(|(r, _)| BufReader::new(r))
+                .context("Could not open pairs file")?;
+            parse_pairs(reader, col_names, row_names).context("Failed to load pairs file")?
+        }
+        (None, Some(groups)) => within_group_indices(groups, n_seqs2 == 0),
+        (None, None) => all_pairwise_indices(n_seqs1, n_seqs2),
+    };
+    check_max_pairs(&opts, pairwise_indices.len())?;
+
+    if let Some(ref p) = opts.spectrum {
+        info!("Counting substitutions...");
+        let row_seqs = seqs2.as_ref().unwrap_or(&seqs1);
+        let spectrum = pairwise_spectrum(&pairwise_indices, &seqs1, row_seqs);
+        let mut spectrum_ostream = create_output(&opts, Some(p))?;
+        write_spectrum(&mut spectrum_ostream, opts.delimiter, &spectrum)
+            .context("Failed to write substitution spectrum")?;
+    }
+
+    if let Some(ref p) = opts.sites_matrix {
+        info!("Counting compared sites...");
+        let row_seqs = seqs2.as_ref().unwrap_or(&seqs1);
+        let sites = compute_pairwise(&pairwise_indices, &opts, |i, j| {
+            compared_sites(&seqs1[i], &row_seqs[j])
+        });
+        let matrix = to_matrix(sites, &pairwise_indices, n_seqs1, n_seqs2)?;
+        let mut sites_ostream = create_output(&opts, Some(p))?;
         matrix
-            .to_csv(&mut ostream, opts.delimiter, col_names, row_names)
+            .to_csv(
+                &mut sites_ostream,
+                opts.delimiter,
+                col_names,
+                row_names,
+                false,
+                opts.header(),
+            )
+            .context("Failed to write compared sites matrix")?;
+    }
+
+    let weights = match opts.sample_weights {
+        Some(ref p) => {
+            let reader = BufReader::new(File::open(p).context("Failed to open weights file")?);
+            Some(parse_weights(reader, col_names).context("Failed to parse weights file")?)
+        }
+        None => None,
+    };
+
+    let num_items = pairwise_indices.len();
+    info!("Calculating {num_items} pairwise distances...",);
+    let seqs2 = seqs2.as_ref();
+
+    match opts.report {
+        Report::Full => {
+            let stats = compute_pairwise(&pairwise_indices, &opts, |i, j| match seqs2 {
+                None => pair_stats(&seqs1[i], &seqs1[j], opts.iupac, opts.case_aware),
+                Some(s) => pair_stats(&seqs1[i], &s[j], opts.iupac, opts.case_aware),
+            });
+            info!("Finished computing distances");
+
+            info!("Writing full report...");
+            let columns = ReportColumns {
+                soft_matches: opts.iupac,
+                max_gap_run: opts.gap_runs,
+                case_diffs: opts.case_aware,
+                identity_precision: opts.identity_precision,
+            };
+            if opts.pairs_file.is_some() {
+                write_pairs_full_report(
+                    &mut ostream,
+                    opts.delimiter,
+                    &pairwise_indices,
+                    &stats,
+                    col_names,
+                    row_names,
+                    columns,
+                )
+            } else {
+                let matrix = to_matrix(stats, &pairwise_indices, n_seqs1, n_seqs2)?;
+                write_full_report(
+                    &mut ostream,
+                    opts.delimiter,
+                    &matrix,
+                    col_names,
+                    row_names,
+                    columns,
+                )
+            }
             .context("Failed to write output table")?;
+        }
+        Report::Snps if opts.also_normalized => {
+            let stats = compute_pairwise(&pairwise_indices, &opts, |i, j| match seqs2 {
+                None => pair_stats(&seqs1[i], &seqs1[j], opts.iupac, opts.case_aware),
+                Some(s) => pair_stats(&seqs1[i], &s[j], opts.iupac, opts.case_aware),
+            });
+            info!("Finished computing distances");
+            let present = |seqs: &[Vec<u8>]| -> Vec<u64> {
+                seqs.iter().map(|s| compared_sites(s, s)).collect()
+            };
+            let present1 = present(&seqs1);
+            let present2 = seqs2.map(|s| present(s));
+            let seqlen = seqs1[0].len() as u64;
+            let props = stats
+                .iter()
+                .zip(&pairwise_indices)
+                .map(|(s, ix)| {
+                    let denominator = match opts.normalize_by {
+                        NormalizeBy::Compared => s.sites,
+                        NormalizeBy::Total => seqlen,
+                        NormalizeBy::MinPresent => {
+                            let row_present = present2.as_ref().unwrap_or(&present1);
+                            present1[ix[0]].min(row_present[ix[1]])
+                        }
+                    };
+                    let prop = (denominator > 0).then(|| s.snps as f64 / denominator as f64);
+                    opts.format_float(prop)
+                })
+                .collect();
+            let snps: Vec<u64> = stats.iter().map(|s| s.snps).collect();
+            if opts.quantiles {
+                report_quantiles(&snps, &pairwise_indices, n_seqs2 == 0);
+            }
+            if let Some(max) = opts.assert_max {
+                assert_max_distance(
+                    &snps,
+                    &pairwise_indices,
+                    col_names,
+                    row_names,
+                    n_seqs2 == 0,
+                    max,
+                )?;
+            }
+            if let Some(n) = opts.outliers {
+                let matrix = to_matrix(snps.clone(), &pairwise_indices, n_seqs1, n_seqs2)?;
+                report_outliers(&matrix, row_names, n, n_seqs2 == 0, weights.as_deref());
+            }
+            write_snps(
+                &mut ostream,
+                &opts,
+                snps,
+                &pairwise_indices,
+                col_names,
+                row_names,
+                n_seqs2,
+            )?;
+            let mut prop_ostream =
+                create_output(&opts, Some(&normalized_output_path(&opts, "prop")))?;
+            write_distances(
+                &mut prop_ostream,
+                &opts,
+                props,
+                &pairwise_indices,
+                col_names,
+                row_names,
+                n_seqs2,
+            )?;
+        }
+        Report::Snps if opts.n_penalty.is_some() => {
+            let penalty = opts.n_penalty.unwrap_or_default();
+            let dists = compute_pairwise(&pairwise_indices, &opts, |i, j| {
+                let d = match seqs2 {
+                    None => n_penalty_distance(&seqs1[i], &seqs1[j], penalty),
+                    Some(s) => n_penalty_distance(&seqs1[i], &s[j], penalty),
+                };
+                opts.format_float(Some(d))
+            });
+            info!("Finished computing distances");
+            write_distances(
+                &mut ostream,
+                &opts,
+                dists,
+                &pairwise_indices,
+                col_names,
+                row_names,
+                n_seqs2,
+            )?;
+        }
+        Report::Snps if opts.bootstrap.is_some() => {
+            let n_replicates = opts.bootstrap.unwrap_or_default();
+            let seed = opts.seed();
+            info!(
+                "Computing {} bootstrap replicates (seed {})...",
+                n_replicates, seed
+            );
+            let mut rng = fastrand::Rng::with_seed(seed);
+            let seqlen = seqs1[0].len();
+            let replicates: Vec<Vec<u32>> = (0..n_replicates)
+                .map(|_| bootstrap_weights(&mut rng, seqlen))
+                .collect();
+            let stats = compute_pairwise(&pairwise_indices, &opts, |i, j| {
+                let other = match seqs2 {
+                    None => &seqs1[j],
+                    Some(s) => &s[j],
+                };
+                let dists: Vec<f64> = replicates
+                    .iter()
+                    .map(|w| weighted_distance(&seqs1[i], other, w, opts.iupac) as f64)
+                    .collect();
+                mean_sd(&dists)
+            });
+            info!("Finished computing distances");
+            let means = stats
+                .iter()
+                .map(|(mean, _)| opts.format_float(Some(*mean)))
+                .collect();
+            write_distances(
+                &mut ostream,
+                &opts,
+                means,
+                &pairwise_indices,
+                col_names,
+                row_names,
+                n_seqs2,
+            )?;
+            if let Some(ref p) = opts.bootstrap_sd {
+                let sds = stats
+                    .iter()
+                    .map(|(_, sd)| opts.format_float(Some(*sd)))
+                    .collect();
+                let mut sd_ostream = create_output(&opts, Some(p))?;
+                write_distances(
+                    &mut sd_ostream,
+                    &opts,
+                    sds,
+                    &pairwise_indices,
+                    col_names,
+                    row_names,
+                    n_seqs2,
+                )?;
+            }
+        }
+        Report::Snps if opts.adjust_missing => {
+            let seqlen = seqs1[0].len() as f64;
+            let dists = compute_pairwise(&pairwise_indices, &opts, |i, j| {
+                let stats = match seqs2 {
+                    None => pair_stats(&seqs1[i], &seqs1[j], opts.iupac, false),
+                    Some(s) => pair_stats(&seqs1[i], &s[j], opts.iupac, false),
+                };
+                let adjusted =
+                    (stats.sites > 0).then(|| stats.snps as f64 * seqlen / stats.sites as f64);
+                opts.format_float(adjusted)
+            });
+            info!("Finished computing distances");
+            write_distances(
+                &mut ostream,
+                &opts,
+                dists,
+                &pairwise_indices,
+                col_names,
+                row_names,
+                n_seqs2,
+            )?;
+        }
+        Report::Snps if opts.model == Model::Raw => {
+            let distance =
+                raw_distance(seqs1.iter().chain(seqs2.into_iter().flatten()), opts.iupac);
+            let reversed: Option<Vec<Vec<u8>>> = opts.orient.then(|| {
+                info!("Reverse complementing sequences...");
+                seqs2
+                    .unwrap_or(&seqs1)
+                    .par_iter()
+                    .map(|s| reverse_complement(s))
+                    .collect()
+            });
+            let f = |i: usize, j: usize| {
+                let d = match seqs2 {
+                    None if i == j => return 0, // distance between a sequence and itself
+                    None => distance(&seqs1[i], &seqs1[j]),
+                    Some(s) => distance(&seqs1[i], &s[j]),
+                };
+                match reversed {
+                    Some(ref r) => d.min(distance(&seqs1[i], &r[j])),
+                    None => d,
+                }
+            };
+            let dists = match opts.checkpoint {
+                Some(ref p) => {
+                    // the sequences are hashed after loading, so this covers the options that
+                    // change them too
+                    let mut fingerprint = Fingerprint::default();
+                    (col_names, row_names, &seqs1, seqs2, &pairwise_indices).hash(&mut fingerprint);
+                    (opts.iupac, opts.orient).hash(&mut fingerprint);
+                    let fingerprint = fingerprint.finish();
+                    compute_with_checkpoint(
+                        p,
+                        opts.resume,
+                        &pairwise_indices,
+                        fingerprint,
+                        &opts,
+                        f,
+                    )?
+                }
+                None => compute_pairwise(&pairwise_indices, &opts, f),
+            };
+            info!("Finished computing distances");
+            let (dists, pairwise_indices, col_names, row_names, n_seqs1, n_seqs2) = match dedup1 {
+                Some(ref d1) if opts.expand => {
+                    let rows = dedup2.as_ref();
+                    let (dists, pairwise_indices) =
+                        expand_dedup(dists, &pairwise_indices, n_seqs1, n_seqs2, d1, rows)?;
+                    let row_names = &rows.unwrap_or(d1).names;
+                    let n_seqs2 = rows.map_or(0, |d| d.names.len());
+                    (
+                        dists,
+                        pairwise_indices,
+                        &d1.names,
+                        row_names,
+                        d1.names.len(),
+                        n_seqs2,
+                    )
+                }
+                _ => (
+                    dists,
+                    pairwise_indices,
+                    col_names,
+                    row_names,
+                    n_seqs1,
+                    n_seqs2,
+                ),
+            };
+            if opts.quantiles {
+                report_quantiles(&dists, &pairwise_indices, n_seqs2 == 0);
+            }
+            if let Some(max) = opts.assert_max {
+                assert_max_distance(
+                    &dists,
+                    &pairwise_indices,
+                    col_names,
+                    row_names,
+                    n_seqs2 == 0,
+                    max,
+                )?;
+            }
+            if let Some(n) = opts.outliers {
+                let matrix = to_matrix(dists.clone(), &pairwise_indices, n_seqs1, n_seqs2)?;
+                report_outliers(&matrix, row_names, n, n_seqs2 == 0, weights.as_deref());
+            }
+            if let Some(ref p) = opts.tree {
+                if n_seqs2 > 0 {
+                    bail!("--tree is only available for a single alignment");
+                }
+                let matrix = to_matrix(dists.clone(), &pairwise_indices, n_seqs1, n_seqs2)?;
+                info!("Building neighbour-joining tree...");
+                let newick = neighbour_joining(&matrix.mapv(|d| d as f64), col_names);
+                let mut tree_ostream = create_output(&opts, Some(p))?;
+                writeln!(tree_ostream, "{}", newick).context("Failed to write tree")?;
+            }
+            match (&within_groups, &opts.groups) {
+                (Some(groups), _) => write_within_groups(
+                    &mut ostream,
+                    opts.delimiter,
+                    groups,
+                    &pairwise_indices,
+                    &dists,
+                    col_names,
+                    row_names,
+                )?,
+                (None, Some(p)) => {
+                    let matrix = to_matrix(dists, &pairwise_indices, n_seqs1, n_seqs2)?;
+                    write_group_means(
+                        &mut ostream,
+                        &opts,
+                        p,
+                        &matrix,
+                        col_names,
+                        row_names,
+                        n_seqs2 == 0,
+                    )?
+                }
+                (None, None) => write_snps(
+                    &mut ostream,
+                    &opts,
+                    dists,
+                    &pairwise_indices,
+                    col_names,
+                    row_names,
+                    n_seqs2,
+                )?,
+            }
+        }
+        Report::Snps => {
+            let freqs = base_frequencies(seqs1.iter().chain(seqs2.into_iter().flatten()));
+            let dists = compute_pairwise(&pairwise_indices, &opts, |i, j| {
+                let counts = match seqs2 {
+                    None => substitution_counts(&seqs1[i], &seqs1[j]),
+                    Some(s) => substitution_counts(&seqs1[i], &s[j]),
+                };
+                opts.format_float(opts.model.distance(&counts, &freqs))
+            });
+            info!("Finished computing distances");
+            write_distances(
+                &mut ostream,
+                &opts,
+                dists,
+                &pairwise_indices,
+                col_names,
+                row_names,
+                n_seqs2,
+            )?;
+        }
+    }
+    if let Some(ref p) = opts.checkpoint {
+        std::fs::remove_file(p).context("Failed to remove checkpoint")?;
     }
     info!("Done!");
     Ok(())
@@ -256,6 +3507,228 @@ mod tests {
         assert!(result.is_err())
     }
 
+    #[test]
+    fn input_format_from_path() {
+        assert_eq!(
+            InputFormat::from_path(Path::new("aln.phy")),
+            InputFormat::Phylip
+        );
+        assert_eq!(
+            InputFormat::from_path(Path::new("aln.phylip.gz")),
+            InputFormat::Phylip
+        );
+        assert_eq!(
+            InputFormat::from_path(Path::new("aln.fa.gz")),
+            InputFormat::Fasta
+        );
+        assert_eq!(InputFormat::from_path(Path::new("aln")), InputFormat::Fasta);
+    }
+
+    #[test]
+    fn parse_weights_defaults_to_one() {
+        let names = vec![b"s0".to_vec(), b"s1".to_vec(), b"s2".to_vec()];
+        let data = b"s1 0.5\n\ns2\t2\nother 3\n";
+
+        let actual = parse_weights(&data[..], &names).unwrap();
+
+        assert_eq!(actual, vec![1.0, 0.5, 2.0])
+    }
+
+    #[test]
+    fn parse_weights_invalid_weight() {
+        let names = vec![b"s0".to_vec()];
+        let data = b"s0 -1\n";
+
+        let actual = parse_weights(&data[..], &names).unwrap_err();
+
+        assert!(actual.to_string().contains("Invalid weight -1 on line 1"))
+    }
+
+    #[test]
+    fn parse_groups_in_order_of_appearance() {
+        let data = b"s1 B\n\ns0\tA\ns2 B\n";
+
+        let (group_names, groups_by_id) = parse_groups(&data[..]).unwrap();
+
+        assert_eq!(group_names, vec![b"B".to_vec(), b"A".to_vec()]);
+        assert_eq!(groups_by_id[&b"s0".to_vec()], 1);
+        assert_eq!(groups_by_id[&b"s2".to_vec()], 0)
+    }
+
+    #[test]
+    fn assert_max_distance_names_most_distant_pair() {
+        let names = vec![b"s0".to_vec(), b"s1".to_vec(), b"s2".to_vec()];
+        let pairwise_indices: Vec<Vec<usize>> = triangle_indices(3).collect();
+        let dists = vec![9, 2, 5, 9, 3, 9];
+
+        assert!(assert_max_distance(&dists, &pairwise_indices, &names, &names, true, 5).is_ok());
+        let err = assert_max_distance(&dists, &pairwise_indices, &names, &names, true, 2)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "2 pair(s) have a distance above 2; the largest is 5 between s0 and s2"
+        )
+    }
+
+    #[test]
+    fn to_matrix_inter_alignment() {
+        // pairs are (column, row) with the first alignment as the columns
+        let pairwise_indices: Vec<Vec<usize>> =
+            iproduct!(0..3, 0..2).map(|(i, j)| vec![i, j]).collect();
+        let values: Vec<u64> = pairwise_indices
+            .iter()
+            .map(|ix| (ix[0] * 10 + ix[1]) as u64)
+            .collect();
+
+        let actual = to_matrix(values, &pairwise_indices, 3, 2).unwrap();
+        let expected = ndarray::arr2(&[[0, 10, 20], [1, 11, 21]]);
+
+        assert_eq!(actual, expected)
+    }
+
+    #[test]
+    fn crlf_writer_converts_line_endings() {
+        let mut writer = CrlfWriter { inner: vec![] };
+        write!(writer, ",s0\ns0,0\n").unwrap();
+        writeln!(writer, "x").unwrap();
+
+        assert_eq!(writer.inner, b",s0\r\ns0,0\r\nx\r\n".to_vec())
+    }
+
+    #[test]
+    fn flushing_writer_flushes_every_n_lines() {
+        let mut writer = FlushingWriter {
+            inner: BufWriter::new(vec![]),
+            every: 2,
+            lines: 0,
+        };
+        writeln!(writer, "a,b,1").unwrap();
+        assert!(writer.inner.get_ref().is_empty());
+
+        writeln!(writer, "b,a,1").unwrap();
+        assert_eq!(writer.inner.get_ref(), b"a,b,1\nb,a,1\n")
+    }
+
+    #[cfg(feature = "url")]
+    #[test]
+    fn check_path_exists_accepts_url() {
+        let url = "https://example.com/aln.fa.gz";
+        let actual = path_exists(OsStr::new(url)).unwrap();
+        assert_eq!(actual, PathBuf::from(url))
+    }
+
+    #[test]
+    fn render_progress() {
+        let progress = Progress {
+            format: "{percent}\t{done}/{total}\t{elapsed}\t{eta}",
+            precision: 1,
+            every: None,
+        };
+
+        let actual = progress.render(1, 3, 60.0);
+
+        assert_eq!(actual, "33.3\t1/3\t00:01:00\t00:02:00")
+    }
+
+    #[test]
+    fn alignment_stem_strips_extensions() {
+        assert_eq!(alignment_stem(Path::new("dir/gene1.fa.gz")), "gene1");
+        assert_eq!(alignment_stem(Path::new("gene2.phy")), "gene2");
+        assert_eq!(alignment_stem(Path::new("gene3")), "gene3");
+    }
+
+    #[test]
+    fn format_duration_as_hms() {
+        assert_eq!(format_duration(0.4), "00:00:00");
+        assert_eq!(format_duration(61.0), "00:01:01");
+        assert_eq!(format_duration(90061.0), "25:01:01");
+    }
+
+    #[test]
+    fn static_schedule_pins_chunks_to_threads() {
+        let opts = Opt::parse_from(["psdm", "--static-schedule", "tests/cases/aln1.fa"]);
+        let pairwise_indices: Vec<Vec<usize>> = (0..8).map(|i| vec![i, i]).collect();
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(3)
+            .build()
+            .unwrap();
+
+        let actual = pool.install(|| {
+            compute_pairwise(&pairwise_indices, &opts, |i, _| {
+                (i, rayon::current_thread_index())
+            })
+        });
+
+        let expected: Vec<_> = (0..8).map(|i| (i, Some(i / 3))).collect();
+        assert_eq!(actual, expected)
+    }
+
+    #[test]
+    fn output_compression_from_path() {
+        assert_eq!(
+            OutputCompression::from_path(Path::new("dists.csv.gz")),
+            OutputCompression::Gzip
+        );
+        assert_eq!(
+            OutputCompression::from_path(Path::new("dists.csv.zst")),
+            OutputCompression::Zstd
+        );
+        assert_eq!(
+            OutputCompression::from_path(Path::new("dists.csv")),
+            OutputCompression::None
+        );
+    }
+
+    #[test]
+    fn parse_invalid_compression_level() {
+        assert!(parse_compression_level("0").is_err());
+        assert!(parse_compression_level("10").is_err());
+        assert_eq!(parse_compression_level("9"), Ok(niffler::Level::Nine));
+    }
+
+    #[test]
+    fn parse_pairs_into_indices() {
+        let names = vec![b"s0".to_vec(), b"s1".to_vec(), b"s2".to_vec()];
+        let data = b"s0\ts2\n\ns1 s1\n";
+
+        let actual = parse_pairs(&data[..], &names, &names).unwrap();
+        let expected = vec![vec![0, 2], vec![1, 1]];
+
+        assert_eq!(actual, expected)
+    }
+
+    #[test]
+    fn parse_pairs_with_unknown_id() {
+        let names = vec![b"s0".to_vec(), b"s1".to_vec()];
+        let data = b"s0\ts1\ns0\tfoo\n";
+
+        let actual = parse_pairs(&data[..], &names, &names).unwrap_err();
+
+        assert!(actual.to_string().contains("foo on line 2"))
+    }
+
+    #[test]
+    fn same_file_with_different_paths() {
+        assert!(same_file(
+            Path::new("Cargo.toml"),
+            Path::new("./Cargo.toml")
+        ));
+        assert!(!same_file(Path::new("Cargo.toml"), Path::new("README.md")));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn check_path_exists_with_windows_paths() {
+        let backslashes = path_exists(OsStr::new(r"tests\cases\aln1.fa")).unwrap();
+        assert!(niffler::from_path(&backslashes).is_ok());
+
+        let cwd = std::env::current_dir().unwrap();
+        let verbatim = format!(r"\\?\{}\tests/cases/aln1.fa", cwd.display());
+        let verbatim = path_exists(OsStr::new(&verbatim)).unwrap();
+        assert!(niffler::from_path(&verbatim).is_ok());
+    }
+
     #[test]
     fn check_path_it_does() {
         let actual = path_exists(OsStr::new("Cargo.toml")).unwrap();
@@ -0,0 +1,237 @@
+use clap::ArgEnum;
+
+/// Index of a nucleotide in the base frequency array - A, C, G, T (or U)
+fn base_index(b: u8) -> Option<usize> {
+    match b {
+        b'A' => Some(0),
+        b'C' => Some(1),
+        b'G' => Some(2),
+        b'T' | b'U' => Some(3),
+        _ => None,
+    }
+}
+
+/// The distance model used to compute the distance between two sequences
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Model {
+    /// Number of differences (SNPs)
+    Raw,
+    /// Proportion of compared sites that differ
+    P,
+    /// Jukes-Cantor (1969)
+    Jc69,
+    /// Kimura 2-parameter (1980)
+    K80,
+    /// Tamura-Nei (1993)
+    Tn93,
+}
+
+/// Substitution counts between two sequences, only considering sites where both sequences have an
+/// unambiguous nucleotide
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SubstitutionCounts {
+    /// Number of sites where both sequences are A, C, G, or T
+    pub sites: u64,
+    /// Number of purine transitions (A <-> G)
+    pub purine_transitions: u64,
+    /// Number of pyrimidine transitions (C <-> T)
+    pub pyrimidine_transitions: u64,
+    /// Number of transversions (purine <-> pyrimidine)
+    pub transversions: u64,
+}
+
+impl SubstitutionCounts {
+    pub fn transitions(&self) -> u64 {
+        self.purine_transitions + self.pyrimidine_transitions
+    }
+
+    pub fn differences(&self) -> u64 {
+        self.transitions() + self.transversions
+    }
+
+    pub fn matches(&self) -> u64 {
+        self.sites - self.differences()
+    }
+}
+
+/// Classify every position where both sequences have an unambiguous nucleotide
+pub fn substitution_counts(a: &[u8], b: &[u8]) -> SubstitutionCounts {
+    let mut counts = SubstitutionCounts::default();
+    for (x, y) in a.iter().zip(b) {
+        let (i, j) = match (base_index(*x), base_index(*y)) {
+            (Some(i), Some(j)) => (i, j),
+            _ => continue,
+        };
+        counts.sites += 1;
+        match (i.min(j), i.max(j)) {
+            (i, j) if i == j => (),
+            (0, 2) => counts.purine_transitions += 1,
+            (1, 3) => counts.pyrimidine_transitions += 1,
+            _ => counts.transversions += 1,
+        }
+    }
+    counts
+}
+
+/// Frequencies of A, C, G, and T across all sequences
+pub fn base_frequencies<'a, I>(seqs: I) -> [f64; 4]
+where
+    I: IntoIterator<Item = &'a Vec<u8>>,
+{
+    let mut counts = [0u64; 4];
+    for seq in seqs {
+        for i in seq.iter().filter_map(|b| base_index(*b)) {
+            counts[i] += 1;
+        }
+    }
+    let total: u64 = counts.iter().sum();
+    let mut freqs = [0.0; 4];
+    if total > 0 {
+        for (f, c) in freqs.iter_mut().zip(counts) {
+            *f = c as f64 / total as f64;
+        }
+    }
+    freqs
+}
+
+impl Model {
+    /// The distance under this model. Returns `None` if the distance is undefined - i.e., no sites
+    /// were compared or the sequences are too divergent for the model (saturation).
+    pub fn distance(&self, counts: &SubstitutionCounts, freqs: &[f64; 4]) -> Option<f64> {
+        if counts.sites == 0 {
+            return None;
+        }
+        let sites = counts.sites as f64;
+        let p = counts.differences() as f64 / sites;
+        let d = match self {
+            Model::Raw => counts.differences() as f64,
+            Model::P => p,
+            Model::Jc69 => -0.75 * (1.0 - 4.0 / 3.0 * p).ln(),
+            Model::K80 => {
+                let p = counts.transitions() as f64 / sites;
+                let q = counts.transversions as f64 / sites;
+                -0.5 * (1.0 - 2.0 * p - q).ln() - 0.25 * (1.0 - 2.0 * q).ln()
+            }
+            Model::Tn93 => {
+                let [a, c, g, t] = *freqs;
+                let r = a + g;
+                let y = c + t;
+                let p1 = counts.purine_transitions as f64 / sites;
+                let p2 = counts.pyrimidine_transitions as f64 / sites;
+                let q = counts.transversions as f64 / sites;
+                let w1 = 1.0 - r * p1 / (2.0 * a * g) - q / (2.0 * r);
+                let w2 = 1.0 - y * p2 / (2.0 * c * t) - q / (2.0 * y);
+                let w3 = 1.0 - q / (2.0 * r * y);
+                -2.0 * a * g / r * w1.ln()
+                    - 2.0 * c * t / y * w2.ln()
+                    - 2.0 * (r * y - a * g * y / r - c * t * r / y) * w3.ln()
+            }
+        };
+        if d.is_finite() {
+            // distances are never negative, but identical sequences can give -0
+            Some(d.abs())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EQUAL_FREQS: [f64; 4] = [0.25; 4];
+
+    fn assert_close(actual: Option<f64>, expected: f64) {
+        let actual = actual.unwrap();
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{} != {}",
+            actual,
+            expected
+        )
+    }
+
+    #[test]
+    fn count_substitutions() {
+        let a = b"AACCGTNA-";
+        let b = b"GTCTGTAAA";
+
+        let actual = substitution_counts(a, b);
+        let expected = SubstitutionCounts {
+            sites: 7,
+            purine_transitions: 1,
+            pyrimidine_transitions: 1,
+            transversions: 1,
+        };
+
+        assert_eq!(actual, expected);
+        assert_eq!(actual.matches(), 4)
+    }
+
+    #[test]
+    fn base_frequencies_ignore_non_acgt() {
+        let seqs = vec![b"AACN".to_vec(), b"GT-U".to_vec()];
+
+        let actual = base_frequencies(&seqs);
+
+        assert_eq!(actual, [2.0 / 6.0, 1.0 / 6.0, 1.0 / 6.0, 2.0 / 6.0])
+    }
+
+    #[test]
+    fn distance_with_no_sites_is_undefined() {
+        let counts = SubstitutionCounts::default();
+        assert_eq!(Model::P.distance(&counts, &EQUAL_FREQS), None)
+    }
+
+    #[test]
+    fn distance_of_identical_sequences_is_zero() {
+        let counts = SubstitutionCounts {
+            sites: 10,
+            ..Default::default()
+        };
+        for model in [Model::Raw, Model::P, Model::Jc69, Model::K80, Model::Tn93] {
+            assert_eq!(model.distance(&counts, &EQUAL_FREQS), Some(0.0))
+        }
+    }
+
+    #[test]
+    fn saturated_distance_is_undefined() {
+        let counts = SubstitutionCounts {
+            sites: 4,
+            transversions: 3,
+            ..Default::default()
+        };
+        assert_eq!(Model::Jc69.distance(&counts, &EQUAL_FREQS), None)
+    }
+
+    #[test]
+    fn model_distances() {
+        let counts = SubstitutionCounts {
+            sites: 100,
+            purine_transitions: 6,
+            pyrimidine_transitions: 4,
+            transversions: 5,
+        };
+
+        assert_close(Model::Raw.distance(&counts, &EQUAL_FREQS), 15.0);
+        assert_close(Model::P.distance(&counts, &EQUAL_FREQS), 0.15);
+        // -3/4 ln(1 - 4/3 * 0.15)
+        assert_close(Model::Jc69.distance(&counts, &EQUAL_FREQS), 0.167_357_7);
+        // -1/2 ln(1 - 2 * 0.1 - 0.05) - 1/4 ln(1 - 2 * 0.05)
+        assert_close(Model::K80.distance(&counts, &EQUAL_FREQS), 0.170_181_2);
+    }
+
+    #[test]
+    fn tn93_with_equal_frequencies_and_transitions_is_k80() {
+        let counts = SubstitutionCounts {
+            sites: 100,
+            purine_transitions: 5,
+            pyrimidine_transitions: 5,
+            transversions: 5,
+        };
+
+        let expected = Model::K80.distance(&counts, &EQUAL_FREQS).unwrap();
+        assert_close(Model::Tn93.distance(&counts, &EQUAL_FREQS), expected)
+    }
+}
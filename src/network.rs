@@ -0,0 +1,155 @@
+use ndarray::Array2;
+use std::collections::HashSet;
+use std::io::{Error, Write};
+
+/// The (column, row, distance) edges of a distance matrix with a distance of at most `max_dist`.
+/// For intra-alignment (square, symmetric) matrices, each pair is only given once and there are no
+/// self-edges.
+fn edges(
+    matrix: &Array2<u64>,
+    intra: bool,
+    max_dist: Option<u64>,
+) -> impl Iterator<Item = (usize, usize, u64)> + '_ {
+    matrix
+        .indexed_iter()
+        .filter(move |((row, col), _)| !intra || col < row)
+        .filter(move |(_, d)| max_dist.is_none_or(|m| **d <= m))
+        .map(|((row, col), d)| (col, row, *d))
+}
+
+/// Write the matrix as a weighted edge list with a `source`, `target`, and `weight` header
+pub fn write_edgelist(
+    ostream: &mut dyn Write,
+    delimiter: char,
+    matrix: &Array2<u64>,
+    column_names: &[Vec<u8>],
+    row_names: &[Vec<u8>],
+    intra: bool,
+    max_dist: Option<u64>,
+) -> Result<(), Error> {
+    writeln!(ostream, "source{d}target{d}weight", d = delimiter)?;
+    for (col, row, d) in edges(matrix, intra, max_dist) {
+        writeln!(
+            ostream,
+            "{}{d}{}{d}{}",
+            String::from_utf8_lossy(&column_names[col]),
+            String::from_utf8_lossy(&row_names[row]),
+            d,
+            d = delimiter
+        )?;
+    }
+    Ok(())
+}
+
+/// Write the matrix as sparse (row index, column index, distance) triplets, one per line. Indices
+/// are 0-based positions in the row and column names.
+pub fn write_sparse(
+    ostream: &mut dyn Write,
+    delimiter: char,
+    matrix: &Array2<u64>,
+    intra: bool,
+    max_dist: Option<u64>,
+) -> Result<(), Error> {
+    for (col, row, d) in edges(matrix, intra, max_dist) {
+        writeln!(ostream, "{}{d}{}{d}{}", row, col, d, d = delimiter)?;
+    }
+    Ok(())
+}
+
+fn escape_xml(s: &[u8]) -> String {
+    String::from_utf8_lossy(s)
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Write the matrix as an undirected GraphML graph, with a node per sequence and the distances as
+/// edge weights
+pub fn write_graphml(
+    ostream: &mut dyn Write,
+    matrix: &Array2<u64>,
+    column_names: &[Vec<u8>],
+    row_names: &[Vec<u8>],
+    intra: bool,
+    max_dist: Option<u64>,
+) -> Result<(), Error> {
+    writeln!(ostream, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        ostream,
+        r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+    )?;
+    writeln!(
+        ostream,
+        r#"  <key id="weight" for="edge" attr.name="weight" attr.type="long"/>"#
+    )?;
+    writeln!(ostream, r#"  <graph id="psdm" edgedefault="undirected">"#)?;
+    let mut seen = HashSet::new();
+    for name in column_names.iter().chain(row_names) {
+        if seen.insert(name) {
+            writeln!(ostream, r#"    <node id="{}"/>"#, escape_xml(name))?;
+        }
+    }
+    for (col, row, d) in edges(matrix, intra, max_dist) {
+        writeln!(
+            ostream,
+            r#"    <edge source="{}" target="{}"><data key="weight">{}</data></edge>"#,
+            escape_xml(&column_names[col]),
+            escape_xml(&row_names[row]),
+            d
+        )?;
+    }
+    writeln!(ostream, "  </graph>")?;
+    writeln!(ostream, "</graphml>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intra_edges_within_max_dist() {
+        let matrix = ndarray::arr2(&[[0, 1, 9], [1, 0, 7], [9, 7, 0]]);
+
+        let actual: Vec<_> = edges(&matrix, true, Some(7)).collect();
+        let expected = vec![(0, 1, 1), (1, 2, 7)];
+
+        assert_eq!(actual, expected)
+    }
+
+    #[test]
+    fn inter_edges_include_all_pairs() {
+        let matrix = ndarray::arr2(&[[0, 1], [2, 3]]);
+
+        let actual: Vec<_> = edges(&matrix, false, None).collect();
+        let expected = vec![(0, 0, 0), (1, 0, 1), (0, 1, 2), (1, 1, 3)];
+
+        assert_eq!(actual, expected)
+    }
+
+    #[test]
+    fn sparse_triplets_within_max_dist() {
+        let matrix = ndarray::arr2(&[[0, 1, 9], [1, 0, 7], [9, 7, 0]]);
+        let mut buf = vec![];
+
+        write_sparse(&mut buf, ' ', &matrix, true, Some(7)).unwrap();
+
+        assert_eq!(buf, b"1 0 1\n2 1 7\n")
+    }
+
+    #[test]
+    fn graphml_escapes_names() {
+        let matrix = ndarray::arr2(&[[0, 4], [4, 0]]);
+        let names = vec![b"a&b".to_vec(), b"<c>".to_vec()];
+        let mut buf = vec![];
+
+        write_graphml(&mut buf, &matrix, &names, &names, true, None).unwrap();
+        let actual = String::from_utf8(buf).unwrap();
+
+        assert!(actual.contains(r#"<node id="a&amp;b"/>"#));
+        assert!(actual.contains(
+            r#"<edge source="a&amp;b" target="&lt;c&gt;"><data key="weight">4</data></edge>"#
+        ));
+    }
+}
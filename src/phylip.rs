@@ -0,0 +1,152 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::io::BufRead;
+
+use crate::NamesAndSeqs;
+
+/// Split a line into the name (first whitespace-delimited token) and the sequence (the rest of the
+/// line with all whitespace removed)
+fn split_name(line: &str) -> (Vec<u8>, Vec<u8>) {
+    let line = line.trim_start();
+    let (name, rest) = line.split_at(line.find(char::is_whitespace).unwrap_or(line.len()));
+    (name.as_bytes().to_vec(), sequence_chars(rest))
+}
+
+fn sequence_chars(s: &str) -> Vec<u8> {
+    s.bytes().filter(|b| !b.is_ascii_whitespace()).collect()
+}
+
+/// Sequential format - each sequence follows its name and may span multiple lines
+fn parse_sequential(lines: &[String], ntax: usize, nchar: usize) -> Option<NamesAndSeqs> {
+    let mut names = Vec::with_capacity(ntax);
+    let mut seqs = Vec::with_capacity(ntax);
+    let mut lines = lines.iter();
+    for _ in 0..ntax {
+        let (name, mut seq) = split_name(lines.next()?);
+        while seq.len() < nchar {
+            seq.extend(sequence_chars(lines.next()?));
+        }
+        if seq.len() != nchar {
+            return None;
+        }
+        names.push(name);
+        seqs.push(seq);
+    }
+    if lines.next().is_some() {
+        return None;
+    }
+    Some((names, seqs))
+}
+
+/// Interleaved format - the first block has the names, subsequent blocks only have sequence
+fn parse_interleaved(lines: &[String], ntax: usize, nchar: usize) -> Option<NamesAndSeqs> {
+    if lines.len() < ntax || !lines.len().is_multiple_of(ntax) {
+        return None;
+    }
+    let (names, mut seqs): (Vec<_>, Vec<_>) = lines[..ntax].iter().map(|l| split_name(l)).unzip();
+    for (i, line) in lines[ntax..].iter().enumerate() {
+        seqs[i % ntax].extend(sequence_chars(line));
+    }
+    if seqs.iter().any(|s| s.len() != nchar) {
+        return None;
+    }
+    Some((names, seqs))
+}
+
+/// Parse a relaxed PHYLIP alignment. Names are the first whitespace-delimited token of a line (i.e.,
+/// not restricted to 10 characters). Both sequential and interleaved formats are supported.
+pub(crate) fn parse<R: BufRead>(reader: R) -> Result<NamesAndSeqs> {
+    let mut lines = reader.lines();
+    let header = lines
+        .next()
+        .ok_or_else(|| anyhow!("PHYLIP file is empty"))?
+        .context("Failed to read PHYLIP header")?;
+    let dims: Vec<usize> = header
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<_, _>>()
+        .with_context(|| format!("Invalid PHYLIP header {:?}", header))?;
+    let (ntax, nchar) = match dims.as_slice() {
+        [ntax, nchar] => (*ntax, *nchar),
+        _ => bail!("PHYLIP header should contain two numbers, got {:?}", header),
+    };
+
+    let mut body = vec![];
+    for line in lines {
+        let line = line.context("Failed to read PHYLIP file")?;
+        if !line.trim().is_empty() {
+            body.push(line);
+        }
+    }
+
+    parse_sequential(&body, ntax, nchar)
+        .or_else(|| parse_interleaved(&body, ntax, nchar))
+        .ok_or_else(|| {
+            anyhow!(
+                "Could not parse PHYLIP alignment with {} sequences of length {}",
+                ntax,
+                nchar
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sequential_single_line() {
+        let data = b"3 5\ns0 ACGTA\ns1   AC-TA\nlong_sample_name NNGTA\n";
+
+        let actual = parse(&data[..]).unwrap();
+        let expected = (
+            vec![b"s0".to_vec(), b"s1".to_vec(), b"long_sample_name".to_vec()],
+            vec![b"ACGTA".to_vec(), b"AC-TA".to_vec(), b"NNGTA".to_vec()],
+        );
+
+        assert_eq!(actual, expected)
+    }
+
+    #[test]
+    fn parse_sequential_multi_line() {
+        let data = b" 2 12\ns0 ACGTA CGTAC\nGT\ns1 CCCCC\nCCCCC CC\n";
+
+        let actual = parse(&data[..]).unwrap();
+        let expected = (
+            vec![b"s0".to_vec(), b"s1".to_vec()],
+            vec![b"ACGTACGTACGT".to_vec(), b"CCCCCCCCCCCC".to_vec()],
+        );
+
+        assert_eq!(actual, expected)
+    }
+
+    #[test]
+    fn parse_interleaved_blocks() {
+        let data = b"2 12\ns0 ACGTA CGTAC\ns1 CCCCC CCCCC\n\nGT\nAA\n";
+
+        let actual = parse(&data[..]).unwrap();
+        let expected = (
+            vec![b"s0".to_vec(), b"s1".to_vec()],
+            vec![b"ACGTACGTACGT".to_vec(), b"CCCCCCCCCCAA".to_vec()],
+        );
+
+        assert_eq!(actual, expected)
+    }
+
+    #[test]
+    fn parse_with_wrong_length() {
+        let data = b"2 5\ns0 ACGTA\ns1 ACGT\n";
+
+        let actual = parse(&data[..]).unwrap_err();
+
+        assert!(actual.to_string().contains("2 sequences of length 5"))
+    }
+
+    #[test]
+    fn parse_invalid_header() {
+        let data = b">s0\nACGT\n";
+
+        let actual = parse(&data[..]).unwrap_err();
+
+        assert!(actual.to_string().contains("Invalid PHYLIP header"))
+    }
+}
@@ -0,0 +1,114 @@
+use ndarray::Array2;
+
+/// Quote a name for Newick if it contains characters with a special meaning, doubling any single
+/// quotes inside it
+fn newick_label(name: &[u8]) -> String {
+    let name = String::from_utf8_lossy(name);
+    let special = |c: char| c.is_whitespace() || "()[]':;,".contains(c);
+    if name.contains(special) {
+        format!("'{}'", name.replace('\'', "''"))
+    } else {
+        name.into_owned()
+    }
+}
+
+/// Build a neighbour-joining tree (Saitou and Nei, 1987) from a square distance matrix and return
+/// it in Newick format. The tree is unrooted, so the last three nodes are joined at the top level.
+pub fn neighbour_joining(matrix: &Array2<f64>, names: &[Vec<u8>]) -> String {
+    let mut nodes: Vec<String> = names.iter().map(|n| newick_label(n)).collect();
+    let mut dists: Vec<Vec<f64>> = matrix.rows().into_iter().map(|r| r.to_vec()).collect();
+
+    while nodes.len() > 3 {
+        let n = nodes.len();
+        let totals: Vec<f64> = dists.iter().map(|r| r.iter().sum()).collect();
+        let mut best = (0, 1, f64::INFINITY);
+        for i in 0..n {
+            for j in (i + 1)..n {
+                let q = (n - 2) as f64 * dists[i][j] - totals[i] - totals[j];
+                if q < best.2 {
+                    best = (i, j, q);
+                }
+            }
+        }
+        let (i, j, _) = best;
+        let d_ij = dists[i][j];
+        let len_i = d_ij / 2.0 + (totals[i] - totals[j]) / (2.0 * (n - 2) as f64);
+        let len_j = d_ij - len_i;
+        let joined = format!("({}:{},{}:{})", nodes[i], len_i, nodes[j], len_j);
+        let to_joined: Vec<f64> = (0..n)
+            .filter(|k| *k != i && *k != j)
+            .map(|k| (dists[i][k] + dists[j][k] - d_ij) / 2.0)
+            .collect();
+
+        // j > i, so removing j first keeps i's index valid
+        for k in [j, i] {
+            nodes.remove(k);
+            dists.remove(k);
+            for row in dists.iter_mut() {
+                row.remove(k);
+            }
+        }
+        for (row, d) in dists.iter_mut().zip(&to_joined) {
+            row.push(*d);
+        }
+        let mut row = to_joined;
+        row.push(0.0);
+        dists.push(row);
+        nodes.push(joined);
+    }
+
+    match nodes.len() {
+        0 => ";".to_string(),
+        1 => format!("{};", nodes[0]),
+        2 => format!(
+            "({}:{},{}:{});",
+            nodes[0],
+            dists[0][1] / 2.0,
+            nodes[1],
+            dists[0][1] / 2.0
+        ),
+        _ => {
+            let (d01, d02, d12) = (dists[0][1], dists[0][2], dists[1][2]);
+            format!(
+                "({}:{},{}:{},{}:{});",
+                nodes[0],
+                (d01 + d02 - d12) / 2.0,
+                nodes[1],
+                (d01 + d12 - d02) / 2.0,
+                nodes[2],
+                (d02 + d12 - d01) / 2.0
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn neighbour_joining_recovers_additive_tree() {
+        // ((A:1,B:2):1,C:3,D:4)
+        let matrix = ndarray::arr2(&[
+            [0.0, 3.0, 5.0, 6.0],
+            [3.0, 0.0, 6.0, 7.0],
+            [5.0, 6.0, 0.0, 7.0],
+            [6.0, 7.0, 7.0, 0.0],
+        ]);
+        let names = vec![b"A".to_vec(), b"B".to_vec(), b"C".to_vec(), b"D".to_vec()];
+
+        let actual = neighbour_joining(&matrix, &names);
+
+        assert_eq!(actual, "(C:3,D:4,(A:1,B:2):1);")
+    }
+
+    #[test]
+    fn neighbour_joining_two_sequences() {
+        let matrix = ndarray::arr2(&[[0.0, 4.0], [4.0, 0.0]]);
+        let names = vec![b"a b".to_vec(), b"it's".to_vec()];
+
+        let actual = neighbour_joining(&matrix, &names);
+
+        assert_eq!(actual, "('a b':2,'it''s':2);")
+    }
+}
@@ -1,5 +1,5 @@
 use assert_cmd::Command;
-use std::io::Write;
+use std::io::{Read, Write};
 
 #[test]
 fn input_file_does_not_exist() -> Result<(), Box<dyn std::error::Error>> {
@@ -22,7 +22,7 @@ fn more_than_two_input_files_fails() -> Result<(), Box<dyn std::error::Error>> {
         .unwrap_err()
         .to_string();
 
-    assert!(err_msg.contains("expecting any more values"));
+    assert!(err_msg.contains("More than two alignments can only be given with --all-pairs"));
 
     Ok(())
 }
@@ -224,3 +224,1927 @@ fn inter_alignment_in_long_form_sorted() -> Result<(), Box<dyn std::error::Error
 
     Ok(())
 }
+
+#[test]
+fn output_file_with_custom_buffer_size() -> Result<(), Box<dyn std::error::Error>> {
+    let aln = "tests/cases/aln1.fa";
+    let outfile = tempfile::Builder::new().suffix(".csv").tempfile().unwrap();
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    cmd.args(["-c", "--buffer-size", "16", "-o"])
+        .arg(outfile.path())
+        .arg(aln)
+        .unwrap();
+
+    let output = std::fs::read(outfile.path())?;
+    let expected = b",s1,s2,s0\ns1,0,3,3\ns2,3,0,5\ns0,3,5,0\n";
+    assert_eq!(output, expected);
+
+    Ok(())
+}
+
+#[test]
+fn intra_alignment_with_blank_diagonal() -> Result<(), Box<dyn std::error::Error>> {
+    let aln = "tests/cases/aln1.fa";
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd.args(["-c", "--blank-diagonal", aln]).unwrap().stdout;
+
+    let expected = b",s1,s2,s0\ns1,,3,3\ns2,3,,5\ns0,3,5,\n";
+    assert_eq!(output, expected);
+
+    Ok(())
+}
+
+#[test]
+fn inter_alignment_ignores_blank_diagonal() -> Result<(), Box<dyn std::error::Error>> {
+    let aln1 = "tests/cases/aln1.fa";
+    let aln2 = "tests/cases/aln2.fa.gz";
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["-c", "--blank-diagonal", aln1, aln2])
+        .unwrap()
+        .stdout;
+
+    let expected = b",s1,s2,s0\ns2,6,6,5\ns5,1,4,3\n";
+    assert_eq!(output, expected);
+
+    Ok(())
+}
+
+#[test]
+fn intra_alignment_full_report() -> Result<(), Box<dyn std::error::Error>> {
+    let aln = "tests/cases/aln1.fa";
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd.args(["--report", "full", aln]).unwrap().stdout;
+
+    let expected = b"s1,s1,0,8,100.0000
+s1,s2,1,5,80.0000
+s1,s0,0,7,100.0000
+s2,s1,1,5,80.0000
+s2,s2,0,5,100.0000
+s2,s0,1,4,75.0000
+s0,s1,0,7,100.0000
+s0,s2,1,4,75.0000
+s0,s0,0,7,100.0000\n";
+    assert_eq!(output, expected);
+
+    Ok(())
+}
+
+#[test]
+fn inter_alignment_full_report_with_iupac() -> Result<(), Box<dyn std::error::Error>> {
+    let mut file1 = tempfile::Builder::new().suffix(".fa").tempfile().unwrap();
+    file1.write_all(b">s0\nARCTN\n>s1\nGGCTA\n").unwrap();
+    let mut file2 = tempfile::Builder::new().suffix(".fa").tempfile().unwrap();
+    file2.write_all(b">s2\nAACYA\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["--iupac", "--report", "full"])
+        .args([file1.path(), file2.path()])
+        .unwrap()
+        .stdout;
+
+    let expected = b"s0,s2,0,4,100.0000,2\ns1,s2,2,5,60.0000,1\n";
+    assert_eq!(output, expected);
+
+    Ok(())
+}
+
+#[test]
+fn intra_alignment_with_iupac() -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = tempfile::Builder::new().suffix(".fa").tempfile().unwrap();
+    file.write_all(b">s0\nARCTA\n>s1\nGGCYA\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd.args(["--iupac"]).arg(file.path()).unwrap().stdout;
+
+    let expected = b",s0,s1\ns0,0,1\ns1,1,0\n";
+    assert_eq!(output, expected);
+
+    Ok(())
+}
+
+#[test]
+fn inter_alignment_with_full_header() -> Result<(), Box<dyn std::error::Error>> {
+    let aln1 = "tests/cases/aln1.fa";
+    let aln2 = "tests/cases/aln2.fa.gz";
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["-c", "--full-header", aln1, aln2])
+        .unwrap()
+        .stdout;
+
+    let expected = b",s1,s2,s0\ns2,6,6,5\ns5 description,1,4,3\n";
+    assert_eq!(output, expected);
+
+    Ok(())
+}
+
+#[test]
+fn output_is_compressed_based_on_extension() -> Result<(), Box<dyn std::error::Error>> {
+    let aln = "tests/cases/aln1.fa";
+    let outfile = tempfile::Builder::new()
+        .suffix(".csv.gz")
+        .tempfile()
+        .unwrap();
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    cmd.args(["-c", "--compression-level", "9", "-o"])
+        .arg(outfile.path())
+        .arg(aln)
+        .unwrap();
+
+    let (mut reader, format) = niffler::from_path(outfile.path())?;
+    assert_eq!(format, niffler::compression::Format::Gzip);
+    let mut output = Vec::new();
+    reader.read_to_end(&mut output)?;
+    let expected = b",s1,s2,s0\ns1,0,3,3\ns2,3,0,5\ns0,3,5,0\n";
+    assert_eq!(output, expected);
+
+    Ok(())
+}
+
+#[test]
+fn output_compression_none_overrides_extension() -> Result<(), Box<dyn std::error::Error>> {
+    let aln = "tests/cases/aln1.fa";
+    let outfile = tempfile::Builder::new()
+        .suffix(".csv.gz")
+        .tempfile()
+        .unwrap();
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    cmd.args(["-c", "--output-compression", "none", "-o"])
+        .arg(outfile.path())
+        .arg(aln)
+        .unwrap();
+
+    let output = std::fs::read(outfile.path())?;
+    let expected = b",s1,s2,s0\ns1,0,3,3\ns2,3,0,5\ns0,3,5,0\n";
+    assert_eq!(output, expected);
+
+    Ok(())
+}
+
+#[test]
+fn intra_alignment_with_pairs_file() -> Result<(), Box<dyn std::error::Error>> {
+    let aln = "tests/cases/aln1.fa";
+    let mut pairs = tempfile::Builder::new().suffix(".tsv").tempfile().unwrap();
+    pairs.write_all(b"s0\ts2\ns1\ts1\ns2 s1\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["-c", "--pairs-file"])
+        .arg(pairs.path())
+        .arg(aln)
+        .unwrap()
+        .stdout;
+
+    let expected = b"s0,s2,5\ns1,s1,0\ns2,s1,3\n";
+    assert_eq!(output, expected);
+
+    Ok(())
+}
+
+#[test]
+fn inter_alignment_with_pairs_file_unknown_id() -> Result<(), Box<dyn std::error::Error>> {
+    let aln1 = "tests/cases/aln1.fa";
+    let aln2 = "tests/cases/aln2.fa.gz";
+    let mut pairs = tempfile::Builder::new().suffix(".tsv").tempfile().unwrap();
+    pairs.write_all(b"s0\ts5\ns5\ts0\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let err_msg = cmd
+        .arg("--pairs-file")
+        .arg(pairs.path())
+        .args([aln1, aln2])
+        .unwrap_err()
+        .to_string();
+
+    assert!(err_msg.contains("Unknown sequence ID s5 on line 2"));
+
+    Ok(())
+}
+
+#[test]
+fn same_alignment_twice_is_intra_alignment() -> Result<(), Box<dyn std::error::Error>> {
+    let aln = "tests/cases/aln1.fa";
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd.args(["-c", aln, "./tests/cases/aln1.fa"]).unwrap();
+
+    let expected = b",s1,s2,s0\ns1,0,3,3\ns2,3,0,5\ns0,3,5,0\n";
+    assert_eq!(output.stdout, expected);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("same file"));
+
+    Ok(())
+}
+
+#[test]
+fn intra_alignment_with_jc69_model() -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = tempfile::Builder::new().suffix(".fa").tempfile().unwrap();
+    file.write_all(b">a\nACGTACGTAC\n>b\nGCGTACTTAC\n>c\nACGTNNGTAA\n")
+        .unwrap();
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["-m", "jc69", "--precision", "4"])
+        .arg(file.path())
+        .unwrap()
+        .stdout;
+
+    let expected = b",a,b,c
+a,0.0000,0.2326,0.1367
+b,0.2326,0.0000,0.5199
+c,0.1367,0.5199,0.0000\n";
+    assert_eq!(output, expected);
+
+    Ok(())
+}
+
+#[test]
+fn model_with_full_report_fails() -> Result<(), Box<dyn std::error::Error>> {
+    let aln = "tests/cases/aln1.fa";
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let err_msg = cmd
+        .args(["-m", "p", "--report", "full", aln])
+        .unwrap_err()
+        .to_string();
+
+    assert!(err_msg.contains("cannot be used with the full report"));
+
+    Ok(())
+}
+
+#[test]
+fn warnings_are_written_to_log_file() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">s1\nACGT\n>s2\nACGA\n")?;
+    let logfile = tempfile::Builder::new().suffix(".log").tempfile().unwrap();
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    cmd.args(["-q", "--log-file"])
+        .arg(logfile.path())
+        .args([aln.path(), aln.path()])
+        .unwrap();
+
+    let log = std::fs::read_to_string(logfile.path())?;
+    let fields: Vec<&str> = log.trim_end().split('\t').collect();
+    assert_eq!(log.lines().count(), 1);
+    assert_eq!(fields[1], "WARN");
+    assert!(fields[2].contains("same file"));
+
+    Ok(())
+}
+
+#[test]
+fn intra_alignment_with_stride_and_offset() -> Result<(), Box<dyn std::error::Error>> {
+    let aln = "tests/cases/aln1.fa";
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["-c", "--stride", "2", "--offset", "2", aln])
+        .unwrap()
+        .stdout;
+
+    let expected = b",s1,s2,s0\ns1,0,0,3\ns2,0,0,2\ns0,3,2,0\n";
+    assert_eq!(output, expected);
+
+    Ok(())
+}
+
+#[test]
+fn version_json_does_not_need_alignments() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd.arg("--version-json").unwrap().stdout;
+    let output = String::from_utf8(output)?;
+
+    assert!(output.starts_with(r#"{"name":"psdm","version":""#));
+    assert!(output.contains(r#""commit":""#));
+    assert!(output.contains(r#""rustc":""#));
+    assert!(output.trim_end().ends_with("]}"));
+
+    Ok(())
+}
+
+#[test]
+fn no_alignments_fails() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let err_msg = cmd.unwrap_err().to_string();
+
+    assert!(err_msg.contains("required arguments were not provided"));
+
+    Ok(())
+}
+
+#[test]
+fn interleaved_phylip_matches_fasta() -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = tempfile::Builder::new().suffix(".phy").tempfile().unwrap();
+    file.write_all(b"3 8\ns1 ABCD\ns2 aBN-\ns0 AbCd\n\nEFGH\nXFnH\nEfG-\n")
+        .unwrap();
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let phylip = cmd.arg(file.path()).unwrap().stdout;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let fasta = cmd.arg("tests/cases/aln1.fa").unwrap().stdout;
+
+    assert_eq!(phylip, fasta);
+
+    Ok(())
+}
+
+#[test]
+fn intra_alignment_condensed() -> Result<(), Box<dyn std::error::Error>> {
+    let aln = "tests/cases/aln1.fa";
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd.args(["--format", "condensed", aln]).unwrap().stdout;
+
+    let expected = b"1\n0\n1\n";
+    assert_eq!(output, expected);
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["--format", "condensed", "--include-diagonal", aln])
+        .unwrap()
+        .stdout;
+
+    let expected = b"0\n1\n0\n0\n1\n0\n";
+    assert_eq!(output, expected);
+
+    Ok(())
+}
+
+#[test]
+fn inter_alignment_condensed_fails() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let err_msg = cmd
+        .args([
+            "--format",
+            "condensed",
+            "tests/cases/aln1.fa",
+            "tests/cases/aln2.fa.gz",
+        ])
+        .unwrap_err()
+        .to_string();
+
+    assert!(err_msg.contains("only available for a single alignment"));
+
+    Ok(())
+}
+
+#[test]
+fn intra_alignment_r_format() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["--format", "r", "--blank-diagonal", "tests/cases/aln1.fa"])
+        .unwrap()
+        .stdout;
+
+    let expected = "\"\",\"s1\",\"s2\",\"s0\"\n\"s1\",NA,1,0\n\"s2\",1,NA,1\n\"s0\",0,1,NA\n";
+    assert_eq!(String::from_utf8(output)?, expected);
+
+    Ok(())
+}
+
+#[test]
+fn intra_alignment_with_complete_deletion() -> Result<(), Box<dyn std::error::Error>> {
+    let text = ">s0\nACGTN\n>s1\nTCG-A\n>s2\nAGGTC\n";
+    let mut file = tempfile::Builder::new().suffix(".fa").tempfile().unwrap();
+    file.write_all(text.as_bytes()).unwrap();
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["--complete-deletion", "-l", file.path().to_str().unwrap()])
+        .unwrap()
+        .stdout;
+
+    let expected =
+        b"s0,s0,0\ns0,s1,1\ns0,s2,1\ns1,s0,1\ns1,s1,0\ns1,s2,2\ns2,s0,1\ns2,s1,2\ns2,s2,0\n";
+    assert_eq!(output, expected);
+
+    Ok(())
+}
+
+#[test]
+fn intra_alignment_also_normalized() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let prefix = dir.path().join("dists");
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    cmd.args([
+        "--also-normalized",
+        "--precision",
+        "2",
+        "-o",
+        prefix.to_str().unwrap(),
+        "tests/cases/aln1.fa",
+    ])
+    .unwrap();
+
+    let snps = std::fs::read_to_string(dir.path().join("dists.snps.csv"))?;
+    let expected = ",s1,s2,s0\ns1,0,1,0\ns2,1,0,1\ns0,0,1,0\n";
+    assert_eq!(snps, expected);
+
+    let props = std::fs::read_to_string(dir.path().join("dists.prop.csv"))?;
+    let expected = ",s1,s2,s0\ns1,0.00,0.20,0.00\ns2,0.20,0.00,0.25\ns0,0.00,0.25,0.00\n";
+    assert_eq!(props, expected);
+
+    Ok(())
+}
+
+#[test]
+fn intra_alignment_outliers() -> Result<(), Box<dyn std::error::Error>> {
+    let aln = "tests/cases/aln1.fa";
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd.args(["-q", "--outliers", "2", aln]).unwrap();
+
+    let expected = "name\tmean_distance\ns2\t1.00\ns1\t0.50\n";
+    assert_eq!(String::from_utf8(output.stderr)?, expected);
+
+    Ok(())
+}
+
+#[test]
+fn intra_alignment_binary_output() -> Result<(), Box<dyn std::error::Error>> {
+    let aln = "tests/cases/aln1.fa";
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd.args(["--format", "bin", aln]).unwrap().stdout;
+
+    let actual = psdm::read_binary(&output[..])?;
+    let names = vec![b"s1".to_vec(), b"s2".to_vec(), b"s0".to_vec()];
+    assert_eq!(actual.column_names, names);
+    assert_eq!(actual.row_names, names);
+    assert_eq!(
+        actual.matrix,
+        ndarray::arr2(&[[0, 1, 0], [1, 0, 1], [0, 1, 0]])
+    );
+
+    Ok(())
+}
+
+#[test]
+fn intra_alignment_full_report_with_gap_runs() -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = tempfile::Builder::new().suffix(".fa").tempfile().unwrap();
+    file.write_all(b">s0\nA---CN\n>s1\nA--TCN\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["--report", "full", "--gap-runs", "-l"])
+        .arg(file.path())
+        .unwrap()
+        .stdout;
+
+    let expected = b"s0,s0,0,2,100.0000,3
+s0,s1,0,2,100.0000,2
+s1,s0,0,2,100.0000,2
+s1,s1,0,3,100.0000,2\n";
+    assert_eq!(output, expected);
+
+    Ok(())
+}
+
+#[test]
+fn intra_alignment_with_crlf() -> Result<(), Box<dyn std::error::Error>> {
+    let aln = "tests/cases/aln1.fa";
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd.args(["--line-ending", "crlf", aln]).unwrap().stdout;
+
+    let expected = b",s1,s2,s0\r\ns1,0,1,0\r\ns2,1,0,1\r\ns0,0,1,0\r\n";
+    assert_eq!(output, expected);
+
+    Ok(())
+}
+
+#[test]
+fn intra_alignment_drop_singletons() -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = tempfile::Builder::new().suffix(".fa").tempfile().unwrap();
+    file.write_all(b">s0\nAAAAAA\n>s1\nAAAAAC\n>s2\nCCCAAA\n")
+        .unwrap();
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["--drop-singletons", "1"])
+        .arg(file.path())
+        .unwrap()
+        .stdout;
+
+    let expected = b",s0,s1\ns0,0,1\ns1,1,0\n";
+    assert_eq!(output, expected);
+
+    Ok(())
+}
+
+#[test]
+fn intra_alignment_pretty() -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = tempfile::Builder::new().suffix(".fa").tempfile().unwrap();
+    file.write_all(b">s0\nAAAAAAAAAAAA\n>sample1\nAAAAAAAAAAAC\n>s2\nCCCCCCCCCCCC\n")
+        .unwrap();
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd.arg("--pretty").arg(file.path()).unwrap().stdout;
+
+    let expected = "         s0  sample1  s2
+s0        0        1  12
+sample1   1        0  11
+s2       12       11   0
+";
+    assert_eq!(String::from_utf8(output)?, expected);
+
+    Ok(())
+}
+
+#[test]
+fn intra_alignment_pretty_with_name_width() -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = tempfile::Builder::new().suffix(".fa").tempfile().unwrap();
+    file.write_all(b">s0\nAAAAAAAAAAAA\n>sample1\nAAAAAAAAAAAC\n>s2\nCCCCCCCCCCCC\n")
+        .unwrap();
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["--pretty", "--name-width", "4"])
+        .arg(file.path())
+        .unwrap();
+
+    let expected = "      s0  samp  s2
+s0     0     1  12
+samp   1     0  11
+s2    12    11   0
+";
+    assert_eq!(String::from_utf8(output.stdout)?, expected);
+    assert!(String::from_utf8(output.stderr)?.contains("Truncated 1 column and 1 row name(s)"));
+
+    Ok(())
+}
+
+#[test]
+fn intra_alignment_resume_from_checkpoint() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let checkpoint = dir.path().join("checkpoint");
+    // pretend the only chunk was already computed (with fake distances) by an interrupted run
+    std::fs::write(
+        &checkpoint,
+        "#psdm-checkpoint v1 pairs=6 chunk_size=100000 fingerprint=43bdff85d24e1f89\n0\t0,9,8,0,7,0\n",
+    )?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["--checkpoint", checkpoint.to_str().unwrap(), "--resume"])
+        .arg("tests/cases/aln1.fa")
+        .unwrap()
+        .stdout;
+
+    let expected = b",s1,s2,s0\ns1,0,9,8\ns2,9,0,7\ns0,8,7,0\n";
+    assert_eq!(output, expected);
+    assert!(!checkpoint.exists());
+
+    Ok(())
+}
+
+#[test]
+fn resume_checkpoint_with_changed_alignment_fails() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let checkpoint = dir.path().join("checkpoint");
+    // a checkpoint from tests/cases/aln1.fa
+    std::fs::write(
+        &checkpoint,
+        "#psdm-checkpoint v1 pairs=6 chunk_size=100000 fingerprint=43bdff85d24e1f89\n0\t0,9,8,0,7,0\n",
+    )?;
+    let mut file = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(file, ">s1\nABCDEFGH\n>s2\naBN-XFnH\n>s0\nAbCdEfGA\n")?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["--checkpoint", checkpoint.to_str().unwrap(), "--resume"])
+        .arg(file.path())
+        .output()?;
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("does not match this run"));
+
+    Ok(())
+}
+
+#[test]
+fn intra_alignment_vs_consensus() -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = tempfile::Builder::new().suffix(".fa").tempfile().unwrap();
+    file.write_all(b">s0\nACGT\n>s1\nACGA\n>s2\nTCNA\n")
+        .unwrap();
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd.arg("--vs-consensus").arg(file.path()).unwrap().stdout;
+
+    let expected = b"s0,1\ns1,0\ns2,1\n";
+    assert_eq!(output, expected);
+
+    Ok(())
+}
+
+#[test]
+fn intra_alignment_full_report_case_aware() -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = tempfile::Builder::new().suffix(".fa").tempfile().unwrap();
+    file.write_all(b">s0\nACgT\n>s1\nAcGA\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["--report", "full", "-c", "--case-aware"])
+        .arg(file.path())
+        .unwrap()
+        .stdout;
+
+    let expected = b"s0,s0,0,4,100.0000,0
+s0,s1,1,4,75.0000,2
+s1,s0,1,4,75.0000,2
+s1,s1,0,4,100.0000,0\n";
+    assert_eq!(output, expected);
+
+    Ok(())
+}
+
+#[test]
+fn intra_alignment_outliers_with_weights() -> Result<(), Box<dyn std::error::Error>> {
+    let mut weights = tempfile::Builder::new().suffix(".tsv").tempfile().unwrap();
+    weights.write_all(b"s0 0\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["-q", "--outliers", "2", "--sample-weights"])
+        .arg(weights.path())
+        .arg("tests/cases/aln1.fa")
+        .unwrap();
+
+    let expected = "name\tmean_distance\ns1\t1.00\ns2\t1.00\n";
+    assert_eq!(String::from_utf8(output.stderr)?, expected);
+
+    Ok(())
+}
+
+#[test]
+fn intra_alignment_edgelist_with_max_dist() -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = tempfile::Builder::new().suffix(".fa").tempfile().unwrap();
+    file.write_all(b">s0\nAAAAAA\n>s1\nAAAAAC\n>s2\nCCCAAA\n")
+        .unwrap();
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["--format", "edgelist", "--max-dist", "3", "-d", "\t"])
+        .arg(file.path())
+        .unwrap()
+        .stdout;
+
+    let expected = b"source\ttarget\tweight\ns0\ts1\t1\ns0\ts2\t3\n";
+    assert_eq!(output, expected);
+
+    Ok(())
+}
+
+#[test]
+fn intra_alignment_graphml() -> Result<(), Box<dyn std::error::Error>> {
+    let aln = "tests/cases/aln1.fa";
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd.args(["--format", "graphml", aln]).unwrap().stdout;
+    let output = String::from_utf8(output)?;
+
+    assert!(output.starts_with("<?xml"));
+    assert_eq!(output.matches("<node ").count(), 3);
+    assert_eq!(output.matches("<edge ").count(), 3);
+    assert!(output.contains(r#"<edge source="s1" target="s2"><data key="weight">1</data></edge>"#));
+
+    Ok(())
+}
+
+#[test]
+fn groups_mean_matrix() -> Result<(), Box<dyn std::error::Error>> {
+    let aln = "tests/cases/aln1.fa";
+    let mut groups = tempfile::NamedTempFile::new()?;
+    writeln!(groups, "s0 A\ns1 A\ns2 B")?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["--precision", "2", "--groups"])
+        .arg(groups.path())
+        .arg(aln)
+        .unwrap()
+        .stdout;
+
+    let expected = b",A,B\nA,0.00,1.00\nB,1.00,NA\n";
+    assert_eq!(output, expected);
+
+    Ok(())
+}
+
+#[test]
+fn variant_report() -> Result<(), Box<dyn std::error::Error>> {
+    let aln = "tests/cases/aln1.fa";
+    let report = tempfile::NamedTempFile::new()?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    cmd.arg("--variant-report")
+        .arg(report.path())
+        .arg(aln)
+        .assert()
+        .success();
+
+    let actual = std::fs::read_to_string(report.path())?;
+    assert!(actual.starts_with("position,alleles,counts\n"));
+    assert_eq!(actual.lines().count(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn protein_alignment_warning() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">p1\nMKVLAAGIVG\n>p2\nMKVLSAGIEG\n")?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd.arg(aln.path()).unwrap();
+    let stderr = String::from_utf8(output.stderr)?;
+
+    assert!(stderr.contains("looks like protein"));
+
+    Ok(())
+}
+
+#[test]
+fn adjust_missing_scales_by_compared_sites() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">s1\nACGTACGT\n>s2\nACGANNNN\n")?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["--adjust-missing", "--precision", "1", "-l"])
+        .arg(aln.path())
+        .unwrap()
+        .stdout;
+
+    let expected = b"s1,s1,0.0\ns1,s2,2.0\ns2,s1,2.0\ns2,s2,0.0\n";
+    assert_eq!(output, expected);
+
+    Ok(())
+}
+
+#[test]
+fn multi_member_gzip_reads_all_records() -> Result<(), Box<dyn std::error::Error>> {
+    // two gzip members concatenated, as produced by `cat a.fa.gz b.fa.gz`
+    let aln = "tests/cases/multi_member.fa.gz";
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd.arg(aln).unwrap().stdout;
+
+    let expected = b",s1,s2,s3\ns1,0,1,2\ns2,1,0,1\ns3,2,1,0\n";
+    assert_eq!(output, expected);
+
+    Ok(())
+}
+
+#[test]
+fn dedup_collapses_identical_sequences() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">a\nACGT\n>b\nACGA\n>c\nACGT\n")?;
+    let map = tempfile::NamedTempFile::new()?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .arg("--dedup")
+        .arg("--dedup-map")
+        .arg(map.path())
+        .arg(aln.path())
+        .unwrap()
+        .stdout;
+
+    assert_eq!(output, b",a,b\na,0,1\nb,1,0\n");
+    assert_eq!(std::fs::read_to_string(map.path())?, "a,a\nb,b\nc,a\n");
+
+    Ok(())
+}
+
+#[test]
+fn per_sequence_outputs_quote_names_with_delimiter() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">a,1\nACGT\n>b\nACGA\n>c\nACGT\n")?;
+    let mut panel = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(panel, ">p,1\nACGA\n>p2\nTCGT\n")?;
+    let map = tempfile::NamedTempFile::new()?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    cmd.arg("--dedup")
+        .arg("--dedup-map")
+        .arg(map.path())
+        .arg(aln.path())
+        .assert()
+        .success();
+    let expected = "\"a,1\",\"a,1\"\nb,b\nc,\"a,1\"\n";
+    assert_eq!(std::fs::read_to_string(map.path())?, expected);
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd.arg("--vs-consensus").arg(aln.path()).unwrap().stdout;
+    assert_eq!(output, b"\"a,1\",0\nb,1\nc,0\n");
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .arg("--panel")
+        .arg(panel.path())
+        .arg(aln.path())
+        .unwrap()
+        .stdout;
+    assert_eq!(output, b"\"a,1\",\"p,1\",1\nb,\"p,1\",0\nc,\"p,1\",1\n");
+
+    Ok(())
+}
+
+#[test]
+fn dedup_expand_matches_full_matrix() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">a\nACGT\n>b\nACGA\n>c\nACGT\n>d\nTCGA\n")?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let expected = cmd.arg(aln.path()).unwrap().stdout;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["--dedup", "--expand"])
+        .arg(aln.path())
+        .unwrap()
+        .stdout;
+
+    assert_eq!(output, expected);
+
+    Ok(())
+}
+
+#[test]
+fn matrix_without_header() -> Result<(), Box<dyn std::error::Error>> {
+    let aln = "tests/cases/aln1.fa";
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd.args(["--no-header", aln]).unwrap().stdout;
+
+    let expected = b"s1,0,1,0\ns2,1,0,1\ns0,0,1,0\n";
+    assert_eq!(output, expected);
+
+    Ok(())
+}
+
+#[test]
+fn nearest_panel_sequence() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">q1\nACGT\n>q2\nTCGA\n")?;
+    let mut panel = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(panel, ">p1\nACGA\n>p2\nACGT\n>p3\nTCGT\n")?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .arg("--panel")
+        .arg(panel.path())
+        .arg(aln.path())
+        .unwrap()
+        .stdout;
+
+    let expected = b"q1,p2,0\nq2,p1,1\n";
+    assert_eq!(output, expected);
+
+    Ok(())
+}
+
+#[test]
+fn explain_lists_differing_positions() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">a\nACGTNA\n>b\nTCGAAA\n")?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["--explain", "a,b"])
+        .arg(aln.path())
+        .unwrap()
+        .stdout;
+
+    let expected = b"position\ta\tb\n1\tA\tT\n4\tT\tA\n";
+    assert_eq!(output, expected);
+
+    Ok(())
+}
+
+#[test]
+fn positions_are_in_input_coordinates_with_regions() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">a\nAAGTAAAAAA\n>b\nAACTAAAATA\n")?;
+    let mut bed = tempfile::NamedTempFile::new()?;
+    writeln!(bed, "chr\t3\t10")?;
+    let report = tempfile::NamedTempFile::new()?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .arg("--regions")
+        .arg(bed.path())
+        .arg("--variant-report")
+        .arg(report.path())
+        .args(["--explain", "a,b"])
+        .arg(aln.path())
+        .unwrap()
+        .stdout;
+
+    assert_eq!(output, b"position\ta\tb\n3\tG\tC\n9\tA\tT\n");
+    let actual = std::fs::read_to_string(report.path())?;
+    assert_eq!(actual, "position,alleles,counts\n3,C/G,1/1\n9,A/T,1/1\n");
+
+    Ok(())
+}
+
+#[test]
+fn names_with_delimiter_are_quoted() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">a,1\nACGT\n>b\nACGA\n")?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd.arg(aln.path()).unwrap().stdout;
+
+    let expected = b",\"a,1\",b\n\"a,1\",0,1\nb,1,0\n";
+    assert_eq!(output, expected);
+
+    Ok(())
+}
+
+#[test]
+fn sparse_output_with_names_sidecar() -> Result<(), Box<dyn std::error::Error>> {
+    let aln = "tests/cases/aln1.fa";
+    let dir = tempfile::tempdir()?;
+    let out = dir.path().join("dists.txt");
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    cmd.args(["--format", "sparse", "--max-dist", "0", "-o"])
+        .arg(&out)
+        .arg(aln)
+        .assert()
+        .success();
+
+    assert_eq!(std::fs::read_to_string(&out)?, "2,0,0\n");
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("dists.txt.names.txt"))?,
+        "s1\ns2\ns0\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn quantiles_written_to_stderr() -> Result<(), Box<dyn std::error::Error>> {
+    let aln = "tests/cases/aln1.fa";
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd.args(["-q", "--quantiles", aln]).unwrap();
+    let stderr = String::from_utf8(output.stderr)?;
+
+    let expected = "quantile\tdistance\nmin\t0\n25%\t0\n50%\t1\n75%\t1\n95%\t1\nmax\t1\n";
+    assert_eq!(stderr, expected);
+
+    Ok(())
+}
+
+#[test]
+fn all_pairs_of_alignments() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    for (name, seq) in [("a", "ACGT"), ("b", "ACGA"), ("c", "TCGA")] {
+        std::fs::write(
+            dir.path().join(format!("{}.fa", name)),
+            format!(">{}\n{}\n", name, seq),
+        )?;
+    }
+    let prefix = dir.path().join("out");
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    cmd.arg("--all-pairs")
+        .arg("-o")
+        .arg(&prefix)
+        .args(["a.fa", "b.fa", "c.fa"].map(|f| dir.path().join(f)))
+        .assert()
+        .success();
+
+    let read = |kind: &str| std::fs::read_to_string(dir.path().join(format!("out.{}.csv", kind)));
+    assert_eq!(read("a_vs_b")?, ",a\nb,1\n");
+    assert_eq!(read("a_vs_c")?, ",a\nc,2\n");
+    assert_eq!(read("b_vs_c")?, ",b\nc,1\n");
+
+    Ok(())
+}
+
+#[test]
+fn within_groups_long_form() -> Result<(), Box<dyn std::error::Error>> {
+    let aln = "tests/cases/aln1.fa";
+    let mut groups = tempfile::NamedTempFile::new()?;
+    writeln!(groups, "s0 A\ns1 A\ns2 B")?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .arg("--within-groups")
+        .arg(groups.path())
+        .arg(aln)
+        .unwrap()
+        .stdout;
+
+    let expected = b"A,s1,s0,0\n";
+    assert_eq!(output, expected);
+
+    Ok(())
+}
+
+#[test]
+fn count_sites_only() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">a\nACGT\n>b\nAN-T\n>c\nACNT\n")?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .arg("--count-sites-only")
+        .arg(aln.path())
+        .unwrap()
+        .stdout;
+
+    let expected = "statistic,value\ncolumns,4\ncomplete_columns,2\npairs,3\nmin_sites,2\n25%_sites,2\n50%_sites,2\n75%_sites,3\n95%_sites,3\nmax_sites,3\n";
+    assert_eq!(String::from_utf8(output)?, expected);
+
+    Ok(())
+}
+
+#[test]
+fn full_report_identity_precision() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">a\nACG\n>b\nACT\n")?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["--report", "full", "--identity-precision", "1"])
+        .arg(aln.path())
+        .unwrap()
+        .stdout;
+    let output = String::from_utf8(output)?;
+
+    assert!(output.contains("a,b,1,3,66.7\n"));
+
+    Ok(())
+}
+
+#[test]
+fn normalize_by_denominator() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">a\nACGTNNNN\n>b\nTCGTACNN\n")?;
+    let dir = tempfile::tempdir()?;
+    let prefix = dir.path().join("out");
+
+    for (by, expected) in [
+        ("compared", "0.250"),
+        ("total", "0.125"),
+        ("min-present", "0.250"),
+    ] {
+        let mut cmd = Command::cargo_bin("psdm").unwrap();
+        cmd.args([
+            "--also-normalized",
+            "--precision",
+            "3",
+            "-l",
+            "--normalize-by",
+            by,
+            "-o",
+        ])
+        .arg(&prefix)
+        .arg(aln.path())
+        .assert()
+        .success();
+
+        let props = std::fs::read_to_string(dir.path().join("out.prop.csv"))?;
+        assert!(props.contains(&format!("a,b,{}\n", expected)), "{}", by);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn gzipped_pairs_file() -> Result<(), Box<dyn std::error::Error>> {
+    let aln = "tests/cases/aln1.fa";
+    let pairs = "tests/cases/pairs.txt.gz";
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd.args(["--pairs-file", pairs, aln]).unwrap().stdout;
+
+    let expected = b"s1,s2,1\ns0,s2,1\n";
+    assert_eq!(output, expected);
+
+    Ok(())
+}
+
+#[test]
+fn shape_of_inter_alignment_matrix() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln2 = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln2, ">x\nACGTACGT\n>y\nACGTACGA\n")?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .arg("--shape")
+        .arg("tests/cases/aln1.fa")
+        .arg(aln2.path())
+        .unwrap()
+        .stdout;
+
+    assert_eq!(output, b"2\t3\tinter\n");
+
+    Ok(())
+}
+
+#[test]
+fn compact_gives_same_distances() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">a\nACGTNACGT\n>b\nACGANACCT\n>c\nTCGTAACGT\n")?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let expected = cmd.arg(aln.path()).unwrap().stdout;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let actual = cmd.arg("--compact").arg(aln.path()).unwrap().stdout;
+
+    assert_eq!(actual, expected);
+    assert_eq!(actual, b",a,b,c\na,0,2,1\nb,2,0,3\nc,1,3,0\n");
+
+    Ok(())
+}
+
+#[test]
+fn cluster_single_linkage() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">a\nAAAAAA\n>b\nTTTTTT\n>c\nAAAAAT\n>d\nAAAATT\n")?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd.args(["--cluster", "1"]).arg(aln.path()).unwrap().stdout;
+
+    assert_eq!(output, b"sequence,cluster\na,0\nb,1\nc,0\nd,0\n");
+
+    Ok(())
+}
+
+#[test]
+fn intra_from_pair_combines_alignments() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln1 = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln1, ">a\nACGT\n>b\nACGA\n")?;
+    let mut aln2 = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln2, ">b\nACGA\n>c\nTCGA\n")?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .arg("--intra-from-pair")
+        .arg(aln1.path())
+        .arg(aln2.path())
+        .unwrap()
+        .stdout;
+
+    assert_eq!(output, b",a,b,c\na,0,1,2\nb,1,0,1\nc,2,1,0\n");
+
+    Ok(())
+}
+
+#[test]
+fn shared_ids_in_inter_mode_warns() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln1 = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln1, ">a\nACGT\n>b\nACGA\n")?;
+    let mut aln2 = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln2, ">b\nACGA\n>c\nTCGA\n")?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd.arg(aln1.path()).arg(aln2.path()).unwrap();
+
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("1 sequence ID(s) are in both alignments")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn atomic_output_moves_file_into_place() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let out = dir.path().join("dists.csv");
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    cmd.arg("--atomic-output")
+        .arg("-o")
+        .arg(&out)
+        .arg("tests/cases/aln1.fa")
+        .assert()
+        .success();
+
+    let expected = ",s1,s2,s0\ns1,0,1,0\ns2,1,0,1\ns0,0,1,0\n";
+    assert_eq!(std::fs::read_to_string(&out)?, expected);
+    assert_eq!(std::fs::read_dir(dir.path())?.count(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn n_penalty_scores_n_against_base() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">a\nACGTN-\n>b\nATGNNA\n")?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["--n-penalty", "0.5", "--precision", "1", "-l"])
+        .arg(aln.path())
+        .unwrap()
+        .stdout;
+
+    assert_eq!(output, b"a,a,0.0\na,b,1.5\nb,a,1.5\nb,b,0.0\n");
+
+    Ok(())
+}
+
+#[test]
+fn from_matrix_rewrites_and_clusters() -> Result<(), Box<dyn std::error::Error>> {
+    let mut matrix = tempfile::Builder::new().suffix(".csv").tempfile()?;
+    write!(matrix, ",a,b,c\na,0,5,1\nb,5,0,4\nc,1,4,0\n")?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .arg("-l")
+        .arg("--from-matrix")
+        .arg(matrix.path())
+        .unwrap()
+        .stdout;
+    let expected = b"a,a,0\na,b,5\na,c,1\nb,a,5\nb,b,0\nb,c,4\nc,a,1\nc,b,4\nc,c,0\n";
+    assert_eq!(output, expected);
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["--cluster", "2", "--from-matrix"])
+        .arg(matrix.path())
+        .unwrap()
+        .stdout;
+    assert_eq!(output, b"sequence,cluster\na,0\nb,1\nc,0\n");
+
+    Ok(())
+}
+
+#[test]
+fn intra_long_form_order_is_row_major() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">c\nACGT\n>a\nACGA\n>b\nTCGA\n")?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd.arg("-l").arg(aln.path()).unwrap().stdout;
+
+    let expected = "c,c,0\nc,a,1\nc,b,2\na,c,1\na,a,0\na,b,1\nb,c,2\nb,a,1\nb,b,0\n";
+    assert_eq!(String::from_utf8(output)?, expected);
+
+    Ok(())
+}
+
+#[test]
+fn spectrum_counts_substitution_types() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">a\nACGTN\n>b\nGCGTA\n>c\nACTTA\n")?;
+    let spectrum = tempfile::NamedTempFile::new()?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    cmd.arg("--spectrum")
+        .arg(spectrum.path())
+        .arg(aln.path())
+        .assert()
+        .success();
+
+    // pairs are a>b, a>c, and b>c
+    let expected = ",A,G,T\nA,0,1,0\nG,1,0,2\nT,0,0,0\n";
+    assert_eq!(std::fs::read_to_string(spectrum.path())?, expected);
+
+    Ok(())
+}
+
+#[test]
+fn progress_every_n_pairs() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">a\nACGT\n>b\nACGA\n>c\nTCGA\n")?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args([
+            "-q",
+            "-P",
+            "--progress-every",
+            "4",
+            "--progress-format",
+            "[{done}]",
+        ])
+        .arg(aln.path())
+        .unwrap();
+
+    // 6 pairs, so only one update
+    assert_eq!(String::from_utf8(output.stderr)?, "\r[4]\n");
+
+    Ok(())
+}
+
+#[test]
+fn zero_based_positions_in_explain() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">a\nACGT\n>b\nACGA\n")?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["--explain", "a,b"])
+        .arg(aln.path())
+        .unwrap()
+        .stdout;
+    assert_eq!(output, b"position\ta\tb\n4\tT\tA\n");
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["--zero-based", "--explain", "a,b"])
+        .arg(aln.path())
+        .unwrap()
+        .stdout;
+    assert_eq!(output, b"position\ta\tb\n3\tT\tA\n");
+
+    Ok(())
+}
+
+#[test]
+fn trim_flanking_gaps_for_normalized_distance() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">a\n--ACGT-\n>b\n-NACGA-\n")?;
+    let dir = tempfile::tempdir()?;
+    let prefix = dir.path().join("out");
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    cmd.args([
+        "--trim",
+        "--also-normalized",
+        "--normalize-by",
+        "total",
+        "-l",
+        "-o",
+    ])
+    .arg(&prefix)
+    .arg(aln.path())
+    .assert()
+    .success();
+
+    let props = std::fs::read_to_string(dir.path().join("out.prop.csv"))?;
+    assert!(props.contains("a,b,0.250000\n"), "{}", props);
+
+    Ok(())
+}
+
+#[test]
+fn orient_takes_min_over_reverse_complement() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">a\nACGTTA\n>b\nTAACGT\n>c\nACGTTT\n")?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd.args(["-l"]).arg(aln.path()).unwrap().stdout;
+    assert!(String::from_utf8(output)?.contains("a,b,6\n"));
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd.args(["--orient", "-l"]).arg(aln.path()).unwrap().stdout;
+    let output = String::from_utf8(output)?;
+    assert!(output.contains("a,b,0\n"), "{}", output);
+    assert!(output.contains("a,c,1\n"), "{}", output);
+    assert!(output.contains("b,c,1\n"), "{}", output);
+
+    Ok(())
+}
+
+#[test]
+fn orient_conflicts_with_column_removal() -> Result<(), Box<dyn std::error::Error>> {
+    for opt in [
+        vec!["--complete-deletion"],
+        vec!["--trim"],
+        vec!["--regions", "tests/cases/aln1.fa"],
+        vec!["--stride", "3"],
+        vec!["--offset", "2"],
+    ] {
+        let mut cmd = Command::cargo_bin("psdm").unwrap();
+        let output = cmd
+            .arg("--orient")
+            .args(&opt)
+            .arg("tests/cases/aln1.fa")
+            .output()?;
+
+        assert!(!output.status.success(), "{:?}", opt);
+        assert!(
+            String::from_utf8_lossy(&output.stderr).contains("cannot be used with"),
+            "{:?}",
+            opt
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn bootstrap_mean_and_sd() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">a\nAAAA\n>b\nCCCC\n>c\nAAAA\n")?;
+    let dir = tempfile::tempdir()?;
+    let sd_path = dir.path().join("sd.csv");
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["--bootstrap", "10", "--seed", "1", "-l", "--bootstrap-sd"])
+        .arg(&sd_path)
+        .arg(aln.path())
+        .unwrap()
+        .stdout;
+    let output = String::from_utf8(output)?;
+
+    // every column differs between a and b, so every replicate gives the same distance
+    assert!(output.contains("a,b,4.000000\n"), "{}", output);
+    assert!(output.contains("a,c,0.000000\n"), "{}", output);
+    let sds = std::fs::read_to_string(sd_path)?;
+    assert!(sds.contains("a,b,0.000000\n"), "{}", sds);
+
+    Ok(())
+}
+
+#[test]
+fn dash_output_writes_main_table_to_stdout() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">a\nACGT\n>b\nACGA\n")?;
+    let dir = tempfile::tempdir()?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .current_dir(dir.path())
+        .args(["-o", "-", "--spectrum", "spectrum.csv", "-l"])
+        .arg(aln.path())
+        .unwrap()
+        .stdout;
+
+    assert!(String::from_utf8(output)?.contains("a,b,1\n"));
+    assert!(dir.path().join("spectrum.csv").exists());
+    assert!(!dir.path().join("-").exists());
+
+    Ok(())
+}
+
+#[test]
+fn compare_matrices_reports_changed_pairs() -> Result<(), Box<dyn std::error::Error>> {
+    let mut a = tempfile::Builder::new().suffix(".csv").tempfile()?;
+    write!(a, ",x,y,z\nx,0,1,5\ny,1,0,2\nz,5,2,0\n")?;
+    let mut b = tempfile::Builder::new().suffix(".csv").tempfile()?;
+    write!(b, ",z,y,x\nz,0,2,1\ny,2,0,1\nx,1,1,0\n")?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .arg("--compare-matrices")
+        .args([a.path(), b.path()])
+        .unwrap();
+
+    let expected = "sequence1,sequence2,distance_a,distance_b,difference\nx,z,5,1,-4\n";
+    assert_eq!(String::from_utf8(output.stdout)?, expected);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("changed\t1\n"), "{}", stderr);
+
+    Ok(())
+}
+
+#[test]
+fn tree_written_as_newick() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">a\nAAAAAAA\n>b\nAAAAACC\n>c\nCCAAAAA\n>d\nCCCCAAA\n")?;
+    let dir = tempfile::tempdir()?;
+    let tree_path = dir.path().join("tree.nwk");
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    cmd.arg("--tree")
+        .arg(&tree_path)
+        .arg(aln.path())
+        .assert()
+        .success();
+
+    let tree = std::fs::read_to_string(tree_path)?;
+    assert_eq!(tree, "(c:0,d:2,(a:0,b:2):2);\n");
+
+    Ok(())
+}
+
+#[test]
+fn tree_needs_every_pair() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">s1\nAAAA\n>s2\nAACC\n>s3\nACCC\n>s4\nCCCC\n")?;
+    let mut groups = tempfile::NamedTempFile::new()?;
+    write!(groups, "s1\tg1\ns2\tg2\ns3\tg1\ns4\tg2\n")?;
+    let dir = tempfile::tempdir()?;
+    let tree_path = dir.path().join("tree.nwk");
+
+    for opt in [
+        vec!["--within-groups", groups.path().to_str().unwrap()],
+        vec!["--groups", groups.path().to_str().unwrap()],
+        vec!["--drop-singletons", "1"],
+    ] {
+        let mut cmd = Command::cargo_bin("psdm").unwrap();
+        let output = cmd
+            .arg("--tree")
+            .arg(&tree_path)
+            .args(&opt)
+            .arg(aln.path())
+            .output()?;
+
+        assert!(!output.status.success(), "{:?}", opt);
+        assert!(
+            String::from_utf8_lossy(&output.stderr).contains("cannot be used with"),
+            "{:?}",
+            opt
+        );
+        assert!(!tree_path.exists());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn metadata_reports_alignment_length_and_compared_sites() -> Result<(), Box<dyn std::error::Error>>
+{
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">a\nAC-GT\n>b\nACNGA\n")?;
+    let dir = tempfile::tempdir()?;
+    let metadata_path = dir.path().join("metadata.tsv");
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    cmd.args(["--complete-deletion", "-d", "\t", "--metadata"])
+        .arg(&metadata_path)
+        .arg(aln.path())
+        .assert()
+        .success();
+
+    let metadata = std::fs::read_to_string(metadata_path)?;
+    assert_eq!(metadata, "alignment_length\t5\ncompared_sites\t4\n");
+
+    Ok(())
+}
+
+#[test]
+fn max_pairs_exceeded_fails() -> Result<(), Box<dyn std::error::Error>> {
+    let aln = "tests/cases/aln1.fa";
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd.args(["--max-pairs", "5", aln]).output()?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("more than --max-pairs 5"));
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    cmd.args(["--max-pairs", "6", aln]).assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn max_pairs_applies_to_cluster_and_count_sites_only() -> Result<(), Box<dyn std::error::Error>> {
+    for opt in [vec!["--cluster", "3"], vec!["--count-sites-only"]] {
+        let mut cmd = Command::cargo_bin("psdm").unwrap();
+        let output = cmd
+            .args(["--max-pairs", "1"])
+            .args(&opt)
+            .arg("tests/cases/aln1.fa")
+            .output()?;
+
+        assert!(!output.status.success(), "{:?}", opt);
+        assert!(
+            String::from_utf8_lossy(&output.stderr).contains("3 pairwise distances"),
+            "{:?}",
+            opt
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn static_schedule_gives_same_distances() -> Result<(), Box<dyn std::error::Error>> {
+    let aln = "tests/cases/aln1.fa";
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let dynamic = cmd.args(["-t", "2", aln]).unwrap().stdout;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let fixed = cmd
+        .args(["--static-schedule", "-t", "2", aln])
+        .unwrap()
+        .stdout;
+
+    assert_eq!(dynamic, fixed);
+
+    Ok(())
+}
+
+#[test]
+fn group_digits_avoids_delimiter() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    let a = "A".repeat(1200);
+    let b = "C".repeat(1200);
+    write!(aln, ">a\n{}\n>b\n{}\n", a, b)?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["--group-digits", "-l"])
+        .arg(aln.path())
+        .unwrap()
+        .stdout;
+    assert!(String::from_utf8(output)?.contains("a,b,1_200\n"));
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["--group-digits", "-l", "-d", "\t"])
+        .arg(aln.path())
+        .unwrap()
+        .stdout;
+    assert!(String::from_utf8(output)?.contains("a\tb\t1,200\n"));
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["--group-digits", "--group-separator", ","])
+        .arg(aln.path())
+        .output()?;
+    assert!(!output.status.success());
+
+    Ok(())
+}
+
+#[test]
+fn sites_matrix_counts_compared_sites() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">a\nACGT\n>b\nAN-T\n>c\nACGA\n")?;
+    let dir = tempfile::tempdir()?;
+    let sites_path = dir.path().join("sites.csv");
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    cmd.arg("--sites-matrix")
+        .arg(&sites_path)
+        .arg(aln.path())
+        .assert()
+        .success();
+
+    let sites = std::fs::read_to_string(sites_path)?;
+    assert_eq!(sites, ",a,b,c\na,4,2,4\nb,2,2,2\nc,4,2,4\n");
+
+    Ok(())
+}
+
+#[test]
+fn sites_matrix_conflicts_with_other_shapes() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let sites_path = dir.path().join("sites.csv");
+    let mut groups = tempfile::NamedTempFile::new()?;
+    write!(groups, "s0\tg1\ns1\tg1\ns2\tg2\n")?;
+
+    for opt in [
+        vec!["--compact"],
+        vec!["--cluster", "3"],
+        vec!["--shape"],
+        vec!["--explain", "s0,s1"],
+        vec!["--drop-singletons", "1"],
+        vec!["--groups", groups.path().to_str().unwrap()],
+    ] {
+        let mut cmd = Command::cargo_bin("psdm").unwrap();
+        let output = cmd
+            .arg("--sites-matrix")
+            .arg(&sites_path)
+            .args(&opt)
+            .arg("tests/cases/aln1.fa")
+            .output()?;
+
+        assert!(!output.status.success(), "{:?}", opt);
+        assert!(
+            String::from_utf8_lossy(&output.stderr).contains("cannot be used with"),
+            "{:?}",
+            opt
+        );
+        assert!(!sites_path.exists());
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn alignment_from_fifo() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let fifo = dir.path().join("aln");
+    assert!(std::process::Command::new("mkfifo")
+        .arg(&fifo)
+        .status()?
+        .success());
+    // like process substitution, the alignment can only be read once and not seeked
+    let writer = {
+        let fifo = fifo.clone();
+        std::thread::spawn(move || -> std::io::Result<()> {
+            let mut f = std::fs::OpenOptions::new().write(true).open(fifo)?;
+            f.write_all(b">a\nACGT\n>b\nACGA\n")
+        })
+    };
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd.arg("-l").arg(&fifo).unwrap().stdout;
+    writer.join().unwrap()?;
+
+    assert!(String::from_utf8(output)?.contains("a,b,1\n"));
+
+    Ok(())
+}
+
+#[test]
+fn base_freqs_counts_characters() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">a\nAACG\n>b\nTTN-\n")?;
+    let dir = tempfile::tempdir()?;
+    let freqs_path = dir.path().join("freqs.csv");
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    cmd.args(["--ignored-chars", "", "--precision", "3", "--base-freqs"])
+        .arg(&freqs_path)
+        .arg(aln.path())
+        .assert()
+        .success();
+
+    let freqs = std::fs::read_to_string(freqs_path)?;
+    let expected = "character,count,fraction\nA,2,0.250\nC,1,0.125\nG,1,0.125\nT,2,0.250\n\
+                    N,1,0.125\ngap,1,0.125\nignored,0,0.000\nother,0,0.000\n";
+    assert_eq!(freqs, expected);
+
+    Ok(())
+}
+
+#[test]
+fn no_corner_omits_leading_delimiter() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["--no-corner", "tests/cases/aln1.fa"])
+        .unwrap()
+        .stdout;
+
+    let expected = "s1,s2,s0\ns1,0,1,0\ns2,1,0,1\ns0,0,1,0\n";
+    assert_eq!(String::from_utf8(output)?, expected);
+
+    Ok(())
+}
+
+#[test]
+fn subsample_with_seed_is_reproducible() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">a\nAAAA\n>b\nCAAA\n>c\nCCAA\n>d\nCCCA\n>e\nCCCC\n")?;
+
+    let run = || -> Result<String, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("psdm").unwrap();
+        let output = cmd
+            .args(["--subsample", "2", "--seed", "42", "-l"])
+            .arg(aln.path())
+            .unwrap()
+            .stdout;
+        Ok(String::from_utf8(output)?)
+    };
+    let first = run()?;
+
+    assert_eq!(first.lines().count(), 4, "{}", first);
+    assert_eq!(first, run()?);
+
+    Ok(())
+}
+
+#[test]
+fn dot_in_alignment_warns() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">a\nAC.T\n>b\nACGT\n")?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd.arg(aln.path()).unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("1 sequence(s) contain '.'"), "{}", stderr);
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd.args(["-e", "N-."]).arg(aln.path()).unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("contain '.'"), "{}", stderr);
+
+    Ok(())
+}
+
+#[test]
+fn dot_left_by_dot_is_ref_warns_without_hint() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">a\nAC.T\n>b\nA.GT\n")?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd.args(["--dot-is-ref", "-l"]).arg(aln.path()).unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("1 sequence(s) contain '.'"), "{}", stderr);
+    assert!(!stderr.contains("--dot-is-ref"), "{}", stderr);
+    assert!(String::from_utf8(output.stdout)?.contains("a,b,0\n"));
+
+    Ok(())
+}
+
+#[test]
+fn round_mode_floor_for_normalized_distance() -> Result<(), Box<dyn std::error::Error>> {
+    let mut aln = tempfile::Builder::new().suffix(".fa").tempfile()?;
+    write!(aln, ">a\nACGTAC\n>b\nTTTTTC\n")?;
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["-m", "p", "-l", "--precision", "2"])
+        .arg(aln.path())
+        .unwrap()
+        .stdout;
+    assert!(String::from_utf8(output)?.contains("a,b,0.67\n"));
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["-m", "p", "-l", "--precision", "2", "--round-mode", "floor"])
+        .arg(aln.path())
+        .unwrap()
+        .stdout;
+    assert!(String::from_utf8(output)?.contains("a,b,0.66\n"));
+
+    Ok(())
+}
+
+#[test]
+fn output_directory_names_files_by_format() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let out_dir = format!("{}/out/", dir.path().display());
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    cmd.args(["-o", &out_dir, "tests/cases/aln1.fa"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    cmd.args(["-o", &out_dir, "-l", "-d", "\t", "tests/cases/aln1.fa"])
+        .assert()
+        .success();
+
+    let matrix = std::fs::read_to_string(dir.path().join("out/distances.csv"))?;
+    assert!(matrix.starts_with(",s1,s2,s0\n"));
+    assert!(dir.path().join("out/distances.long.tsv").exists());
+
+    Ok(())
+}
+
+#[test]
+fn self_test_passes() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd.arg("--self-test").unwrap();
+
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Self-test passed"));
+
+    Ok(())
+}
+
+#[test]
+fn hdf5_output_needs_output_path() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["--format", "hdf5", "tests/cases/aln1.fa"])
+        .output()?;
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("HDF5 output requires"));
+
+    Ok(())
+}
+
+#[test]
+fn model_is_rejected_where_it_would_be_ignored() -> Result<(), Box<dyn std::error::Error>> {
+    for opt in [
+        vec!["--cluster", "3"],
+        vec!["--vs-consensus"],
+        vec!["--panel", "tests/cases/aln1.fa"],
+        vec!["--iupac"],
+    ] {
+        let mut cmd = Command::cargo_bin("psdm").unwrap();
+        let output = cmd
+            .args(["--model", "jc69"])
+            .args(&opt)
+            .arg("tests/cases/aln1.fa")
+            .output()?;
+
+        assert!(!output.status.success(), "{:?}", opt);
+        assert!(
+            String::from_utf8_lossy(&output.stderr)
+                .contains("can only be used with the raw SNP distance, not with --model"),
+            "{:?}",
+            opt
+        );
+    }
+
+    let mut cmd = Command::cargo_bin("psdm").unwrap();
+    let output = cmd
+        .args(["--cluster", "3", "--report", "full"])
+        .arg("tests/cases/aln1.fa")
+        .output()?;
+    assert!(!output.status.success());
+
+    Ok(())
+}